@@ -0,0 +1,319 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::*;
+use std::io;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use flate2::read::GzDecoder;
+
+use crate::error::SeineError;
+use crate::salmon::CsrMatrix;
+
+/*******************************************************************************/
+/*                         Per-cell Features                                   */
+/*******************************************************************************/
+
+/// One row of alevin's `featureDump.txt`, the per-barcode QC summary.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct CellFeatures {
+    #[serde(rename = "CorrectedReads")]
+    pub corrected_reads: u64,
+
+    #[serde(rename = "MappedReads")]
+    pub mapped_reads: u64,
+
+    #[serde(rename = "DeduplicatedReads")]
+    pub deduplicated_reads: u64,
+
+    #[serde(rename = "MappingRate")]
+    pub mapping_rate: f64,
+
+    #[serde(rename = "DedupRate")]
+    pub dedup_rate: f64,
+
+    #[serde(rename = "MeanByMax")]
+    pub mean_by_max: f64,
+
+    #[serde(rename = "NumGenesExpressed")]
+    pub num_genes_expressed: u64,
+
+    #[serde(rename = "NumGenesOverMean")]
+    pub num_genes_over_mean: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct CellFeatureRecord {
+    #[serde(rename = "CB")]
+    barcode: String,
+
+    #[serde(flatten)]
+    features: CellFeatures,
+}
+
+/// Per-cell features of an alevin run, keyed by cellular barcode.
+#[derive(Debug, Default)]
+pub struct AlevinCellFeatures {
+    pub cells: HashMap<String, CellFeatures>,
+}
+
+impl AlevinCellFeatures {
+    /// Read `alevin/featureDump.txt` from an alevin output directory.
+    pub fn from_dir<P: AsRef<Path>>(dir: P) -> Result<AlevinCellFeatures, csv::Error> {
+        Self::from_path(dir.as_ref().join("alevin").join("featureDump.txt"))
+    }
+
+    pub fn from_path<P: AsRef<Path>>(p: P) -> Result<AlevinCellFeatures, csv::Error> {
+        let file = File::open(p)?;
+        let mut rdr = csv::ReaderBuilder::new().delimiter(b'\t').from_reader(file);
+
+        let mut cells = HashMap::new();
+        for record in rdr.deserialize() {
+            let record: CellFeatureRecord = record?;
+            cells.insert(record.barcode, record.features);
+        }
+        Ok(AlevinCellFeatures { cells })
+    }
+
+    pub fn get(&self, barcode: &str) -> Option<&CellFeatures> {
+        self.cells.get(barcode)
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+}
+
+/*******************************************************************************/
+/*                         Cell x Gene Matrix                                  */
+/*******************************************************************************/
+
+/// An alevin cell-by-feature count matrix: row `i` holds the counts of
+/// `barcodes[i]`, column `j` those of `features[j]`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct AlevinMatrix {
+    pub barcodes: Vec<String>,
+    pub features: Vec<String>,
+    pub counts: CsrMatrix,
+}
+
+impl AlevinMatrix {
+    /// Keep only the cells whose barcode is in `keep`, preserving row order.
+    pub fn filter_cells(&self, keep: &HashSet<&str>) -> AlevinMatrix {
+        let mut out = AlevinMatrix {
+            barcodes: Vec::new(),
+            features: self.features.clone(),
+            counts: CsrMatrix {
+                nrows: 0,
+                ncols: self.counts.ncols,
+                indptr: vec![0],
+                indices: Vec::new(),
+                data: Vec::new(),
+            },
+        };
+        for (i, bc) in self.barcodes.iter().enumerate() {
+            if !keep.contains(bc.as_str()) {
+                continue;
+            }
+            let (p, q) = (self.counts.indptr[i], self.counts.indptr[i + 1]);
+            out.barcodes.push(bc.clone());
+            out.counts
+                .indices
+                .extend_from_slice(&self.counts.indices[p..q]);
+            out.counts.data.extend_from_slice(&self.counts.data[p..q]);
+            out.counts.indptr.push(out.counts.indices.len());
+        }
+        out.counts.nrows = out.barcodes.len();
+        out
+    }
+
+    /// Keep only the cells listed in `whitelist` (see [`read_whitelist`]).
+    pub fn filter_to_whitelist(&self, whitelist: &[String]) -> AlevinMatrix {
+        let keep: HashSet<&str> = whitelist.iter().map(|s| s.as_str()).collect();
+        self.filter_cells(&keep)
+    }
+
+    /// Read alevin's `quants_mat.gz` together with the barcodes in
+    /// `quants_mat_rows.txt` and the feature names in `quants_mat_cols.txt`.
+    pub fn from_dir<P: AsRef<Path>>(dir: P) -> Result<AlevinMatrix, SeineError> {
+        let alevin = dir.as_ref().join("alevin");
+        let read_names = |name: &str| -> Result<Vec<String>, SeineError> {
+            Ok(read_to_string(alevin.join(name))?
+                .lines()
+                .filter(|l| !l.is_empty())
+                .map(|l| l.to_string())
+                .collect())
+        };
+        let barcodes = read_names("quants_mat_rows.txt")?;
+        let features = read_names("quants_mat_cols.txt")?;
+        let counts = read_eds(
+            &alevin.join("quants_mat.gz"),
+            barcodes.len(),
+            features.len(),
+        )?;
+        Ok(AlevinMatrix {
+            barcodes,
+            features,
+            counts,
+        })
+    }
+}
+
+/// Decode a gzipped matrix in alevin's EDS layout. Each of the `num_rows`
+/// rows is stored as a bit-flag block of `ceil(num_cols / 8)` bytes, where
+/// the most significant bit of byte `j / 8` marks column `j` as nonzero,
+/// followed by one little-endian `f32` per flagged column.
+pub fn read_eds(path: &Path, num_rows: usize, num_cols: usize) -> Result<CsrMatrix, SeineError> {
+    let mut reader = BufReader::new(GzDecoder::new(File::open(path)?));
+    let mut flags = vec![0_u8; num_cols.div_ceil(8)];
+    let mut value = [0_u8; 4];
+
+    let mut m = CsrMatrix {
+        nrows: num_rows,
+        ncols: num_cols,
+        indptr: Vec::with_capacity(num_rows + 1),
+        indices: Vec::new(),
+        data: Vec::new(),
+    };
+    m.indptr.push(0);
+    for _ in 0..num_rows {
+        reader.read_exact(&mut flags)?;
+        for (byte_idx, &byte) in flags.iter().enumerate() {
+            for bit in 0..8 {
+                if byte & (0x80 >> bit) == 0 {
+                    continue;
+                }
+                let col = byte_idx * 8 + bit;
+                if col >= num_cols {
+                    return Err(SeineError::Io(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("EDS flag set for column {} of {}", col, num_cols),
+                    )));
+                }
+                reader.read_exact(&mut value)?;
+                m.indices.push(col);
+                m.data.push(f32::from_le_bytes(value) as f64);
+            }
+        }
+        m.indptr.push(m.indices.len());
+    }
+    Ok(m)
+}
+
+/// Read the barcodes alevin accepted, one per line of `alevin/whitelist.txt`.
+pub fn read_whitelist(dir: &Path) -> Result<Vec<String>, SeineError> {
+    let text = read_to_string(dir.join("alevin").join("whitelist.txt"))?;
+    Ok(text
+        .lines()
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty())
+        .map(|l| l.to_string())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    fn tmp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir()
+            .join(format!("seine-alevin-test-{}", std::process::id()))
+            .join(name);
+        create_dir_all(dir.join("alevin")).unwrap();
+        dir
+    }
+
+    #[test]
+    fn read_feature_dump() {
+        let dir = tmp_dir("feature_dump");
+        write(
+            dir.join("alevin").join("featureDump.txt"),
+            "CB\tCorrectedReads\tMappedReads\tDeduplicatedReads\tMappingRate\tDedupRate\tMeanByMax\tNumGenesExpressed\tNumGenesOverMean\n\
+             AAACCTGA\t1000\t800\t600\t0.8\t0.25\t0.1\t150\t40\n\
+             TTTGGTTC\t500\t250\t200\t0.5\t0.2\t0.05\t90\t12\n",
+        )
+        .unwrap();
+
+        let feats = AlevinCellFeatures::from_dir(&dir).unwrap();
+        assert_eq!(feats.len(), 2);
+        let cell = feats.get("AAACCTGA").unwrap();
+        assert_eq!(cell.mapped_reads, 800);
+        assert_eq!(cell.mapping_rate, 0.8);
+        assert_eq!(cell.num_genes_expressed, 150);
+        assert_eq!(feats.get("TTTGGTTC").unwrap().dedup_rate, 0.2);
+        assert!(feats.get("GGGGGGGG").is_none());
+    }
+
+    #[test]
+    fn whitelist_filters_matrix() {
+        let dir = tmp_dir("whitelist");
+        write(dir.join("alevin").join("whitelist.txt"), "CCCC\nAAAA\n").unwrap();
+        let whitelist = read_whitelist(&dir).unwrap();
+        assert_eq!(whitelist, vec!["CCCC", "AAAA"]);
+
+        let m = AlevinMatrix {
+            barcodes: vec!["AAAA".to_string(), "BBBB".to_string(), "CCCC".to_string()],
+            features: vec!["g1".to_string(), "g2".to_string()],
+            counts: CsrMatrix {
+                nrows: 3,
+                ncols: 2,
+                indptr: vec![0, 1, 3, 4],
+                indices: vec![0, 0, 1, 1],
+                data: vec![5.0, 1.0, 2.0, 7.0],
+            },
+        };
+        let filtered = m.filter_to_whitelist(&whitelist);
+        assert_eq!(filtered.barcodes, vec!["AAAA", "CCCC"]);
+        assert_eq!(filtered.counts.nrows, 2);
+        assert_eq!(filtered.counts.indptr, vec![0, 1, 2]);
+        assert_eq!(filtered.counts.indices, vec![0, 1]);
+        assert_eq!(filtered.counts.data, vec![5.0, 7.0]);
+    }
+
+    #[test]
+    fn decode_eds() {
+        // 2 cells x 10 genes: cell 0 has genes 0 and 9, cell 1 has gene 3
+        let mut raw = Vec::new();
+        raw.extend_from_slice(&[0b1000_0000, 0b0100_0000]);
+        raw.extend_from_slice(&2.0_f32.to_le_bytes());
+        raw.extend_from_slice(&1.5_f32.to_le_bytes());
+        raw.extend_from_slice(&[0b0001_0000, 0]);
+        raw.extend_from_slice(&7.0_f32.to_le_bytes());
+
+        let dir = tmp_dir("eds");
+        let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+        gz.write_all(&raw).unwrap();
+        write(
+            dir.join("alevin").join("quants_mat.gz"),
+            gz.finish().unwrap(),
+        )
+        .unwrap();
+
+        let m = read_eds(&dir.join("alevin").join("quants_mat.gz"), 2, 10).unwrap();
+        assert_eq!(m.indptr, vec![0, 2, 3]);
+        assert_eq!(m.indices, vec![0, 9, 3]);
+        assert_eq!(m.data, vec![2.0, 1.5, 7.0]);
+
+        write(
+            dir.join("alevin").join("quants_mat_rows.txt"),
+            "AAAA\nCCCC\n",
+        )
+        .unwrap();
+        let genes: String = (0..10).map(|g| format!("g{}\n", g)).collect();
+        write(dir.join("alevin").join("quants_mat_cols.txt"), genes).unwrap();
+        let mat = AlevinMatrix::from_dir(&dir).unwrap();
+        assert_eq!(mat.barcodes, vec!["AAAA", "CCCC"]);
+        assert_eq!(mat.features.len(), 10);
+        assert_eq!(mat.counts, m);
+
+        assert!(read_eds(&dir.join("alevin").join("quants_mat.gz"), 3, 10).is_err());
+    }
+}
@@ -0,0 +1,214 @@
+//! Abundance estimation from equivalence classes by expectation
+//! maximization.
+
+use std::collections::HashMap;
+
+use crate::salmon::{
+    coverage, tpm_from_rates, EqClassCollection, FragmentLengthDist, QuantRecord, Tx2Gene,
+};
+
+/// Stopping rule for [`estimate_abundances`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EmOptions {
+    /// The most update rounds to run.
+    pub max_iters: usize,
+    /// Stop once no target's count changes by more than this in a round.
+    pub tolerance: f64,
+}
+
+impl Default for EmOptions {
+    fn default() -> Self {
+        EmOptions {
+            max_iters: 10_000,
+            tolerance: 1e-8,
+        }
+    }
+}
+
+/// Estimate reads per target with the EM update salmon and kallisto use:
+/// starting from uniform counts, each class's count is repeatedly split
+/// across its labels in proportion to `alpha[t] * weight / efflen[t]`, where
+/// `alpha` holds the previous round's counts. Stops after `max_iter` rounds
+/// or once no target's count changes by more than `tol`.
+fn run_em(ecs: &EqClassCollection, efflens: &[f64], max_iter: usize, tol: f64) -> Vec<f64> {
+    let n = ecs.ntarget;
+    let total: f64 = ecs.classes.counts.iter().map(|&c| c as f64).sum();
+    if n == 0 || total == 0.0 {
+        return vec![0.0; n];
+    }
+    let mut alpha = vec![total / n as f64; n];
+    let mut next = vec![0.0; n];
+    for _ in 0..max_iter {
+        expected_counts(ecs, &alpha, efflens, &mut next);
+        let change = alpha
+            .iter()
+            .zip(&next)
+            .map(|(a, b)| (a - b).abs())
+            .fold(0.0, f64::max);
+        std::mem::swap(&mut alpha, &mut next);
+        if change <= tol {
+            break;
+        }
+    }
+    alpha
+}
+
+/// One E-step: split each class's count across its labels by their
+/// responsibilities `alpha[t] * weight / efflen[t]` (normalized within the
+/// class) and accumulate the result per target into `out`. Classes whose
+/// labels all have zero responsibility contribute nothing.
+fn expected_counts(ecs: &EqClassCollection, alpha: &[f64], efflens: &[f64], out: &mut [f64]) {
+    out.iter_mut().for_each(|x| *x = 0.0);
+    let mut probs = Vec::new();
+    for ec in ecs.classes.iter() {
+        probs.clear();
+        probs.extend(
+            ec.labels
+                .iter()
+                .zip(ec.weights)
+                .map(|(&l, w)| alpha[l as usize] * w / efflens[l as usize]),
+        );
+        let denom: f64 = probs.iter().sum();
+        if denom > 0.0 {
+            for (&l, p) in ec.labels.iter().zip(&probs) {
+                out[l as usize] += ec.count as f64 * p / denom;
+            }
+        }
+    }
+}
+
+/// Reads per target estimated by a length-free EM over the classes alone:
+/// counts start uniform and each class's count is repeatedly split across
+/// its labels in proportion to `alpha[t] * weight`. Effective lengths are
+/// not used, and salmon's dumped weights do not include them either; see
+/// [`quantify_from_eq_classes`] for the length-aware EM, which also divides
+/// by each target's effective length.
+pub fn estimate_abundances(collection: &EqClassCollection, opts: EmOptions) -> Vec<f64> {
+    let unit = vec![1.0; collection.ntarget];
+    run_em(collection, &unit, opts.max_iters, opts.tolerance)
+}
+
+/// Gene-level read counts implied by the abundances `alphas`: one E-step
+/// splits each class's count across its labels in proportion to
+/// `alphas[t] * weight`, and the expected counts are summed per gene, with
+/// target `t` named `names[t]`. Targets missing from `t2g` are skipped.
+pub fn gene_expected_counts(
+    ecs: &EqClassCollection,
+    alphas: &[f64],
+    t2g: &Tx2Gene,
+    names: &[String],
+) -> HashMap<String, f64> {
+    let mut counts = vec![0.0; ecs.ntarget];
+    expected_counts(ecs, alphas, &vec![1.0; ecs.ntarget], &mut counts);
+    let mut genes = HashMap::<String, f64>::new();
+    for (name, c) in names.iter().zip(counts) {
+        if let Some(gene) = t2g.gene(name) {
+            *genes.entry(gene.to_string()).or_insert(0.0) += c;
+        }
+    }
+    genes
+}
+
+/// Re-quantify a run from its equivalence classes: effective lengths come
+/// from `lengths` (one per target) and `fld`, read counts from EM (stopping
+/// after `max_iter` rounds or once no count moves by more than `tol`) and
+/// TPM from the counts per effective base. Records are in target order.
+pub fn quantify_from_eq_classes(
+    ecs: &EqClassCollection,
+    lengths: &[u32],
+    fld: &FragmentLengthDist,
+    max_iter: usize,
+    tol: f64,
+) -> Vec<QuantRecord> {
+    assert_eq!(
+        lengths.len(),
+        ecs.ntarget,
+        "need one transcript length per target"
+    );
+    let efflens: Vec<f64> = lengths.iter().map(|&l| fld.effective_length(l)).collect();
+    let counts = run_em(ecs, &efflens, max_iter, tol);
+
+    let mut records: Vec<QuantRecord> = (0..ecs.ntarget)
+        .map(|t| QuantRecord {
+            name: ecs.targets[t].clone(),
+            len: lengths[t],
+            efflen: efflens[t],
+            tpm: 0.0,
+            num_reads: counts[t],
+        })
+        .collect();
+    let tpm = tpm_from_rates(&coverage(&records));
+    for (r, tpm) in records.iter_mut().zip(tpm) {
+        r.tpm = tpm;
+    }
+    records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::salmon::EqClassCollectionBuilder;
+
+    /// Targets `a` and `b` with 2 reads unique to `a`, 1 unique to `b` and
+    /// 6 shared evenly.
+    fn two_targets() -> EqClassCollection {
+        let mut b = EqClassCollectionBuilder::new();
+        b.add_target("a").unwrap();
+        b.add_target("b").unwrap();
+        b.add_class(vec![0], vec![1.0], 2).unwrap();
+        b.add_class(vec![1], vec![1.0], 1).unwrap();
+        b.add_class(vec![0, 1], vec![0.5, 0.5], 6).unwrap();
+        b.build()
+    }
+
+    #[test]
+    fn requantify_matches_hand_em() {
+        let ecs = two_targets();
+        let fld = FragmentLengthDist::new(vec![0.0, 0.0, 1.0]);
+
+        // one round from (4.5, 4.5): the shared 6 split evenly
+        let one = quantify_from_eq_classes(&ecs, &[102, 102], &fld, 1, 0.0);
+        assert_eq!(one[0].num_reads, 5.0);
+        assert_eq!(one[1].num_reads, 4.0);
+
+        // the fixed point solves a = 2 + 6a/9, b = 1 + 6b/9
+        let recs = quantify_from_eq_classes(&ecs, &[102, 102], &fld, 1000, 1e-10);
+        assert_eq!(recs[0].name, "a");
+        assert_eq!(recs[0].efflen, 100.0);
+        assert!((recs[0].num_reads - 6.0).abs() < 1e-8);
+        assert!((recs[1].num_reads - 3.0).abs() < 1e-8);
+        assert!((recs[0].tpm - 2.0 / 3.0 * 1e6).abs() < 1e-2);
+    }
+
+    #[test]
+    fn estimate_converges() {
+        let counts = estimate_abundances(&two_targets(), EmOptions::default());
+        assert!((counts[0] - 6.0).abs() < 1e-6);
+        assert!((counts[1] - 3.0).abs() < 1e-6);
+        assert!((counts.iter().sum::<f64>() - 9.0).abs() < 1e-9);
+
+        let opts = EmOptions {
+            max_iters: 0,
+            ..EmOptions::default()
+        };
+        assert_eq!(estimate_abundances(&two_targets(), opts), vec![4.5, 4.5]);
+        let empty = EqClassCollectionBuilder::new().build();
+        assert!(estimate_abundances(&empty, EmOptions::default()).is_empty());
+    }
+
+    #[test]
+    fn gene_counts_from_alphas() {
+        let ecs = two_targets();
+        let t2g: Tx2Gene = vec![("a", "g1"), ("b", "g2")].into_iter().collect();
+        let names = vec!["a".to_string(), "b".to_string()];
+        // the shared 6 reads split 1:3 by the abundances
+        let genes = gene_expected_counts(&ecs, &[1.0, 3.0], &t2g, &names);
+        assert_eq!(genes["g1"], 3.5);
+        assert_eq!(genes["g2"], 5.5);
+
+        let t2g: Tx2Gene = vec![("a", "g1"), ("b", "g1")].into_iter().collect();
+        let genes = gene_expected_counts(&ecs, &[1.0, 3.0], &t2g, &names);
+        assert_eq!(genes.len(), 1);
+        assert_eq!(genes["g1"], 9.0);
+    }
+}
@@ -0,0 +1,272 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// The error type shared by the fallible readers and checks in this crate.
+#[derive(Debug)]
+pub enum SeineError {
+    Io(io::Error),
+    Csv(csv::Error),
+    Json(serde_json::Error),
+    /// Two inputs disagree on how many targets they describe.
+    TargetCountMismatch {
+        expected: usize,
+        found: usize,
+    },
+    /// Two inputs name a different target at the same position.
+    TargetNameMismatch {
+        index: usize,
+        expected: String,
+        found: String,
+    },
+    /// A target present in the first input is missing from input `input`.
+    MissingTarget {
+        name: String,
+        input: usize,
+    },
+    /// An expected (possibly optional) output file is absent.
+    MissingFile(PathBuf),
+    /// A path expected to be a salmon output directory is not a directory.
+    NotADirectory(PathBuf),
+    /// meta_info.json exists but could not be parsed.
+    MalformedMetaInfo {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    /// An equivalence class is inconsistent with its collection.
+    InvalidEqClass {
+        class: usize,
+        reason: String,
+    },
+    /// Parallel arrays disagree on how many `what` there are.
+    LayoutMismatch {
+        what: &'static str,
+        expected: usize,
+        found: usize,
+    },
+    /// meta_info.json and the eq-class file disagree on the class count.
+    EqClassCountMismatch {
+        meta_info: usize,
+        eq_file: usize,
+    },
+    /// A file's header line is not the one its format requires.
+    HeaderMismatch {
+        path: PathBuf,
+        expected: String,
+        found: String,
+    },
+    /// The run in this directory wrote no inferential replicates
+    /// (`num_bootstraps` is 0 or the sampling type is `none`).
+    NoInferentialReplicates(PathBuf),
+    /// An optional auxiliary output (e.g. `fld.gz`, which single-end runs
+    /// may lack) was not written by this run.
+    NotAvailable(PathBuf),
+    /// The sample in `dir` does not line up with the samples before it;
+    /// `source` says how (e.g. the first differing target).
+    InSample {
+        dir: PathBuf,
+        source: Box<SeineError>,
+    },
+    /// A target name is not in the shared target dictionary.
+    UnknownTarget(String),
+    /// A target index is not below the number of targets.
+    TargetOutOfRange {
+        index: usize,
+        ntarget: usize,
+    },
+    /// A groups file identifies transcripts by index where names are
+    /// needed.
+    IndexedGroups,
+}
+
+impl fmt::Display for SeineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SeineError::Io(e) => write!(f, "I/O error: {}", e),
+            SeineError::Csv(e) => write!(f, "CSV error: {}", e),
+            SeineError::Json(e) => write!(f, "JSON error: {}", e),
+            SeineError::TargetCountMismatch { expected, found } => write!(
+                f,
+                "target count mismatch: expected {} targets, found {}",
+                expected, found
+            ),
+            SeineError::TargetNameMismatch {
+                index,
+                expected,
+                found,
+            } => write!(
+                f,
+                "target name mismatch at index {}: expected `{}`, found `{}`",
+                index, expected, found
+            ),
+            SeineError::MissingTarget { name, input } => {
+                write!(f, "target `{}` is missing from input {}", name, input)
+            }
+            SeineError::MissingFile(p) => write!(f, "file not found: {}", p.display()),
+            SeineError::NotADirectory(p) => write!(f, "not a directory: {}", p.display()),
+            SeineError::MalformedMetaInfo { path, source } => {
+                write!(f, "malformed meta_info.json {}: {}", path.display(), source)
+            }
+            SeineError::InvalidEqClass { class, reason } => {
+                write!(f, "invalid equivalence class {}: {}", class, reason)
+            }
+            SeineError::LayoutMismatch {
+                what,
+                expected,
+                found,
+            } => write!(
+                f,
+                "layout mismatch: {} {} stored but {} described",
+                expected, what, found
+            ),
+            SeineError::EqClassCountMismatch { meta_info, eq_file } => write!(
+                f,
+                "meta_info.json records {} equivalence classes but the eq-class file declares {}",
+                meta_info, eq_file
+            ),
+            SeineError::HeaderMismatch {
+                path,
+                expected,
+                found,
+            } => write!(
+                f,
+                "unexpected header in {}: expected `{}`, found `{}`",
+                path.display(),
+                expected,
+                found
+            ),
+            SeineError::NoInferentialReplicates(p) => {
+                write!(f, "no inferential replicates recorded for {}", p.display())
+            }
+            SeineError::NotAvailable(p) => {
+                write!(f, "{} was not written by this run", p.display())
+            }
+            SeineError::InSample { dir, source } => {
+                write!(f, "sample {}: {}", dir.display(), source)
+            }
+            SeineError::UnknownTarget(name) => {
+                write!(f, "target `{}` is not in the target dictionary", name)
+            }
+            SeineError::TargetOutOfRange { index, ntarget } => write!(
+                f,
+                "target index {} is out of range for {} targets",
+                index, ntarget
+            ),
+            SeineError::IndexedGroups => {
+                write!(f, "groups file lists target indices, not names")
+            }
+        }
+    }
+}
+
+impl Error for SeineError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SeineError::Io(e) => Some(e),
+            SeineError::Csv(e) => Some(e),
+            SeineError::Json(e) => Some(e),
+            SeineError::MalformedMetaInfo { source, .. } => Some(source),
+            SeineError::InSample { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SeineError {
+    fn from(e: io::Error) -> Self {
+        SeineError::Io(e)
+    }
+}
+
+impl From<csv::Error> for SeineError {
+    fn from(e: csv::Error) -> Self {
+        SeineError::Csv(e)
+    }
+}
+
+impl From<serde_json::Error> for SeineError {
+    fn from(e: serde_json::Error) -> Self {
+        SeineError::Json(e)
+    }
+}
+
+/// For the `io::Error` wrappers around `SeineError`-returning loaders: an
+/// `Io` error is passed through, a missing input becomes `NotFound` and
+/// anything else `InvalidData`, each wrapping the original error.
+impl From<SeineError> for io::Error {
+    fn from(e: SeineError) -> Self {
+        let kind = match e {
+            SeineError::Io(e) => return e,
+            SeineError::MissingFile(_)
+            | SeineError::NotAvailable(_)
+            | SeineError::NoInferentialReplicates(_) => io::ErrorKind::NotFound,
+            _ => io::ErrorKind::InvalidData,
+        };
+        io::Error::new(kind, e)
+    }
+}
+
+/// A malformed or truncated eq-class file. Line numbers are 1-based.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EqParseError {
+    /// The input ended where `expected` should have been.
+    UnexpectedEof { line: usize, expected: String },
+    /// A token could not be parsed as `expected`.
+    InvalidField {
+        line: usize,
+        expected: String,
+        found: String,
+    },
+    /// A class line's token count does not fit its label count.
+    TokenCount {
+        line: usize,
+        labels: usize,
+        found: usize,
+    },
+    /// The header declares `declared` classes but the file holds `found`.
+    ClassCount { declared: usize, found: usize },
+}
+
+impl fmt::Display for EqParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EqParseError::UnexpectedEof { line, expected } => write!(
+                f,
+                "unexpected end of input on line {}: expected {}",
+                line, expected
+            ),
+            EqParseError::InvalidField {
+                line,
+                expected,
+                found,
+            } => write!(
+                f,
+                "expected {} on line {}, found `{}`",
+                expected, line, found
+            ),
+            EqParseError::TokenCount {
+                line,
+                labels,
+                found,
+            } => write!(
+                f,
+                "equivalence class on line {} has {} labels but {} tokens",
+                line, labels, found
+            ),
+            EqParseError::ClassCount { declared, found } => write!(
+                f,
+                "header declares {} equivalence classes but the file holds {}",
+                declared, found
+            ),
+        }
+    }
+}
+
+impl Error for EqParseError {}
+
+impl From<EqParseError> for io::Error {
+    fn from(e: EqParseError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, e)
+    }
+}
@@ -1 +1,6 @@
+pub mod alevin;
+pub mod em;
+pub mod error;
+pub mod prelude;
 pub mod salmon;
+pub mod stats;
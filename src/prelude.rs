@@ -0,0 +1,13 @@
+//! The commonly used types and traits, for a single glob import:
+//!
+//! ```
+//! use seine::prelude::*;
+//! ```
+//!
+//! Everything here is also available at its full path.
+
+pub use crate::error::SeineError;
+pub use crate::salmon::{
+    Bootstraps, EqClass, EqClassCollection, EqClassList, EqClassView, FromPathExt, MetaInfo,
+    QuantEntry, QuantRecord, QuantTable, SalmonFiles, SalmonFilesBuilder, Tx2Gene,
+};
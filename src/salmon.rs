@@ -1,12 +1,19 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BinaryHeap, HashMap, HashSet};
+use std::convert::TryFrom;
 use std::fs::*;
 use std::io;
 use std::io::prelude::*;
-use std::io::{BufReader, Read};
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom};
+use std::ops::Range;
 use std::path::{Path, PathBuf};
 
 use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+use crate::error::{EqParseError, SeineError};
+use crate::stats;
 
 /*******************************************************************************/
 /*                         Salmon Output Files                                 */
@@ -26,52 +33,346 @@ pub struct SalmonFiles {
     pub delta_file: PathBuf,
     pub cluster_file: PathBuf,
     pub gene_cluster_file: PathBuf,
+    pub fld_file: PathBuf,
+    pub aux_dir: PathBuf,
 }
 
 // construct the files
 impl SalmonFiles {
+    /// Panicking wrapper around [`try_new`](Self::try_new).
     pub fn new<P: AsRef<Path>>(dname: P) -> SalmonFiles {
-        let dir = dname.as_ref();
-        if !dir.exists() {
-            panic!("The directory {} did not exist", dir.to_str().unwrap());
+        match SalmonFiles::try_new(dname) {
+            Ok(sf) => sf,
+            Err(e) => panic!("{}", e),
         }
-        if !dir.is_dir() {
-            panic!(
-                "The path {} did not point to a valid directory",
-                dir.to_str().unwrap()
-            );
+    }
+
+    /// Locate the outputs of the salmon run in `dname`, assuming salmon's
+    /// default layout. A missing meta_info.json is not an error; the
+    /// eq-class file is then assumed to be the uncompressed
+    /// `eq_classes.txt`. See [`SalmonFilesBuilder`] for other layouts.
+    pub fn try_new<P: AsRef<Path>>(dname: P) -> Result<SalmonFiles, SeineError> {
+        SalmonFilesBuilder::new(dname).build()
+    }
+}
+
+/// Locates the outputs of a salmon run laid out differently from the
+/// default, e.g. one run with `--auxDir`. Paths that are not overridden are
+/// derived as in [`SalmonFiles::try_new`]; relative overrides are taken
+/// relative to the output directory.
+#[derive(Debug, Clone)]
+pub struct SalmonFilesBuilder {
+    dir: PathBuf,
+    aux_dir: Option<PathBuf>,
+    quant_file: Option<PathBuf>,
+    eq_file: Option<PathBuf>,
+    mi_file: Option<PathBuf>,
+    ambig_file: Option<PathBuf>,
+    bootstrap_dir: Option<PathBuf>,
+    cmd_file: Option<PathBuf>,
+}
+
+impl SalmonFilesBuilder {
+    pub fn new<P: AsRef<Path>>(dname: P) -> SalmonFilesBuilder {
+        SalmonFilesBuilder {
+            dir: dname.as_ref().to_path_buf(),
+            aux_dir: None,
+            quant_file: None,
+            eq_file: None,
+            mi_file: None,
+            ambig_file: None,
+            bootstrap_dir: None,
+            cmd_file: None,
         }
-        let aux_info = dir.join("aux_info");
+    }
+
+    /// The auxiliary directory (salmon's `--auxDir`, `aux_info` by default)
+    /// holding meta_info.json, the eq classes and the bootstraps.
+    pub fn aux_dir<P: AsRef<Path>>(mut self, p: P) -> SalmonFilesBuilder {
+        self.aux_dir = Some(p.as_ref().to_path_buf());
+        self
+    }
 
-        let mut eq_name = "eq_classes.txt";
-        let mi_path = aux_info.join("meta_info.json");
-        if mi_path.exists() {
-            let file = File::open(mi_path);
-            let reader = BufReader::new(file.unwrap());
-            let jd: MetaInfo = serde_json::from_reader(reader).unwrap();
+    pub fn quant_file<P: AsRef<Path>>(mut self, p: P) -> SalmonFilesBuilder {
+        self.quant_file = Some(p.as_ref().to_path_buf());
+        self
+    }
 
-            eq_name = if jd.eq_class_properties.contains(&"gzipped".to_string()) {
-                "eq_classes.txt.gz"
-            } else {
-                "eq_classes.txt"
-            };
+    /// The eq-class file. Setting it turns off the meta_info-based choice
+    /// between `eq_classes.txt` and `eq_classes.txt.gz`; compression is
+    /// still detected from the content when reading.
+    pub fn eq_file<P: AsRef<Path>>(mut self, p: P) -> SalmonFilesBuilder {
+        self.eq_file = Some(p.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn meta_info_file<P: AsRef<Path>>(mut self, p: P) -> SalmonFilesBuilder {
+        self.mi_file = Some(p.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn ambig_file<P: AsRef<Path>>(mut self, p: P) -> SalmonFilesBuilder {
+        self.ambig_file = Some(p.as_ref().to_path_buf());
+        self
+    }
+
+    /// The directory holding `bootstraps.gz` and `names.tsv.gz`.
+    pub fn bootstrap_dir<P: AsRef<Path>>(mut self, p: P) -> SalmonFilesBuilder {
+        self.bootstrap_dir = Some(p.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn cmd_file<P: AsRef<Path>>(mut self, p: P) -> SalmonFilesBuilder {
+        self.cmd_file = Some(p.as_ref().to_path_buf());
+        self
+    }
+
+    /// Resolve every path. Fails if the output directory does not exist or
+    /// the meta_info.json used to pick the eq-class file is malformed.
+    pub fn build(self) -> Result<SalmonFiles, SeineError> {
+        let dir = self.dir;
+        if !dir.is_dir() {
+            return Err(SeineError::NotADirectory(dir));
         }
+        let aux_info = dir.join(
+            self.aux_dir
+                .as_deref()
+                .unwrap_or_else(|| Path::new("aux_info")),
+        );
+        let mi_file = match self.mi_file {
+            Some(p) => dir.join(p),
+            None => aux_info.join("meta_info.json"),
+        };
+
+        let eq_file = match self.eq_file {
+            Some(p) => dir.join(p),
+            None => {
+                let mut eq_name = "eq_classes.txt";
+                if mi_file.exists() {
+                    let jd = MetaInfo::from_path(&mi_file)?;
+
+                    eq_name = if jd.eq_class_properties.contains(&"gzipped".to_string()) {
+                        "eq_classes.txt.gz"
+                    } else {
+                        "eq_classes.txt"
+                    };
+                }
+                aux_info.join(eq_name)
+            }
+        };
 
-        SalmonFiles {
-            prefix: PathBuf::from(dir),
-            ambig_file: aux_info.join("ambig_info.tsv"),
-            mi_file: aux_info.join("meta_info.json"),
-            quant_file: dir.join("quant.sf"),
-            eq_file: aux_info.join(eq_name),
-            bootstrap_file: aux_info.join("bootstrap").join("bootstraps.gz"),
-            names_tsv_file: aux_info.join("bootstrap").join("names.tsv.gz"),
-            cmd_file: dir.join("cmd_info.json"),
+        let quant_file = match self.quant_file {
+            Some(p) => dir.join(p),
+            None => {
+                // some pipelines ship only a compressed quant.sf
+                let quant_file = dir.join("quant.sf");
+                let gz_quant = dir.join("quant.sf.gz");
+                if !quant_file.exists() && gz_quant.exists() {
+                    gz_quant
+                } else {
+                    quant_file
+                }
+            }
+        };
+
+        let bootstrap_dir = match self.bootstrap_dir {
+            Some(p) => dir.join(p),
+            None => aux_info.join("bootstrap"),
+        };
+
+        Ok(SalmonFiles {
+            ambig_file: match self.ambig_file {
+                Some(p) => dir.join(p),
+                None => aux_info.join("ambig_info.tsv"),
+            },
+            mi_file,
+            quant_file,
+            eq_file,
+            bootstrap_file: bootstrap_dir.join("bootstraps.gz"),
+            names_tsv_file: bootstrap_dir.join("names.tsv.gz"),
+            cmd_file: match self.cmd_file {
+                Some(p) => dir.join(p),
+                None => dir.join("cmd_info.json"),
+            },
             cluster_file: dir.join("clusters.txt"),
             collapsed_log_file: dir.join("collapsed.log"),
             group_file: dir.join("groups.txt"),
             delta_file: dir.join("delta.log"),
             gene_cluster_file: dir.join("gene_cluster.log"),
+            fld_file: aux_info.join("fld.gz"),
+            aux_dir: aux_info,
+            prefix: dir,
+        })
+    }
+}
+
+/// The fields that differ between two meta_info records, as
+/// `(field, a_value, b_value)` sorted by field name. Values are rendered as
+/// JSON (strings unquoted); a field only one side records is shown as an
+/// empty string on the other. Useful for spotting configuration drift
+/// between runs.
+pub fn meta_diff(a: &MetaInfo, b: &MetaInfo) -> Vec<(String, String, String)> {
+    let to_map = |mi: &MetaInfo| match serde_json::to_value(mi) {
+        Ok(serde_json::Value::Object(m)) => m,
+        _ => serde_json::Map::new(),
+    };
+    let render = |v: Option<&serde_json::Value>| match v {
+        None => String::new(),
+        Some(serde_json::Value::String(s)) => s.clone(),
+        Some(v) => v.to_string(),
+    };
+    let (ma, mb) = (to_map(a), to_map(b));
+    let mut fields: Vec<&String> = ma.keys().chain(mb.keys()).collect();
+    fields.sort();
+    fields.dedup();
+    fields
+        .into_iter()
+        .filter(|f| ma.get(*f) != mb.get(*f))
+        .map(|f| (f.clone(), render(ma.get(f)), render(mb.get(f))))
+        .collect()
+}
+
+impl SalmonFiles {
+    /// Whether the run wrote inferential replicates (`bootstraps.gz`).
+    pub fn has_bootstraps(&self) -> bool {
+        self.bootstrap_file.exists()
+    }
+
+    /// The number of inferential replicates recorded in meta_info.json.
+    pub fn num_bootstraps(&self) -> Result<u32, SeineError> {
+        Ok(self.read_meta_info()?.num_bootstraps)
+    }
+
+    pub fn read_meta_info(&self) -> Result<MetaInfo, SeineError> {
+        MetaInfo::from_path(&self.mi_file)
+    }
+
+    /// Open the equivalence-class file, decompressing it if needed.
+    pub fn open_eq_classes(&self) -> Result<Box<dyn Read>, SeineError> {
+        open_maybe_gz(&self.eq_file)
+    }
+
+    /// Open quant.sf.
+    pub fn open_quant(&self) -> Result<Box<dyn Read>, SeineError> {
+        open_maybe_gz(&self.quant_file)
+    }
+
+    /// Open the decompressed inferential replicates.
+    pub fn open_bootstraps(&self) -> Result<Box<dyn Read>, SeineError> {
+        open_maybe_gz(&self.bootstrap_file)
+    }
+
+    /// The target names labelling the bootstrap columns, in column order,
+    /// from `aux_info/bootstrap/names.tsv.gz`. Empty if the run has no
+    /// names file.
+    pub fn read_bootstrap_names(&self) -> Result<Vec<String>, io::Error> {
+        if !self.names_tsv_file.exists() {
+            return Ok(Vec::new());
+        }
+        let mut names = String::new();
+        decompress_if_gzipped(File::open(&self.names_tsv_file)?)?.read_to_string(&mut names)?;
+        Ok(names
+            .split(['\t', '\n', '\r'])
+            .filter(|n| !n.is_empty())
+            .map(String::from)
+            .collect())
+    }
+}
+
+/// Open `path`, transparently decompressing it when it starts with the gzip
+/// magic bytes. A missing file is reported as [`SeineError::MissingFile`].
+fn open_maybe_gz(path: &Path) -> Result<Box<dyn Read>, SeineError> {
+    let file = File::open(path).map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => SeineError::MissingFile(path.to_path_buf()),
+        _ => SeineError::Io(e),
+    })?;
+    Ok(decompress_if_gzipped(file)?)
+}
+
+/// Wrap `file` in a decoder if it starts with the gzip magic bytes, so
+/// compression is detected from the content rather than the file name.
+fn decompress_if_gzipped(file: File) -> Result<Box<dyn Read>, io::Error> {
+    let mut reader = BufReader::new(file);
+    let gzipped = reader.fill_buf()?.starts_with(&[0x1f, 0x8b]);
+    Ok(if gzipped {
+        Box::new(GzDecoder::new(reader))
+    } else {
+        Box::new(reader)
+    })
+}
+
+/*******************************************************************************/
+/*                         Command Info                                        */
+/*******************************************************************************/
+
+/// The command line salmon was run with, from `cmd_info.json`. Flags other
+/// than the well-known ones below are kept verbatim in `extra`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CmdInfo {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub salmon_version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub index: Option<String>,
+    #[serde(rename = "libType", default, skip_serializing_if = "Option::is_none")]
+    pub lib_type: Option<String>,
+    /// Read files; salmon writes a single file as a string and several as a
+    /// list, both of which are read as a list here.
+    #[serde(
+        default,
+        deserialize_with = "string_or_list",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub mates1: Option<Vec<String>>,
+    #[serde(
+        default,
+        deserialize_with = "string_or_list",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub mates2: Option<Vec<String>>,
+    #[serde(
+        rename = "unmatedReads",
+        default,
+        deserialize_with = "string_or_list",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub unmated_reads: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    #[serde(rename = "auxDir", default, skip_serializing_if = "Option::is_none")]
+    pub aux_dir: Option<String>,
+    #[serde(flatten)]
+    pub extra: HashMap<String, serde_json::Value>,
+}
+
+impl CmdInfo {
+    pub fn from_path<P: AsRef<Path>>(p: P) -> Result<CmdInfo, SeineError> {
+        let reader = BufReader::new(File::open(p)?);
+        Ok(serde_json::from_reader(reader)?)
+    }
+}
+
+fn string_or_list<'de, D>(d: D) -> Result<Option<Vec<String>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+    Ok(Some(match OneOrMany::deserialize(d)? {
+        OneOrMany::One(s) => vec![s],
+        OneOrMany::Many(v) => v,
+    }))
+}
+
+impl SalmonFiles {
+    pub fn read_cmd_info(&self) -> Result<CmdInfo, SeineError> {
+        if !self.cmd_file.exists() {
+            return Err(SeineError::MissingFile(self.cmd_file.clone()));
         }
+        CmdInfo::from_path(&self.cmd_file)
     }
 }
 
@@ -87,31 +388,229 @@ pub struct MetaInfo {
     pub num_eq_classes: u32,
     pub eq_class_properties: Vec<String>,
     pub samp_type: String,
+
+    // Fields below are only written by newer versions of salmon.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub salmon_version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub length_classes: Option<Vec<u64>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub num_decoy_fragments: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub num_fragments_filtered_vm: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub num_alignments_below_threshold_for_mapped_fragments_vm: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub num_processed: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub num_mapped: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub percent_mapped: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub library_types: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frag_length_mean: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frag_length_sd: Option<f64>,
 }
 
-#[derive(Debug)]
+impl MetaInfo {
+    /// Read a meta_info.json. A file that is not valid meta-info JSON is
+    /// reported as [`SeineError::MalformedMetaInfo`].
+    pub fn from_path<P: AsRef<Path>>(p: P) -> Result<MetaInfo, SeineError> {
+        let path = p.as_ref();
+        let reader = BufReader::new(File::open(path)?);
+        serde_json::from_reader(reader).map_err(|source| SeineError::MalformedMetaInfo {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+
+    /// The transcript length class boundaries, empty for older runs.
+    pub fn length_classes(&self) -> &[u64] {
+        self.length_classes.as_deref().unwrap_or(&[])
+    }
+
+    /// Whether the run records the selective-alignment filtering counts
+    /// (`num_decoy_fragments` and the two `_vm` fields).
+    pub fn has_selective_alignment_stats(&self) -> bool {
+        self.num_decoy_fragments.is_some()
+            && self.num_fragments_filtered_vm.is_some()
+            && self
+                .num_alignments_below_threshold_for_mapped_fragments_vm
+                .is_some()
+    }
+
+    /// Whether bootstraps.gz holds 32-bit integers rather than doubles,
+    /// which is the case for Gibbs samples written by salmon before 0.8.
+    pub fn writes_integer_samples(&self) -> bool {
+        let before_0_8 = self.salmon_version.as_deref().is_some_and(|v| {
+            let mut parts = v.split('.').map(|p| p.parse::<u32>().unwrap_or(0));
+            let major = parts.next().unwrap_or(0);
+            let minor = parts.next().unwrap_or(0);
+            (major, minor) < (0, 8)
+        });
+        self.samp_type == "gibbs" && before_0_8
+    }
+
+    /// The kind of inferential replicates the run drew, from `samp_type`.
+    pub fn sampling_type(&self) -> SampType {
+        SampType::from(self.samp_type.as_str())
+    }
+
+    /// Whether the run wrote any inferential replicates: a sampling type
+    /// other than `none` and a positive `num_bootstraps`.
+    pub fn has_inferential_replicates(&self) -> bool {
+        self.sampling_type() != SampType::None && self.num_bootstraps > 0
+    }
+}
+
+/// How a run's inferential replicates were drawn (`--numBootstraps` or
+/// `--numGibbsSamples`), as recorded in meta_info.json's `samp_type`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampType {
+    Bootstrap,
+    Gibbs,
+    /// No replicates; also used for values salmon does not write.
+    None,
+}
+
+impl From<&str> for SampType {
+    fn from(s: &str) -> SampType {
+        match s {
+            "bootstrap" => SampType::Bootstrap,
+            "gibbs" => SampType::Gibbs,
+            _ => SampType::None,
+        }
+    }
+}
+
+/// A target id as stored in equivalence classes. Labels are the largest
+/// array of a collection and transcriptomes have far fewer than 2^32
+/// targets, so they are kept as `u32`, half the size of the `usize` labels
+/// of earlier versions. This changes the type of `EqClass::labels`,
+/// `EqClassView::labels` and `EqClassList::labels`; use
+/// [`EqClassView::labels_usize`] (or `as usize`) to index with them.
+/// Methods taking a target index still take `usize`.
+pub type Label = u32;
+
+/// Equality on the eq-class types compares weights exactly; use
+/// [`EqClassCollection::approx_eq`] to allow for rounding.
+#[derive(Debug, PartialEq)]
 pub struct EqClass {
-    pub labels: Vec<usize>,
+    pub labels: Vec<Label>,
     pub weights: Vec<f64>,
     // Todo: probably should be usize?
     pub count: u32,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct EqClassView<'a> {
-    pub labels: &'a [usize],
+    pub labels: &'a [Label],
     pub weights: &'a [f64],
     pub count: u32,
 }
 
-#[derive(Debug, Default)]
+impl<'a> EqClassView<'a> {
+    /// Simpson dominance `sum(w_i^2)` of the class's weights after
+    /// normalizing them to sum to 1 (uniform if they sum to 0). A
+    /// single-label class has dominance 1.
+    pub fn simpson_dominance(&self) -> f64 {
+        let total: f64 = self.weights.iter().sum();
+        if total > 0.0 {
+            self.weights.iter().map(|w| (w / total).powi(2)).sum()
+        } else {
+            1.0 / self.labels.len() as f64
+        }
+    }
+
+    /// The labels as `usize`, for indexing per-target arrays.
+    pub fn labels_usize(&self) -> impl Iterator<Item = usize> + 'a {
+        self.labels.iter().map(|&l| l as usize)
+    }
+
+    /// The class's read count as a float.
+    pub fn total_mass(&self) -> f64 {
+        self.count as f64
+    }
+
+    /// The weights rescaled to sum to 1, or uniform if they sum to 0.
+    pub fn normalized_weights(&self) -> Vec<f64> {
+        let total: f64 = self.weights.iter().sum();
+        let n = self.weights.len() as f64;
+        self.weights
+            .iter()
+            .map(|w| if total > 0.0 { w / total } else { 1.0 / n })
+            .collect()
+    }
+
+    /// `count * weight` for `label`, or `None` if it is not in the class.
+    pub fn mass_for(&self, label: usize) -> Option<f64> {
+        self.labels
+            .iter()
+            .position(|&l| l as usize == label)
+            .map(|i| self.count as f64 * self.weights[i])
+    }
+}
+
+/// An equivalence class with its labels resolved to target names, from
+/// [`EqClassCollection::named_view`]. Displays as
+/// `count=15 [TX1:0.2, TX2:0.3, TX3:0.5]`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NamedEqClass<'a> {
+    /// `(target name, weight)` per label, in class order.
+    pub entries: Vec<(&'a str, f64)>,
+    pub count: u32,
+}
+
+impl std::fmt::Display for NamedEqClass<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "count={} [", self.count)?;
+        for (i, (name, w)) in self.entries.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}:{}", name, w)?;
+        }
+        write!(f, "]")
+    }
+}
+
+/// Deserializing runs [`check_layout`](EqClassList::check_layout), so a
+/// corrupt input is rejected instead of yielding a list that panics later.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "RawEqClassList")]
 pub struct EqClassList {
     pub offsets: Vec<usize>,
-    pub labels: Vec<usize>,
+    pub labels: Vec<Label>,
     pub weights: Vec<f64>,
     pub counts: Vec<u32>,
 }
 
+/// The unchecked serialized form of an [`EqClassList`].
+#[derive(Deserialize)]
+struct RawEqClassList {
+    offsets: Vec<usize>,
+    labels: Vec<Label>,
+    weights: Vec<f64>,
+    counts: Vec<u32>,
+}
+
+impl TryFrom<RawEqClassList> for EqClassList {
+    type Error = SeineError;
+
+    fn try_from(raw: RawEqClassList) -> Result<EqClassList, SeineError> {
+        let list = EqClassList {
+            offsets: raw.offsets,
+            labels: raw.labels,
+            weights: raw.weights,
+            counts: raw.counts,
+        };
+        list.check_layout()?;
+        Ok(list)
+    }
+}
+
 pub struct IterEqClassList<'a> {
     inner: &'a EqClassList,
     pos: usize,
@@ -121,14 +620,60 @@ pub struct IterEqClassList<'a> {
 impl<'a> Iterator for IterEqClassList<'a> {
     type Item = EqClassView<'a>;
     fn next(&mut self) -> Option<Self::Item> {
-        let ec = self.inner.get(self.pos);
+        let ec = self.inner.get(self.pos)?;
         self.pos += 1;
-        return ec;
+        Some(ec)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.inner.len() - self.pos;
+        (remaining, Some(remaining))
     }
 }
 
+impl ExactSizeIterator for IterEqClassList<'_> {}
+
 impl EqClassList {
-    pub fn iter(&self) -> IterEqClassList {
+    /// Check that the flattened arrays fit together: the offsets start at 0,
+    /// never decrease and end at `labels.len()`, and there is one weight
+    /// per label and one count per class. Offsets that go wrong are
+    /// reported as [`SeineError::InvalidEqClass`] naming the class, arrays
+    /// of the wrong length as [`SeineError::LayoutMismatch`].
+    pub fn check_layout(&self) -> Result<(), SeineError> {
+        if self.offsets.first() != Some(&0) {
+            return Err(SeineError::InvalidEqClass {
+                class: 0,
+                reason: "offsets must start at 0".to_string(),
+            });
+        }
+        if let Some(class) = self.offsets.windows(2).position(|w| w[1] < w[0]) {
+            return Err(SeineError::InvalidEqClass {
+                class,
+                reason: format!(
+                    "offsets decrease from {} to {}",
+                    self.offsets[class],
+                    self.offsets[class + 1]
+                ),
+            });
+        }
+        let checks = [
+            ("labels", self.offsets[self.len()], self.labels.len()),
+            ("weights", self.labels.len(), self.weights.len()),
+            ("counts", self.len(), self.counts.len()),
+        ];
+        for &(what, expected, found) in checks.iter() {
+            if expected != found {
+                return Err(SeineError::LayoutMismatch {
+                    what,
+                    expected,
+                    found,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    pub fn iter(&self) -> IterEqClassList<'_> {
         IterEqClassList {
             inner: self,
             pos: 0,
@@ -143,6 +688,24 @@ impl EqClassList {
         self.offsets.len() == 1
     }
 
+    /// Release the spare capacity left in the four arrays by bulk
+    /// construction, for a list that is kept around.
+    pub fn shrink_to_fit(&mut self) {
+        self.offsets.shrink_to_fit();
+        self.labels.shrink_to_fit();
+        self.weights.shrink_to_fit();
+        self.counts.shrink_to_fit();
+    }
+
+    /// Bytes allocated beyond what the arrays hold.
+    pub fn capacity_overhead(&self) -> usize {
+        use std::mem::size_of;
+        (self.offsets.capacity() - self.offsets.len()) * size_of::<usize>()
+            + (self.labels.capacity() - self.labels.len()) * size_of::<Label>()
+            + (self.weights.capacity() - self.weights.len()) * size_of::<f64>()
+            + (self.counts.capacity() - self.counts.len()) * size_of::<u32>()
+    }
+
     pub fn push(&mut self, mut ec: EqClass) {
         let len = &ec.weights.len();
         self.offsets.push(self.offsets.last().unwrap() + len);
@@ -151,21 +714,172 @@ impl EqClassList {
         self.counts.push(ec.count);
     }
 
+    /// Append a copy of a borrowed class, so a parser can keep reusing one
+    /// scratch class instead of allocating per class.
+    pub fn push_view(&mut self, ec: EqClassView) {
+        self.offsets
+            .push(self.offsets.last().unwrap() + ec.labels.len());
+        self.labels.extend_from_slice(ec.labels);
+        self.weights.extend_from_slice(ec.weights);
+        self.counts.push(ec.count);
+    }
+
     pub fn new() -> EqClassList {
         EqClassList {
             offsets: vec![0_usize],
-            labels: Vec::<usize>::new(),
+            labels: Vec::<Label>::new(),
             weights: Vec::<f64>::new(),
             counts: Vec::<u32>::new(),
         }
     }
 
-    pub fn get(&self, i: usize) -> Option<EqClassView> {
+    /// Scale each label's weight by `1 / efflen[label]` and renormalize every
+    /// class to sum to 1. Labels with a non-positive effective length get
+    /// weight 0; a class whose rescaled weights all vanish is left as is.
+    pub fn reweight_by_length(&mut self, efflen: &[f64]) {
+        for i in 0..self.len() {
+            let (p, q) = (self.offsets[i], self.offsets[i + 1]);
+            let rescaled: Vec<f64> = (p..q)
+                .map(|j| {
+                    let len = efflen[self.labels[j] as usize];
+                    if len > 0.0 {
+                        self.weights[j] / len
+                    } else {
+                        0.0
+                    }
+                })
+                .collect();
+            let total: f64 = rescaled.iter().sum();
+            if total > 0.0 {
+                for (w, r) in self.weights[p..q].iter_mut().zip(rescaled) {
+                    *w = r / total;
+                }
+            }
+        }
+    }
+
+    /// Rebuild `offsets` from the number of labels in each class, e.g.
+    /// after editing `labels`, `weights` and `counts` directly. Fails,
+    /// leaving the list untouched, unless the lengths cover exactly the
+    /// stored labels and weights and there is one per count.
+    pub fn recompute_offsets(&mut self, per_class_len: &[usize]) -> Result<(), SeineError> {
+        let total: usize = per_class_len.iter().sum();
+        let checks = [
+            ("classes", self.counts.len(), per_class_len.len()),
+            ("labels", self.labels.len(), total),
+            ("weights", self.weights.len(), total),
+        ];
+        for &(what, expected, found) in checks.iter() {
+            if expected != found {
+                return Err(SeineError::LayoutMismatch {
+                    what,
+                    expected,
+                    found,
+                });
+            }
+        }
+        self.offsets.clear();
+        self.offsets.push(0);
+        for &n in per_class_len {
+            self.offsets.push(self.offsets.last().unwrap() + n);
+        }
+        Ok(())
+    }
+
+    /// Merge classes with the same label set, regardless of label order.
+    /// Each merged class keeps the position and label order of its first
+    /// occurrence, its count is the sum of the duplicates' counts, and each
+    /// label's weight is the count-weighted mean of its duplicate weights
+    /// (a plain mean if every duplicate has a count of 0). A list with no
+    /// duplicates comes back unchanged. Fails with
+    /// [`SeineError::InvalidEqClass`], naming the first occurrence, if a
+    /// merged count does not fit in a `u32`.
+    pub fn collapse_identical(&self) -> Result<EqClassList, SeineError> {
+        let mut group_of = HashMap::<Vec<Label>, usize>::new();
+        let mut groups = Vec::<Vec<usize>>::new();
+        for (i, ec) in self.iter().enumerate() {
+            let mut key = ec.labels.to_vec();
+            key.sort_unstable();
+            let g = *group_of.entry(key).or_insert_with(|| {
+                groups.push(Vec::new());
+                groups.len() - 1
+            });
+            groups[g].push(i);
+        }
+
+        let mut out = EqClassList::new();
+        for members in &groups {
+            let first = self.get(members[0]).unwrap();
+            let total: u64 = members.iter().map(|&i| self.counts[i] as u64).sum();
+            let total = u32::try_from(total).map_err(|_| SeineError::InvalidEqClass {
+                class: members[0],
+                reason: format!("merged count {} does not fit in a u32", total),
+            })?;
+            out.labels.extend_from_slice(first.labels);
+            for &l in first.labels {
+                let (mut num, mut den) = (0.0, 0.0);
+                for &i in members {
+                    let ec = self.get(i).unwrap();
+                    let pos = ec.labels.iter().position(|&x| x == l).unwrap();
+                    let c = if total == 0 { 1.0 } else { ec.count as f64 };
+                    num += c * ec.weights[pos];
+                    den += c;
+                }
+                out.weights.push(num / den);
+            }
+            out.offsets.push(out.labels.len());
+            out.counts.push(total);
+        }
+        Ok(out)
+    }
+
+    /// A new list holding only the classes with `count >= min_count`, in
+    /// their original order.
+    pub fn filter_by_count(&self, min_count: u32) -> EqClassList {
+        let keep: Vec<usize> = (0..self.len())
+            .filter(|&i| self.counts[i] >= min_count)
+            .collect();
+        self.select(&keep)
+    }
+
+    /// Build a new list holding the classes at `order`, in that order.
+    fn select(&self, order: &[usize]) -> EqClassList {
+        let mut out = EqClassList::new();
+        for &i in order {
+            let ec = self.get(i).unwrap();
+            out.labels.extend_from_slice(ec.labels);
+            out.weights.extend_from_slice(ec.weights);
+            out.offsets.push(out.labels.len());
+            out.counts.push(ec.count);
+        }
+        out
+    }
+
+    /// The number of labels in class `i`, read from the offsets without
+    /// building a view.
+    pub fn class_size(&self, i: usize) -> Option<usize> {
+        if i + 1 >= self.offsets.len() {
+            None
+        } else {
+            Some(self.offsets[i + 1] - self.offsets[i])
+        }
+    }
+
+    /// The read count of class `i`.
+    pub fn class_count(&self, i: usize) -> Option<u32> {
+        if i + 1 >= self.offsets.len() {
+            None
+        } else {
+            Some(self.counts[i])
+        }
+    }
+
+    pub fn get(&self, i: usize) -> Option<EqClassView<'_>> {
         if i + 1 >= self.offsets.len() {
             None
         } else {
             let p = self.offsets[i];
-            let l = self.offsets[(i + 1)] - p;
+            let l = self.offsets[i + 1] - p;
             Some(EqClassView {
                 labels: &self.labels[p..(p + l)],
                 weights: &self.weights[p..(p + l)],
@@ -175,12 +889,48 @@ impl EqClassList {
     }
 }
 
-#[derive(Debug)]
+/// Serializes as its targets, counts and classes; the sort order and name
+/// index are caches and are not kept. Deserializing runs
+/// [`validate`](EqClassCollection::validate).
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(try_from = "RawEqClassCollection")]
 pub struct EqClassCollection {
     pub targets: Vec<String>,
     pub ntarget: usize,
     pub neq: usize,
     pub classes: EqClassList,
+    /// Original class index of each current class, recorded by sorts.
+    #[serde(skip)]
+    order: Option<Vec<usize>>,
+    /// Target ids sorted by name, built on request for prefix queries.
+    #[serde(skip)]
+    name_index: Option<Vec<usize>>,
+}
+
+/// The unchecked serialized form of an [`EqClassCollection`].
+#[derive(Deserialize)]
+struct RawEqClassCollection {
+    targets: Vec<String>,
+    ntarget: usize,
+    neq: usize,
+    classes: EqClassList,
+}
+
+impl TryFrom<RawEqClassCollection> for EqClassCollection {
+    type Error = String;
+
+    fn try_from(raw: RawEqClassCollection) -> Result<EqClassCollection, String> {
+        let ecs = EqClassCollection {
+            targets: raw.targets,
+            ntarget: raw.ntarget,
+            neq: raw.neq,
+            classes: raw.classes,
+            order: None,
+            name_index: None,
+        };
+        ecs.validate().map_err(|e| e.to_string())?;
+        Ok(ecs)
+    }
 }
 
 impl EqClassCollection {
@@ -195,188 +945,8467 @@ impl EqClassCollection {
             ntarget: 0,
             neq: 0,
             classes: EqClassList::new(),
+            order: None,
+            name_index: None,
         }
     }
 
     pub fn from_path<P: AsRef<Path>>(filename: &P) -> Result<EqClassCollection, io::Error> {
         let filename = filename.as_ref();
-        let file = File::open(filename).expect("equivalence class file does not exist");
-        let reader: Box<dyn Read> = if filename.ends_with("eq_classes.txt.gz") {
-            Box::new(GzDecoder::new(file))
+        let mut buf_reader = BufReader::new(File::open(filename)?);
+        let gzipped = buf_reader.fill_buf()?.starts_with(&[0x1f, 0x8b]);
+        let mut ecs = EqClassCollection::from_reader(buf_reader, gzipped)?;
+        ecs.classes.shrink_to_fit();
+        Ok(ecs)
+    }
+
+    /// Load an eq-class file with its labels renumbered to ids in `dict`,
+    /// so that collections loaded against the same dictionary share target
+    /// ids. The collection's targets are the dictionary's names at the time
+    /// of loading; targets of `dict` missing from the file simply appear in
+    /// no class. Names not in `dict` are added when `extend` is set and
+    /// otherwise fail with [`SeineError::UnknownTarget`]. An id that does
+    /// not fit in a [`Label`] fails with [`SeineError::TargetOutOfRange`].
+    pub fn from_path_with_dict<P: AsRef<Path>>(
+        filename: &P,
+        dict: &mut TargetDict,
+        extend: bool,
+    ) -> Result<EqClassCollection, SeineError> {
+        let local = EqClassCollection::from_path(filename)?;
+        let mut remap = Vec::with_capacity(local.ntarget);
+        for name in &local.targets {
+            let id = match dict.id(name) {
+                Some(id) => id,
+                None if extend => dict.intern(name),
+                None => return Err(SeineError::UnknownTarget(name.clone())),
+            };
+            let label = Label::try_from(id).map_err(|_| SeineError::TargetOutOfRange {
+                index: id,
+                ntarget: (Label::MAX as usize).saturating_add(1),
+            })?;
+            remap.push(label);
+        }
+        let mut ecs = local;
+        for l in ecs.classes.labels.iter_mut() {
+            *l = remap[*l as usize];
+        }
+        ecs.targets = dict.names().to_vec();
+        ecs.ntarget = dict.len();
+        Ok(ecs)
+    }
+
+    /// Parse an eq-class file from any source, e.g. a socket, an archive
+    /// entry or an in-memory buffer; `gzipped` says whether to decompress
+    /// it first. Malformed or truncated input yields an `InvalidData` error
+    /// wrapping an [`EqParseError`].
+    pub fn from_reader<R: BufRead>(
+        reader: R,
+        gzipped: bool,
+    ) -> Result<EqClassCollection, io::Error> {
+        if gzipped {
+            parse_eq_classes(BufReader::new(GzDecoder::new(reader)))
         } else {
-            Box::new(file)
-        };
-        let mut buf_reader = BufReader::new(reader);
-        let mut buf = String::new();
+            parse_eq_classes(reader)
+        }
+    }
 
-        let mut exp = EqClassCollection::new();
+    pub fn get(&self, i: usize) -> Option<EqClassView<'_>> {
+        self.classes.get(i)
+    }
 
-        buf_reader
-            .read_line(&mut buf)
-            .expect("Cannot read first line");
-        buf.pop();
-        let num_target: usize = buf.parse().unwrap();
-        exp.ntarget = num_target;
-        buf.clear();
+    /// Class `i` with its labels replaced by target names, for printing.
+    pub fn named_view(&self, i: usize) -> Option<NamedEqClass<'_>> {
+        let ec = self.classes.get(i)?;
+        Some(NamedEqClass {
+            entries: ec
+                .labels_usize()
+                .map(|l| self.targets[l].as_str())
+                .zip(ec.weights.iter().copied())
+                .collect(),
+            count: ec.count,
+        })
+    }
 
-        buf_reader
-            .read_line(&mut buf)
-            .expect("Cannot read second line");
-        buf.pop();
-        let num_eq: usize = buf.parse().unwrap();
+    /// Group targets into connected components, where two targets are joined
+    /// when they co-occur in at least `min_shared` equivalence classes. This
+    /// approximates salmon's collapse grouping from the eq classes alone.
+    ///
+    /// Every target belongs to exactly one cluster (targets that share no
+    /// class are singletons). Members are sorted and clusters are ordered by
+    /// their smallest member. A `min_shared` of 0 behaves like 1.
+    pub fn cluster_targets(&self, min_shared: usize) -> Vec<Vec<usize>> {
+        let mut shared = HashMap::<(usize, usize), usize>::new();
+        for ec in self.classes.iter() {
+            for (i, &a) in ec.labels.iter().enumerate() {
+                for &b in &ec.labels[(i + 1)..] {
+                    let (a, b) = (a as usize, b as usize);
+                    let key = if a < b { (a, b) } else { (b, a) };
+                    *shared.entry(key).or_insert(0) += 1;
+                }
+            }
+        }
 
-        exp.neq = num_eq;
+        let mut uf = UnionFind::new(self.ntarget);
+        for (&(a, b), &n) in shared.iter() {
+            if n >= min_shared {
+                uf.union(a, b);
+            }
+        }
 
-        let mut tnames = Vec::<String>::with_capacity(num_target);
+        let mut by_root = HashMap::<usize, usize>::new();
+        let mut clusters = Vec::<Vec<usize>>::new();
+        for t in 0..self.ntarget {
+            let root = uf.find(t);
+            let idx = *by_root.entry(root).or_insert_with(|| {
+                clusters.push(Vec::new());
+                clusters.len() - 1
+            });
+            clusters[idx].push(t);
+        }
+        clusters
+    }
 
-        for _ in 0..num_target {
-            buf.clear();
-            buf_reader
-                .read_line(&mut buf)
-                .expect("could read target name");
-            buf.pop();
-            tnames.push(buf.to_string());
-        }
-
-        exp.targets = tnames;
-
-        //let mut pb = pbr::ProgressBar::new(count);
-        //pb.format("╢▌▌░╟");
-
-        for _ in 0..num_eq {
-            buf.clear();
-            buf_reader
-                .read_line(&mut buf)
-                .expect("could read eq. class");
-            buf.pop();
-            let mut iter = buf.split_ascii_whitespace();
-            let nt: usize = iter.next().unwrap().parse().unwrap();
-            let mut tv = Vec::<usize>::with_capacity(nt);
-            let mut wv = Vec::<f64>::with_capacity(nt);
-            for _ in 0..nt {
-                tv.push(iter.next().unwrap().parse().unwrap());
+    /// The graph joining every pair of targets that share a class, weighted
+    /// by the summed counts of the classes they share. Each class adds to
+    /// all of its label pairs, so a class of `k` labels costs `O(k^2)`;
+    /// classes with more than `max_class_size` labels, if given, are skipped
+    /// to keep a few enormous classes from dominating the build.
+    pub fn target_graph(&self, max_class_size: Option<usize>) -> TargetGraph {
+        let mut edges = HashMap::<(usize, usize), f64>::new();
+        for ec in self.classes.iter() {
+            if max_class_size.is_some_and(|max| ec.labels.len() > max) {
+                continue;
             }
-            for _ in 0..nt {
-                wv.push(iter.next().unwrap().parse().unwrap());
+            for (i, &a) in ec.labels.iter().enumerate() {
+                for &b in &ec.labels[(i + 1)..] {
+                    let (a, b) = (a as usize, b as usize);
+                    let key = if a < b { (a, b) } else { (b, a) };
+                    *edges.entry(key).or_insert(0.0) += ec.count as f64;
+                }
             }
-            let c: u32 = iter.next().unwrap().parse().unwrap();
-
-            let ec = EqClass {
-                labels: tv,
-                weights: wv,
-                count: c,
-            };
-            exp.classes.push(ec);
-            //pb.inc();
         }
-        //pb.finish_print("done");
-        Ok(exp)
+        TargetGraph {
+            ntarget: self.ntarget,
+            edges,
+        }
     }
 
-    pub fn get(&self, i: usize) -> Option<EqClassView> {
-        self.classes.get(i)
+    /// For every class containing `target_idx`, return the class index and
+    /// the portion of that class's count attributed to the target
+    /// (`count * weight`), in class order.
+    pub fn target_class_contributions(&self, target_idx: usize) -> Vec<(usize, f64)> {
+        self.classes
+            .iter()
+            .enumerate()
+            .filter_map(|(i, ec)| {
+                ec.labels
+                    .iter()
+                    .position(|&l| l as usize == target_idx)
+                    .map(|j| (i, ec.count as f64 * ec.weights[j]))
+            })
+            .collect()
     }
-}
 
-impl Default for EqClassCollection {
-    fn default() -> Self {
-        Self::new()
+    /// Sort classes by decreasing count (ties keep their relative order) and
+    /// return, for each class in the new order, its index in the original
+    /// file order. Repeated sorts compose, so the result always refers back
+    /// to the order the classes were loaded in.
+    pub fn sort_by_count_desc(&mut self) -> Vec<usize> {
+        let counts = &self.classes.counts;
+        let mut perm: Vec<usize> = (0..counts.len()).collect();
+        perm.sort_by(|&a, &b| counts[b].cmp(&counts[a]));
+        self.apply_order(&perm)
     }
-}
 
-/*******************************************************************************/
-/*                         Quants                                              */
-/*******************************************************************************/
+    /// The original index of each class if the collection has been sorted,
+    /// or `None` if the classes are still in file order.
+    pub fn current_order(&self) -> Option<&[usize]> {
+        self.order.as_deref()
+    }
 
-#[derive(Debug, Deserialize, Serialize)]
-pub struct QuantRecord {
-    #[serde(rename = "Name")]
-    pub name: String,
+    fn apply_order(&mut self, perm: &[usize]) -> Vec<usize> {
+        self.classes = self.classes.select(perm);
+        let original: Vec<usize> = match &self.order {
+            Some(prev) => perm.iter().map(|&i| prev[i]).collect(),
+            None => perm.to_vec(),
+        };
+        self.order = Some(original.clone());
+        original
+    }
 
-    #[serde(rename = "Length")]
-    pub len: u32,
+    /// A quick, EM-free abundance baseline: the reads assigned to each
+    /// target by splitting every class's count across its labels by the
+    /// stored weights. Files without weights were given uniform `1 / nt`
+    /// weights when parsed, so those split evenly. Always has length
+    /// `ntarget`; targets in no class get 0.
+    pub fn naive_counts(&self) -> Vec<f64> {
+        let mut counts = vec![0.0_f64; self.ntarget];
+        for ec in self.classes.iter() {
+            for (&l, w) in ec.labels.iter().zip(ec.weights) {
+                counts[l as usize] += ec.count as f64 * w;
+            }
+        }
+        counts
+    }
 
-    #[serde(rename = "EffectiveLength")]
-    pub efflen: f64,
+    /// Each target's share of the total assigned count (`naive_counts`
+    /// normalized to sum to 1). If nothing is assigned, all entries are 0.
+    pub fn target_proportions(&self) -> Vec<f64> {
+        let mut counts = self.naive_counts();
+        let total: f64 = counts.iter().sum();
+        if total > 0.0 {
+            counts.iter_mut().for_each(|c| *c /= total);
+        }
+        counts
+    }
 
-    #[serde(rename = "TPM")]
-    pub tpm: f64,
+    /// The classes as a class-by-target CSR matrix whose values are the
+    /// per-label weights. Counts are not included; see `classes.counts`.
+    pub fn to_csr(&self) -> CsrMatrix {
+        CsrMatrix {
+            nrows: self.classes.len(),
+            ncols: self.ntarget,
+            indptr: self.classes.offsets.clone(),
+            indices: self.classes.labels.iter().map(|&l| l as usize).collect(),
+            data: self.classes.weights.clone(),
+        }
+    }
 
-    #[serde(rename = "NumReads")]
-    pub num_reads: f64,
-}
+    /// Collapse transcript labels to gene labels, producing a gene-level
+    /// collection whose targets are `t2g.genes`. Transcripts of the same gene
+    /// within a class merge into one label with the summed weight; labels in
+    /// each class are ordered by gene index. Transcripts absent from `t2g`
+    /// become their own gene (named after the transcript), appended after
+    /// the mapped genes, so no reads are lost. If that name is already taken
+    /// by a gene it gets a `_unmapped` suffix (plus a number if needed), so
+    /// gene names stay unique.
+    pub fn to_gene_collection(&self, t2g: &Tx2Gene) -> EqClassCollection {
+        let mut genes = t2g.genes.clone();
+        let mut taken: HashSet<String> = genes.iter().cloned().collect();
+        let gene_of: Vec<usize> = self
+            .targets
+            .iter()
+            .map(|name| {
+                t2g.gene_index(name).unwrap_or_else(|| {
+                    let mut gene = name.clone();
+                    let mut n = 1;
+                    while taken.contains(&gene) {
+                        gene = if n == 1 {
+                            format!("{}_unmapped", name)
+                        } else {
+                            format!("{}_unmapped{}", name, n)
+                        };
+                        n += 1;
+                    }
+                    taken.insert(gene.clone());
+                    genes.push(gene);
+                    genes.len() - 1
+                })
+            })
+            .collect();
 
-#[derive(Debug, Deserialize)]
-pub struct QuantEntry {
-    pub len: u32,
-    pub efflen: f64,
-    pub tpm: f64,
-    pub num_reads: f64,
-}
+        let mut out = EqClassCollection::new();
+        let mut merged = Vec::<(usize, f64)>::new();
+        for ec in self.classes.iter() {
+            merged.clear();
+            for (&l, &w) in ec.labels.iter().zip(ec.weights) {
+                let g = gene_of[l as usize];
+                match merged.iter_mut().find(|(mg, _)| *mg == g) {
+                    Some((_, mw)) => *mw += w,
+                    None => merged.push((g, w)),
+                }
+            }
+            merged.sort_by_key(|&(g, _)| g);
+            out.classes.push(EqClass {
+                labels: merged.iter().map(|&(g, _)| g as Label).collect(),
+                weights: merged.iter().map(|&(_, w)| w).collect(),
+                count: ec.count,
+            });
+        }
+        out.ntarget = genes.len();
+        out.targets = genes;
+        out.neq = out.classes.len();
+        out
+    }
+
+    /// Gini-Simpson diversity of read assignment, `1 - sum(p_i^2)` over
+    /// `target_proportions`. All reads on one target gives 0; an even split
+    /// across `n` targets gives `1 - 1/n`.
+    pub fn gini_simpson(&self) -> f64 {
+        1.0 - self.target_proportions().iter().map(|p| p * p).sum::<f64>()
+    }
+
+    /// Mean `simpson_dominance` over all classes, or 0 if there are none.
+    pub fn mean_dominance(&self) -> f64 {
+        if self.classes.is_empty() {
+            return 0.0;
+        }
+        let total: f64 = self.classes.iter().map(|ec| ec.simpson_dominance()).sum();
+        total / self.classes.len() as f64
+    }
+
+    /// Split into `(unique_classes, ambiguous_classes)`, where unique classes
+    /// have a single label. Both halves keep the full target list and the
+    /// relative order of their classes.
+    pub fn partition_by_ambiguity(self) -> (EqClassCollection, EqClassCollection) {
+        let (unique, ambiguous): (Vec<usize>, Vec<usize>) = (0..self.classes.len())
+            .partition(|&i| self.classes.offsets[i + 1] - self.classes.offsets[i] == 1);
+        let unique = self.with_classes(self.classes.select(&unique));
+        let ambiguous = self.with_classes(self.classes.select(&ambiguous));
+        (unique, ambiguous)
+    }
+
+    /// A collection over the same targets holding `classes`.
+    fn with_classes(&self, classes: EqClassList) -> EqClassCollection {
+        EqClassCollection {
+            targets: self.targets.clone(),
+            ntarget: self.ntarget,
+            neq: classes.len(),
+            classes,
+            order: None,
+            name_index: self.name_index.clone(),
+        }
+    }
+
+    /// Write the classes as a MatrixMarket coordinate matrix with one row
+    /// per class, one column per target and `weight * count` as the value.
+    /// Indices are 1-based, as the format requires.
+    pub fn write_target_mtx<W: Write>(&self, w: &mut W) -> Result<(), io::Error> {
+        writeln!(w, "%%MatrixMarket matrix coordinate real general")?;
+        writeln!(
+            w,
+            "{} {} {}",
+            self.classes.len(),
+            self.ntarget,
+            self.classes.labels.len()
+        )?;
+        for (i, ec) in self.classes.iter().enumerate() {
+            for (&l, wt) in ec.labels.iter().zip(ec.weights) {
+                writeln!(w, "{} {} {}", i + 1, l + 1, wt * ec.count as f64)?;
+            }
+        }
+        w.flush()
+    }
+
+    /// Proportions of the targets in `subset`, normalized over the subset
+    /// only and returned in `subset` order. Classes with any label outside
+    /// the subset are excluded entirely, since their reads cannot be
+    /// attributed to the subset without an abundance model. All entries are
+    /// 0 if no class lies wholly inside the subset.
+    pub fn target_proportions_within(&self, subset: &[usize]) -> Vec<f64> {
+        let mut pos = HashMap::<usize, usize>::with_capacity(subset.len());
+        for (i, &t) in subset.iter().enumerate() {
+            pos.insert(t, i);
+        }
+        let mut counts = vec![0.0_f64; subset.len()];
+        for ec in self.classes.iter() {
+            if !ec.labels.iter().all(|&l| pos.contains_key(&(l as usize))) {
+                continue;
+            }
+            for (&l, w) in ec.labels.iter().zip(ec.weights) {
+                counts[pos[&(l as usize)]] += ec.count as f64 * w;
+            }
+        }
+        let total: f64 = counts.iter().sum();
+        if total > 0.0 {
+            counts.iter_mut().for_each(|c| *c /= total);
+        }
+        counts
+    }
+
+    /// Targets that appear in at least one single-label class and never in a
+    /// multi-label one, in increasing order.
+    pub fn uniquely_mappable_targets(&self) -> Vec<usize> {
+        let mut unique = vec![false; self.ntarget];
+        let mut ambiguous = vec![false; self.ntarget];
+        for ec in self.classes.iter() {
+            let seen = if ec.labels.len() == 1 {
+                &mut unique
+            } else {
+                &mut ambiguous
+            };
+            for &l in ec.labels {
+                seen[l as usize] = true;
+            }
+        }
+        (0..self.ntarget)
+            .filter(|&t| unique[t] && !ambiguous[t])
+            .collect()
+    }
+
+    /// A deterministic representative target per class: the label with the
+    /// highest weight, ties broken by the lowest target index. Look the id up
+    /// in `targets` for its name. Entries line up with `classes`; an empty
+    /// class has no representative and yields `None`.
+    pub fn class_representatives(&self) -> Vec<Option<usize>> {
+        self.classes
+            .iter()
+            .map(|ec| {
+                let (mut best_l, mut best_w) = (*ec.labels.first()?, *ec.weights.first()?);
+                for (&l, &w) in ec.labels.iter().zip(ec.weights).skip(1) {
+                    if w > best_w || (w == best_w && l < best_l) {
+                        best_l = l;
+                        best_w = w;
+                    }
+                }
+                Some(best_l as usize)
+            })
+            .collect()
+    }
+
+    /// Split every class's count binomially into two collections, the first
+    /// receiving each read with probability `fraction`, for cross-validating
+    /// abundance estimators. Both halves keep the targets, labels and
+    /// weights; the split is reproducible for a given `seed`.
+    pub fn split_counts(&self, fraction: f64, seed: u64) -> (EqClassCollection, EqClassCollection) {
+        let mut rng = stats::SplitMix64::new(seed);
+        let (train, test): (Vec<u32>, Vec<u32>) = self
+            .classes
+            .counts
+            .iter()
+            .map(|&c| {
+                let k = rng.binomial(c, fraction);
+                (k, c - k)
+            })
+            .unzip();
+        let half = |counts: Vec<u32>| {
+            self.with_classes(EqClassList {
+                offsets: self.classes.offsets.clone(),
+                labels: self.classes.labels.clone(),
+                weights: self.classes.weights.clone(),
+                counts,
+            })
+        };
+        (half(train), half(test))
+    }
+
+    /// Like [`naive_counts`](Self::naive_counts) for a decoy-aware index,
+    /// where targets at `num_real_targets..` are decoys. Each class's count
+    /// is spread over its real labels only, by their renormalized weights
+    /// (uniformly if those are all 0); classes of decoys alone contribute
+    /// nothing. The result covers the real targets.
+    pub fn target_counts_excluding_decoys(&self, num_real_targets: usize) -> Vec<f64> {
+        let mut counts = vec![0.0_f64; num_real_targets];
+        for ec in self.classes.iter() {
+            let real = || {
+                ec.labels
+                    .iter()
+                    .zip(ec.weights)
+                    .filter(|(&l, _)| (l as usize) < num_real_targets)
+            };
+            let n = real().count();
+            let total: f64 = real().map(|(_, w)| w).sum();
+            for (&l, w) in real() {
+                let share = if total > 0.0 {
+                    w / total
+                } else {
+                    1.0 / n as f64
+                };
+                counts[l as usize] += ec.count as f64 * share;
+            }
+        }
+        counts
+    }
+
+    /// Sort the target names once so that
+    /// [`targets_with_prefix`](Self::targets_with_prefix) can binary-search
+    /// instead of scanning. Call it again after changing `targets`.
+    pub fn index_target_names(&mut self) {
+        let mut idx: Vec<usize> = (0..self.targets.len()).collect();
+        idx.sort_by(|&a, &b| self.targets[a].cmp(&self.targets[b]));
+        self.name_index = Some(idx);
+    }
+
+    /// The ids of the targets whose names start with `prefix` (e.g. all
+    /// isoforms sharing a gene-derived stem), in increasing id order.
+    pub fn targets_with_prefix(&self, prefix: &str) -> Vec<usize> {
+        let mut ids: Vec<usize> = match &self.name_index {
+            Some(idx) => {
+                let start = idx.partition_point(|&t| self.targets[t].as_str() < prefix);
+                idx[start..]
+                    .iter()
+                    .take_while(|&&t| self.targets[t].starts_with(prefix))
+                    .copied()
+                    .collect()
+            }
+            None => (0..self.targets.len())
+                .filter(|&t| self.targets[t].starts_with(prefix))
+                .collect(),
+        };
+        ids.sort_unstable();
+        ids
+    }
+
+    /// Expected number of detected targets (with at least one read) had the
+    /// library been sequenced to `target_depth` reads.
+    ///
+    /// Each read of an ambiguous class is first assigned to one of its
+    /// labels at random in proportion to the weights (reproducibly for a
+    /// given `seed`), giving integer per-target counts `c_t` summing to the
+    /// current depth `N`. Up to `N` the expected detection under binomial
+    /// thinning, `sum_t 1 - (1 - D/N)^c_t`, is used; beyond it the Chao et
+    /// al. (2014) extrapolation from the numbers of targets seen once and
+    /// twice. At `target_depth == N` this is the observed detected count.
+    pub fn complexity_extrapolation(&self, target_depth: u64, seed: u64) -> f64 {
+        let mut rng = stats::SplitMix64::new(seed);
+        let mut counts = vec![0_u64; self.ntarget];
+        for ec in self.classes.iter() {
+            let total: f64 = ec.weights.iter().sum();
+            for _ in 0..ec.count {
+                let mut u = rng.next_f64() * total;
+                let mut pick = ec.labels[ec.labels.len() - 1];
+                for (&l, &w) in ec.labels.iter().zip(ec.weights) {
+                    if u < w {
+                        pick = l;
+                        break;
+                    }
+                    u -= w;
+                }
+                counts[pick as usize] += 1;
+            }
+        }
+
+        let n: u64 = counts.iter().sum();
+        if n == 0 {
+            return 0.0;
+        }
+        if target_depth <= n {
+            let keep = target_depth as f64 / n as f64;
+            return counts
+                .iter()
+                .filter(|&&c| c > 0)
+                .map(|&c| 1.0 - (1.0 - keep).powi(c.min(i32::MAX as u64) as i32))
+                .sum();
+        }
+
+        let observed = counts.iter().filter(|&&c| c > 0).count() as f64;
+        let f1 = counts.iter().filter(|&&c| c == 1).count() as f64;
+        let f2 = counts.iter().filter(|&&c| c == 2).count() as f64;
+        if f1 == 0.0 {
+            return observed;
+        }
+        let f0 = if f2 > 0.0 {
+            f1 * f1 / (2.0 * f2)
+        } else {
+            f1 * (f1 - 1.0) / 2.0
+        };
+        if f0 == 0.0 {
+            return observed;
+        }
+        let n = n as f64;
+        let extra = (target_depth as f64) - n;
+        observed + f0 * (1.0 - (1.0 - f1 / (n * f0 + f1)).powf(extra))
+    }
+
+    /// For each target, the share of its weighted read mass (as in
+    /// [`naive_counts`](Self::naive_counts)) that comes from single-label
+    /// classes: 1 for targets only ever seen uniquely, 0 for those only ever
+    /// seen in ambiguous classes or not at all. Always has length `ntarget`.
+    pub fn unique_fraction(&self) -> Vec<f64> {
+        let mut unique = vec![0.0_f64; self.ntarget];
+        let mut total = vec![0.0_f64; self.ntarget];
+        for ec in self.classes.iter() {
+            for (&l, w) in ec.labels.iter().zip(ec.weights) {
+                let mass = ec.count as f64 * w;
+                total[l as usize] += mass;
+                if ec.labels.len() == 1 {
+                    unique[l as usize] += mass;
+                }
+            }
+        }
+        unique
+            .into_iter()
+            .zip(total)
+            .map(|(u, t)| if t > 0.0 { u / t } else { 0.0 })
+            .collect()
+    }
+
+    #[deprecated(note = "renamed to `unique_fraction`")]
+    pub fn target_unique_fraction(&self) -> Vec<f64> {
+        self.unique_fraction()
+    }
+
+    /// The classes in long format, one row per (class, label), split into
+    /// column batches of at most `batch_size` rows so a large collection
+    /// can be handed to a columnar consumer piecewise.
+    pub fn to_long_batches(&self, batch_size: usize) -> impl Iterator<Item = EqClassBatch> + '_ {
+        assert!(batch_size > 0, "batch size must be positive");
+        let nrows = self.classes.labels.len();
+        let mut class = 0;
+        (0..nrows).step_by(batch_size).map(move |start| {
+            let end = (start + batch_size).min(nrows);
+            let mut batch = EqClassBatch::default();
+            for row in start..end {
+                while self.classes.offsets[class + 1] <= row {
+                    class += 1;
+                }
+                batch.eq_id.push(class as u64);
+                batch.target_id.push(self.classes.labels[row] as u64);
+                batch.weight.push(self.classes.weights[row]);
+                batch.count.push(self.classes.counts[class]);
+            }
+            batch
+        })
+    }
+
+    /// The fraction of classes with a single label, or NaN for an empty
+    /// collection. Unlike a read-weighted multimapping rate, every class
+    /// counts once regardless of its count.
+    pub fn unique_class_fraction(&self) -> f64 {
+        let n = self.classes.len();
+        if n == 0 {
+            return f64::NAN;
+        }
+        let unique = self
+            .classes
+            .iter()
+            .filter(|ec| ec.labels.len() == 1)
+            .count();
+        unique as f64 / n as f64
+    }
+
+    /// A name to target id map, built on each call (`targets` is public
+    /// and may change, so it is not cached). Keep it around when resolving
+    /// many names.
+    pub fn target_index(&self) -> HashMap<&str, usize> {
+        self.targets
+            .iter()
+            .enumerate()
+            .map(|(i, t)| (t.as_str(), i))
+            .collect()
+    }
+
+    /// The name of target `label`, if it is in range.
+    pub fn label_name(&self, label: usize) -> Option<&str> {
+        self.targets.get(label).map(|t| t.as_str())
+    }
+
+    /// Every class whose labels include `target`, with its index. A target
+    /// in no class (or out of range) yields nothing.
+    pub fn classes_with_target(
+        &self,
+        target: usize,
+    ) -> impl Iterator<Item = (usize, EqClassView<'_>)> {
+        self.classes
+            .iter()
+            .enumerate()
+            .filter(move |(_, ec)| ec.labels.iter().any(|&l| l as usize == target))
+    }
+
+    /// For each target, the indices of the classes that reference it, for
+    /// answering many [`classes_with_target`](Self::classes_with_target)
+    /// queries without rescanning.
+    pub fn build_target_to_classes(&self) -> Vec<Vec<usize>> {
+        let mut index = vec![Vec::new(); self.ntarget];
+        for (i, ec) in self.classes.iter().enumerate() {
+            for &l in ec.labels {
+                if index[l as usize].last() != Some(&i) {
+                    index[l as usize].push(i);
+                }
+            }
+        }
+        index
+    }
+
+    /// The classes as target-by-class triplets, ordered by class (CSC
+    /// order). `values` picks whether each entry is the label's weight or
+    /// its weight times the class count.
+    pub fn to_sparse(&self, values: SparseValues) -> SparseEqMatrix {
+        let mut m = SparseEqMatrix {
+            nrows: self.ntarget,
+            ncols: self.classes.len(),
+            ..SparseEqMatrix::default()
+        };
+        for (i, ec) in self.classes.iter().enumerate() {
+            for (&l, &w) in ec.labels.iter().zip(ec.weights) {
+                m.rows.push(l as usize);
+                m.cols.push(i);
+                m.vals.push(match values {
+                    SparseValues::Weight => w,
+                    SparseValues::WeightedCount => w * ec.count as f64,
+                });
+            }
+        }
+        m
+    }
+
+    /// Write the collection to `p` in salmon's `eq_classes.txt` format,
+    /// gzip-compressed if `gzip` is set; see
+    /// [`write_text_to`](Self::write_text_to). [`from_path`](Self::from_path)
+    /// reads it back unchanged.
+    pub fn write_text<P: AsRef<Path>>(&self, p: P, gzip: bool) -> Result<(), io::Error> {
+        let file = BufWriter::new(File::create(p)?);
+        if gzip {
+            let mut gz = GzEncoder::new(file, Compression::default());
+            self.write_text_to(&mut gz)?;
+            gz.finish()?.flush()
+        } else {
+            let mut file = file;
+            self.write_text_to(&mut file)?;
+            file.flush()
+        }
+    }
+
+    /// Write the target count, the class count and one target name per
+    /// line, then a line per class: its label count, labels, weights and
+    /// read count. Weights are written with enough digits to parse back to
+    /// the same value.
+    pub fn write_text_to<W: Write>(&self, w: &mut W) -> Result<(), io::Error> {
+        writeln!(w, "{}", self.targets.len())?;
+        writeln!(w, "{}", self.classes.len())?;
+        for name in &self.targets {
+            writeln!(w, "{}", name)?;
+        }
+        for ec in self.classes.iter() {
+            write!(w, "{}", ec.labels.len())?;
+            for l in ec.labels {
+                write!(w, "\t{}", l)?;
+            }
+            for wt in ec.weights {
+                write!(w, "\t{}", wt)?;
+            }
+            writeln!(w, "\t{}", ec.count)?;
+        }
+        Ok(())
+    }
+
+    /// A collection over the same targets keeping only the classes with
+    /// `count >= min_count`; see [`EqClassList::filter_by_count`].
+    pub fn filter_by_count(&self, min_count: u32) -> EqClassCollection {
+        self.with_classes(self.classes.filter_by_count(min_count))
+    }
+
+    /// Combine samples quantified against the same reference into one
+    /// table of per-sample counts for every distinct label set. Fails with
+    /// the first disagreement in `ntarget` or in a target name, or with
+    /// [`SeineError::InvalidEqClass`] if classes of one sample that share a
+    /// label set sum past `u32::MAX`.
+    pub fn merge(samples: &[EqClassCollection]) -> Result<MergedEqClasses, SeineError> {
+        let mut merged = MergedEqClasses::default();
+        let first = match samples.first() {
+            Some(first) => first,
+            None => return Ok(merged),
+        };
+        let mut row_of = HashMap::<Vec<Label>, usize>::new();
+        for (s, exp) in samples.iter().enumerate() {
+            if exp.ntarget != first.ntarget {
+                return Err(SeineError::TargetCountMismatch {
+                    expected: first.ntarget,
+                    found: exp.ntarget,
+                });
+            }
+            check_same_targets(&first.targets, &exp.targets)?;
+            for (i, ec) in exp.classes.iter().enumerate() {
+                let mut key = ec.labels.to_vec();
+                key.sort_unstable();
+                let row = *row_of.entry(key).or_insert_with_key(|key| {
+                    merged.label_sets.push(key.clone());
+                    merged.counts.push(vec![0; samples.len()]);
+                    merged.label_sets.len() - 1
+                });
+                let cell = &mut merged.counts[row][s];
+                *cell = cell
+                    .checked_add(ec.count)
+                    .ok_or_else(|| SeineError::InvalidEqClass {
+                        class: i,
+                        reason: format!("merged count in sample {} does not fit in a u32", s),
+                    })?;
+            }
+        }
+        merged.targets = first.targets.clone();
+        Ok(merged)
+    }
+
+    /// Check that the collection is internally consistent: the offsets
+    /// start at 0 and never decrease, `neq` and `ntarget` match the stored
+    /// classes and targets, the label, weight and count arrays line up with
+    /// the offsets, and every label is `< ntarget`. The first problem found
+    /// is reported, naming the offending class.
+    pub fn validate(&self) -> Result<(), SeineError> {
+        let list = &self.classes;
+        list.check_layout()?;
+        let checks = [
+            ("classes", self.neq, list.len()),
+            ("targets", self.ntarget, self.targets.len()),
+        ];
+        for &(what, expected, found) in checks.iter() {
+            if expected != found {
+                return Err(SeineError::LayoutMismatch {
+                    what,
+                    expected,
+                    found,
+                });
+            }
+        }
+        for (class, ec) in list.iter().enumerate() {
+            if let Some(&label) = ec.labels.iter().find(|&&l| l as usize >= self.ntarget) {
+                return Err(SeineError::InvalidEqClass {
+                    class,
+                    reason: format!(
+                        "label {} is out of range for {} targets",
+                        label, self.ntarget
+                    ),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// [`from_path`](Self::from_path) followed by
+    /// [`validate`](Self::validate).
+    pub fn from_path_validated<P: AsRef<Path>>(
+        filename: &P,
+    ) -> Result<EqClassCollection, SeineError> {
+        let ecs = EqClassCollection::from_path(filename)?;
+        ecs.validate()?;
+        Ok(ecs)
+    }
+
+    #[deprecated(note = "renamed to `naive_counts`")]
+    pub fn target_counts(&self) -> Vec<f64> {
+        self.naive_counts()
+    }
+
+    /// A collection over the same targets keeping the classes that contain
+    /// any (or all, per `mode`) of `targets`. With an empty `targets`, `Any`
+    /// keeps nothing and `All` keeps everything.
+    pub fn filter_by_labels(
+        &self,
+        targets: &HashSet<usize>,
+        mode: LabelMatch,
+    ) -> EqClassCollection {
+        let keep: Vec<usize> = (0..self.classes.len())
+            .filter(|&i| {
+                let labels = self.classes.get(i).unwrap().labels;
+                match mode {
+                    LabelMatch::Any => labels.iter().any(|&l| targets.contains(&(l as usize))),
+                    LabelMatch::All => targets
+                        .iter()
+                        .all(|&t| labels.iter().any(|&l| l as usize == t)),
+                }
+            })
+            .collect();
+        self.with_classes(self.classes.select(&keep))
+    }
+
+    /// The targets that are not decoys, per `mi`'s `num_valid_targets`.
+    pub fn valid_target_range(&self, mi: &MetaInfo) -> Result<Range<usize>, SeineError> {
+        valid_prefix(self.ntarget, mi)
+    }
+
+    /// The collection restricted to the targets that are not decoys, as
+    /// with [`subset_targets`](Self::subset_targets): classes of decoys
+    /// alone are dropped and the remaining weights renormalized.
+    pub fn drop_decoys(&self, mi: &MetaInfo) -> Result<EqClassCollection, SeineError> {
+        let keep: Vec<usize> = self.valid_target_range(mi)?.collect();
+        Ok(self.subset_targets(&keep))
+    }
+
+    /// Project the collection onto the targets in `keep`, which become
+    /// targets `0..keep.len()` in that order (repeats after the first are
+    /// ignored). Each class loses its labels outside `keep` and its surviving
+    /// weights are rescaled to sum to 1 (made uniform if they sum to 0);
+    /// classes left with no labels are dropped rather than kept empty.
+    ///
+    /// # Panics
+    ///
+    /// If an index in `keep` is not a target of the collection.
+    pub fn subset_targets(&self, keep: &[usize]) -> EqClassCollection {
+        let mut remap = vec![None; self.ntarget];
+        let mut targets = Vec::with_capacity(keep.len());
+        for &t in keep {
+            assert!(t < self.ntarget, "target index {} out of range", t);
+            if remap[t].is_none() {
+                remap[t] = Some(targets.len() as Label);
+                targets.push(self.targets[t].clone());
+            }
+        }
+
+        let mut classes = EqClassList::new();
+        for ec in self.classes.iter() {
+            let (labels, mut weights): (Vec<Label>, Vec<f64>) = ec
+                .labels
+                .iter()
+                .zip(ec.weights)
+                .filter_map(|(&l, &w)| remap[l as usize].map(|l| (l, w)))
+                .unzip();
+            if labels.is_empty() {
+                continue;
+            }
+            let total: f64 = weights.iter().sum();
+            let n = weights.len() as f64;
+            for w in weights.iter_mut() {
+                *w = if total > 0.0 { *w / total } else { 1.0 / n };
+            }
+            classes.push(EqClass {
+                labels,
+                weights,
+                count: ec.count,
+            });
+        }
+
+        EqClassCollection {
+            ntarget: targets.len(),
+            targets,
+            neq: classes.len(),
+            classes,
+            order: None,
+            name_index: None,
+        }
+    }
+
+    /// Cache the collection at `p` in seine's own binary format, which
+    /// stores the flattened class arrays as-is so that
+    /// [`load_binary`](Self::load_binary) can read them back without parsing.
+    /// The file starts with [`EQ_CACHE_MAGIC`] and [`EQ_CACHE_VERSION`],
+    /// followed by little-endian `u64` lengths and values: the targets (as
+    /// length-prefixed UTF-8), `ntarget`, `neq`, then `offsets`, `labels`
+    /// (`u32`), `weights` (`f64`) and `counts` (`u32`), each
+    /// length-prefixed.
+    pub fn save_binary<P: AsRef<Path>>(&self, p: P) -> Result<(), io::Error> {
+        let mut w = io::BufWriter::new(File::create(p)?);
+        w.write_all(EQ_CACHE_MAGIC)?;
+        w.write_all(&EQ_CACHE_VERSION.to_le_bytes())?;
+        w.write_all(&(self.targets.len() as u64).to_le_bytes())?;
+        for name in &self.targets {
+            w.write_all(&(name.len() as u64).to_le_bytes())?;
+            w.write_all(name.as_bytes())?;
+        }
+        w.write_all(&(self.ntarget as u64).to_le_bytes())?;
+        w.write_all(&(self.neq as u64).to_le_bytes())?;
+        let list = &self.classes;
+        w.write_all(&(list.offsets.len() as u64).to_le_bytes())?;
+        for &o in &list.offsets {
+            w.write_all(&(o as u64).to_le_bytes())?;
+        }
+        w.write_all(&(list.labels.len() as u64).to_le_bytes())?;
+        for &l in &list.labels {
+            w.write_all(&l.to_le_bytes())?;
+        }
+        w.write_all(&(list.weights.len() as u64).to_le_bytes())?;
+        for &x in &list.weights {
+            w.write_all(&x.to_le_bytes())?;
+        }
+        w.write_all(&(list.counts.len() as u64).to_le_bytes())?;
+        for &c in &list.counts {
+            w.write_all(&c.to_le_bytes())?;
+        }
+        w.flush()
+    }
+
+    /// Load a collection cached by [`save_binary`](Self::save_binary). A
+    /// file without the magic bytes, from a different format version, or
+    /// whose arrays do not fit together is an `InvalidData` error.
+    pub fn load_binary<P: AsRef<Path>>(p: P) -> Result<EqClassCollection, io::Error> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+        let mut r = BufReader::new(File::open(p)?);
+        let mut magic = [0u8; 8];
+        r.read_exact(&mut magic)?;
+        if &magic != EQ_CACHE_MAGIC {
+            return Err(invalid("not a seine eq-class cache".to_string()));
+        }
+        let version = read_le_u32(&mut r)?;
+        if version != EQ_CACHE_VERSION {
+            return Err(invalid(format!(
+                "eq-class cache version {}, expected {}",
+                version, EQ_CACHE_VERSION
+            )));
+        }
+
+        let mut ecs = EqClassCollection::new();
+        let ntargets = read_le_u64(&mut r)? as usize;
+        for _ in 0..ntargets {
+            ecs.targets.push(read_le_string(&mut r)?);
+        }
+        ecs.ntarget = read_le_u64(&mut r)? as usize;
+        ecs.neq = read_le_u64(&mut r)? as usize;
+
+        let list = &mut ecs.classes;
+        let n = read_le_u64(&mut r)? as usize;
+        list.offsets.clear();
+        for _ in 0..n {
+            list.offsets.push(read_le_u64(&mut r)? as usize);
+        }
+        let n = read_le_u64(&mut r)? as usize;
+        for _ in 0..n {
+            list.labels.push(read_le_u32(&mut r)?);
+        }
+        let n = read_le_u64(&mut r)? as usize;
+        for _ in 0..n {
+            list.weights.push(f64::from_bits(read_le_u64(&mut r)?));
+        }
+        let n = read_le_u64(&mut r)? as usize;
+        for _ in 0..n {
+            list.counts.push(read_le_u32(&mut r)?);
+        }
+        ecs.validate().map_err(|e| invalid(e.to_string()))?;
+        Ok(ecs)
+    }
+
+    /// The number of classes held, which can differ from the declared
+    /// `neq` while a collection is being edited.
+    pub fn len(&self) -> usize {
+        self.classes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.classes.is_empty()
+    }
+
+    /// Class `i`. `EqClassView` borrows from the flattened arrays, so this
+    /// stands in for `Index`, which can only hand out references to stored
+    /// values; like indexing, it panics if `i` is out of range.
+    pub fn class(&self, i: usize) -> EqClassView<'_> {
+        match self.get(i) {
+            Some(ec) => ec,
+            None => panic!("class index {} out of range for {} classes", i, self.len()),
+        }
+    }
+
+    /// Whether `other` has the same targets and class structure (labels,
+    /// counts and offsets, compared exactly) with every weight within `eps`.
+    pub fn approx_eq(&self, other: &Self, eps: f64) -> bool {
+        let (a, b) = (&self.classes, &other.classes);
+        self.targets == other.targets
+            && self.ntarget == other.ntarget
+            && self.neq == other.neq
+            && a.offsets == b.offsets
+            && a.labels == b.labels
+            && a.counts == b.counts
+            && a.weights.len() == b.weights.len()
+            && a.weights
+                .iter()
+                .zip(&b.weights)
+                .all(|(x, y)| (x - y).abs() <= eps)
+    }
+
+    /// Like [`from_path`](Self::from_path), but with the class lines parsed
+    /// on one thread per available core. The rest of the file is read into
+    /// memory first and split into contiguous runs of lines, one per thread,
+    /// whose results are joined in file order, so the classes come out in
+    /// exactly the order the serial parser yields.
+    pub fn from_path_parallel<P: AsRef<Path>>(
+        filename: &P,
+    ) -> Result<EqClassCollection, io::Error> {
+        let mut reader = EqFileReader::new(open_eq_file(filename.as_ref())?);
+        let (targets, num_eq) = reader.read_header()?;
+        let first_line = reader.line + 1;
+        let mut rest = String::new();
+        reader.reader.read_to_string(&mut rest)?;
+        let mut lines: Vec<&str> = rest.lines().collect();
+        while lines.last().is_some_and(|l| is_blank(l)) {
+            lines.pop();
+        }
+        if lines.len() != num_eq {
+            return Err(EqParseError::ClassCount {
+                declared: num_eq,
+                found: lines.len(),
+            }
+            .into());
+        }
+        let has_weights = match lines.first() {
+            Some(line) => class_line_has_weights(line, first_line)?,
+            None => false,
+        };
+
+        let nthreads = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let chunk = num_eq.div_ceil(nthreads).max(1);
+        let parts: Vec<Result<EqClassList, EqParseError>> = std::thread::scope(|s| {
+            let workers: Vec<_> = lines
+                .chunks(chunk)
+                .enumerate()
+                .map(|(c, chunk_lines)| {
+                    s.spawn(move || {
+                        let mut list = EqClassList::new();
+                        let mut ec = EqClass {
+                            labels: Vec::new(),
+                            weights: Vec::new(),
+                            count: 0,
+                        };
+                        for (k, line) in chunk_lines.iter().enumerate() {
+                            let lineno = first_line + c * chunk + k;
+                            parse_eq_line_into(line, lineno, has_weights, &mut ec)?;
+                            list.push_view(EqClassView {
+                                labels: &ec.labels,
+                                weights: &ec.weights,
+                                count: ec.count,
+                            });
+                        }
+                        Ok(list)
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .map(|w| w.join().expect("eq-class parser thread panicked"))
+                .collect()
+        });
+
+        let mut exp = EqClassCollection::new();
+        exp.ntarget = targets.len();
+        exp.neq = num_eq;
+        exp.targets = targets;
+        let list = &mut exp.classes;
+        for part in parts {
+            let mut part = part?;
+            let base = list.labels.len();
+            list.offsets
+                .extend(part.offsets[1..].iter().map(|o| o + base));
+            list.labels.append(&mut part.labels);
+            list.weights.append(&mut part.weights);
+            list.counts.append(&mut part.counts);
+        }
+        Ok(exp)
+    }
+
+    /// Parse an eq-class file that is already in memory, without copying it
+    /// into lines or validating it as UTF-8 as a whole: integers are read
+    /// straight from the bytes. Gzipped input is detected and handed to the
+    /// streaming parser instead.
+    ///
+    /// This is the entry point for memory-mapped files. A caller mapping one
+    /// (e.g. with `memmap2`) has to ensure the file is not modified or
+    /// truncated while the map is alive, as with any map.
+    pub fn from_slice(bytes: &[u8]) -> Result<EqClassCollection, io::Error> {
+        if bytes.starts_with(&[0x1f, 0x8b]) {
+            return EqClassCollection::from_reader(bytes, true);
+        }
+        Ok(parse_eq_slice(bytes)?)
+    }
+
+    /// The total read count over all classes, summed as `u64` so deep
+    /// samples cannot overflow.
+    pub fn total_count(&self) -> u64 {
+        self.classes.counts.iter().map(|&c| c as u64).sum()
+    }
+
+    /// How many classes have each size (number of labels), by size.
+    pub fn count_histogram(&self) -> BTreeMap<usize, usize> {
+        let mut hist = BTreeMap::new();
+        for ec in self.classes.iter() {
+            *hist.entry(ec.labels.len()).or_insert(0) += 1;
+        }
+        hist
+    }
+
+    /// A builder over the given targets, for assembling a collection in
+    /// code; see [`EqClassCollectionBuilder`].
+    pub fn builder(targets: Vec<String>) -> EqClassCollectionBuilder {
+        EqClassCollectionBuilder {
+            targets,
+            classes: EqClassList::new(),
+        }
+    }
+}
+
+/// How [`EqClassCollection::filter_by_labels`] matches a class against a
+/// target set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LabelMatch {
+    /// The class contains at least one of the targets.
+    Any,
+    /// The class contains every one of the targets.
+    All,
+}
+
+/// What [`EqClassCollection::to_sparse`] stores per entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SparseValues {
+    /// The label's weight within its class.
+    Weight,
+    /// The weight times the class count, i.e. the label's read mass.
+    WeightedCount,
+}
+
+/// A `nrows x ncols` (targets x classes) sparse matrix as coordinate
+/// triplets.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SparseEqMatrix {
+    pub nrows: usize,
+    pub ncols: usize,
+    pub rows: Vec<usize>,
+    pub cols: Vec<usize>,
+    pub vals: Vec<f64>,
+}
+
+/// The target co-occurrence graph of [`EqClassCollection::target_graph`]:
+/// an undirected edge joins two targets sharing at least one class, weighted
+/// by the total count of the classes they share. Edges are keyed `(u, v)`
+/// with `u < v`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TargetGraph {
+    pub ntarget: usize,
+    pub edges: HashMap<(usize, usize), f64>,
+}
+
+impl TargetGraph {
+    /// The weight of the edge between `u` and `v` in either order, 0 if
+    /// they share no class.
+    pub fn weight(&self, u: usize, v: usize) -> f64 {
+        let key = if u < v { (u, v) } else { (v, u) };
+        self.edges.get(&key).copied().unwrap_or(0.0)
+    }
+
+    /// The edges as `(u, v, weight)`, sorted by `(u, v)`.
+    pub fn edge_list(&self) -> Vec<(usize, usize, f64)> {
+        let mut edges: Vec<_> = self.edges.iter().map(|(&(u, v), &w)| (u, v, w)).collect();
+        edges.sort_by_key(|&(u, v, _)| (u, v));
+        edges
+    }
+}
+
+/// One batch of [`EqClassCollection::to_long_batches`], as parallel
+/// columns.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EqClassBatch {
+    pub eq_id: Vec<u64>,
+    pub target_id: Vec<u64>,
+    pub weight: Vec<f64>,
+    pub count: Vec<u32>,
+}
+
+impl EqClassBatch {
+    pub fn len(&self) -> usize {
+        self.eq_id.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.eq_id.is_empty()
+    }
+}
+
+/// Minimal disjoint-set forest used for grouping targets.
+struct UnionFind {
+    parent: Vec<usize>,
+}
+
+impl UnionFind {
+    fn new(n: usize) -> UnionFind {
+        UnionFind {
+            parent: (0..n).collect(),
+        }
+    }
+
+    fn find(&mut self, mut x: usize) -> usize {
+        while self.parent[x] != x {
+            self.parent[x] = self.parent[self.parent[x]];
+            x = self.parent[x];
+        }
+        x
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (ra, rb) = (self.find(a), self.find(b));
+        if ra != rb {
+            // keep the smaller index as root so output is deterministic
+            let (lo, hi) = if ra < rb { (ra, rb) } else { (rb, ra) };
+            self.parent[hi] = lo;
+        }
+    }
+}
+
+/// The first bytes of an eq-class cache written by
+/// [`EqClassCollection::save_binary`].
+pub const EQ_CACHE_MAGIC: &[u8; 8] = b"SEINEEQC";
+/// The cache layout version, bumped whenever the layout changes.
+pub const EQ_CACHE_VERSION: u32 = 2;
+
+fn read_le_u64<R: Read>(r: &mut R) -> Result<u64, io::Error> {
+    let mut b = [0u8; 8];
+    r.read_exact(&mut b)?;
+    Ok(u64::from_le_bytes(b))
+}
+
+/// A `u64` length followed by that many bytes of UTF-8. The bytes are read
+/// through `take`, so a corrupt length fails at the end of the input rather
+/// than allocating whatever size it names.
+fn read_le_string<R: Read>(r: &mut R) -> Result<String, io::Error> {
+    let len = read_le_u64(r)?;
+    let mut bytes = Vec::new();
+    r.take(len).read_to_end(&mut bytes)?;
+    if (bytes.len() as u64) < len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    String::from_utf8(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn read_le_u32<R: Read>(r: &mut R) -> Result<u32, io::Error> {
+    let mut b = [0u8; 4];
+    r.read_exact(&mut b)?;
+    Ok(u32::from_le_bytes(b))
+}
+
+/// The body of [`EqClassCollection::from_reader`], on decompressed input.
+fn parse_eq_classes<R: BufRead>(reader: R) -> Result<EqClassCollection, io::Error> {
+    let mut reader = EqFileReader::new(reader);
+    let mut exp = EqClassCollection::new();
+
+    let (tnames, num_eq) = reader.read_header()?;
+    exp.ntarget = tnames.len();
+    exp.neq = num_eq;
+    exp.targets = tnames;
+
+    //let mut pb = pbr::ProgressBar::new(count);
+    //pb.format("╢▌▌░╟");
+
+    // parse every line into the same scratch class and copy it into the
+    // flattened list, so no per-class vectors are allocated
+    let mut scratch = EqClass {
+        labels: Vec::new(),
+        weights: Vec::new(),
+        count: 0,
+    };
+    for i in 0..num_eq {
+        reader.next_declared_class(i, num_eq, &mut scratch)?;
+        exp.classes.push_view(EqClassView {
+            labels: &scratch.labels,
+            weights: &scratch.weights,
+            count: scratch.count,
+        });
+        //pb.inc();
+    }
+    //pb.finish_print("done");
+    reader.finish(num_eq)?;
+    Ok(exp)
+}
+
+/// Open an eq-class file, decompressing it if it is gzipped, whatever it is
+/// called.
+fn open_eq_file(filename: &Path) -> Result<BufReader<Box<dyn Read>>, io::Error> {
+    Ok(BufReader::new(decompress_if_gzipped(File::open(
+        filename,
+    )?)?))
+}
+
+/// Inspect the header and first class line of an eq-class file to tell
+/// whether per-label weights were written: a class line has `1 + 2*nt + 1`
+/// tokens with weights and `1 + nt + 1` without.
+pub fn eq_class_has_weights<P: AsRef<Path>>(p: P) -> Result<bool, SeineError> {
+    let mut reader = EqFileReader::new(open_eq_file(p.as_ref())?);
+    reader.read_header()?;
+    reader.next_line("an equivalence class")?;
+    Ok(class_line_has_weights(&reader.buf, reader.line).map_err(io::Error::from)?)
+}
+
+/// Check that the class count declared in the run's eq-class file matches
+/// `num_eq_classes` in its meta_info.json, which catches truncated files and
+/// output directories assembled from different runs. Only the file's header
+/// is read.
+pub fn check_eq_class_count(sf: &SalmonFiles) -> Result<(), SeineError> {
+    let meta_info = sf.read_meta_info()?.num_eq_classes as usize;
+    let mut reader = EqFileReader::new(BufReader::new(sf.open_eq_classes()?));
+    reader.next_line("number of targets")?;
+    reader.next_line("number of equivalence classes")?;
+    let eq_file = parse_header_count(&reader.buf, reader.line, "number of equivalence classes")
+        .map_err(io::Error::from)?;
+    if meta_info != eq_file {
+        return Err(SeineError::EqClassCountMismatch { meta_info, eq_file });
+    }
+    Ok(())
+}
+
+/// Check that a quant.sf and an eq-class file come from the same index:
+/// salmon writes both in the same target order, so the quant rows must name
+/// exactly the eq-class targets, position by position. A length difference
+/// is reported as [`SeineError::TargetCountMismatch`], otherwise the first
+/// differing position as [`SeineError::TargetNameMismatch`] (with the quant
+/// name as `expected`).
+pub fn check_consistency(quant: &QuantTable, eqs: &EqClassCollection) -> Result<(), SeineError> {
+    if quant.names.len() != eqs.ntarget {
+        return Err(SeineError::TargetCountMismatch {
+            expected: quant.names.len(),
+            found: eqs.ntarget,
+        });
+    }
+    check_same_targets(&quant.names, &eqs.targets)
+}
+
+/// The valid (non-decoy) targets of a run with `ntarget` targets. Salmon
+/// places decoys after every valid target, so these are the first
+/// `num_valid_targets` from meta_info.json; a run recording more valid
+/// targets than there are fails with [`SeineError::TargetCountMismatch`].
+fn valid_prefix(ntarget: usize, mi: &MetaInfo) -> Result<Range<usize>, SeineError> {
+    let nvalid = mi.num_valid_targets as usize;
+    if nvalid > ntarget {
+        return Err(SeineError::TargetCountMismatch {
+            expected: nvalid,
+            found: ntarget,
+        });
+    }
+    Ok(0..nvalid)
+}
+
+/// Whether the class on line `lineno` carries weights, from its token count.
+fn class_line_has_weights(line: &str, lineno: usize) -> Result<bool, EqParseError> {
+    let nt: usize = parse_field(line.split_ascii_whitespace().next(), lineno, "label count")?;
+    let found = line.split_ascii_whitespace().count();
+    if found == 2 * nt + 2 {
+        Ok(true)
+    } else if found == nt + 2 {
+        Ok(false)
+    } else {
+        Err(EqParseError::TokenCount {
+            line: lineno,
+            labels: nt,
+            found,
+        })
+    }
+}
+
+/// Line-numbered reader over an eq-class file that reports malformed or
+/// truncated input as [`EqParseError`]s (wrapped in `InvalidData` I/O
+/// errors). Whether classes carry weights is detected from the first class
+/// line.
+struct EqFileReader<R> {
+    reader: R,
+    buf: String,
+    /// 1-based number of the line in `buf`.
+    line: usize,
+    has_weights: Option<bool>,
+}
+
+impl<R: BufRead> EqFileReader<R> {
+    fn new(reader: R) -> EqFileReader<R> {
+        EqFileReader {
+            reader,
+            buf: String::new(),
+            line: 0,
+            has_weights: None,
+        }
+    }
+
+    /// Read the next line, without its line ending, into `buf`.
+    fn next_line(&mut self, expected: &str) -> Result<(), io::Error> {
+        self.buf.clear();
+        self.line += 1;
+        if self.reader.read_line(&mut self.buf)? == 0 {
+            return Err(EqParseError::UnexpectedEof {
+                line: self.line,
+                expected: expected.to_string(),
+            }
+            .into());
+        }
+        let len = self.buf.trim_end_matches(&['\r', '\n'][..]).len();
+        self.buf.truncate(len);
+        Ok(())
+    }
+
+    /// The target names and the number of classes.
+    fn read_header(&mut self) -> Result<(Vec<String>, usize), io::Error> {
+        self.next_line("number of targets")?;
+        let num_target = parse_header_count(&self.buf, self.line, "number of targets")?;
+
+        self.next_line("number of equivalence classes")?;
+        let num_eq = parse_header_count(&self.buf, self.line, "number of equivalence classes")?;
+
+        let mut tnames = Vec::<String>::with_capacity(num_target);
+        for _ in 0..num_target {
+            self.next_line("target name")?;
+            tnames.push(self.buf.clone());
+        }
+        Ok((tnames, num_eq))
+    }
+
+    /// Whether the input is exhausted.
+    fn at_eof(&mut self) -> Result<bool, io::Error> {
+        Ok(self.reader.fill_buf()?.is_empty())
+    }
+
+    /// Consume the rest of the input, counting its non-blank lines.
+    fn count_remaining(&mut self) -> Result<usize, io::Error> {
+        let mut n = 0;
+        loop {
+            self.buf.clear();
+            if self.reader.read_line(&mut self.buf)? == 0 {
+                return Ok(n);
+            }
+            if !is_blank(&self.buf) {
+                n += 1;
+            }
+        }
+    }
+
+    /// Parse class `index` of the `declared` ones into `ec`. Input that
+    /// ends before it, or holds only blank lines from here on, fails with
+    /// [`EqParseError::ClassCount`].
+    fn next_declared_class(
+        &mut self,
+        index: usize,
+        declared: usize,
+        ec: &mut EqClass,
+    ) -> Result<(), io::Error> {
+        let short = EqParseError::ClassCount {
+            declared,
+            found: index,
+        };
+        if self.at_eof()? {
+            return Err(short.into());
+        }
+        self.next_line("equivalence class")?;
+        if is_blank(&self.buf) {
+            let blank = std::mem::take(&mut self.buf);
+            if self.count_remaining()? == 0 {
+                return Err(short.into());
+            }
+            // a blank line between classes is reported as malformed
+            self.buf = blank;
+        }
+        self.parse_class(ec)
+    }
+
+    /// After the `declared` classes have been read, fail with
+    /// [`EqParseError::ClassCount`] if any non-blank lines remain.
+    fn finish(&mut self, declared: usize) -> Result<(), io::Error> {
+        let extra = self.count_remaining()?;
+        if extra > 0 {
+            return Err(EqParseError::ClassCount {
+                declared,
+                found: declared + extra,
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Parse the class line in `buf` into `ec`, reusing its buffers.
+    fn parse_class(&mut self, ec: &mut EqClass) -> Result<(), io::Error> {
+        let weighted = match self.has_weights {
+            Some(w) => w,
+            None => *self
+                .has_weights
+                .insert(class_line_has_weights(&self.buf, self.line)?),
+        };
+        parse_eq_line_into(&self.buf, self.line, weighted, ec)?;
+        Ok(())
+    }
+}
+
+/// Whether a line holds nothing but ASCII whitespace. Trailing blank lines
+/// after the last class are ignored by every eq-class parser.
+fn is_blank(line: &str) -> bool {
+    line.bytes().all(|b| b.is_ascii_whitespace())
+}
+
+/// Parse the class on line `lineno`:
+/// `nt label_1 .. label_nt weight_1 .. weight_nt count`, or
+/// `nt label_1 .. label_nt count` when salmon wrote no weights, in which
+/// case every label gets the uniform weight `1 / nt`. Reuses `ec`'s buffers.
+fn parse_eq_line_into(
+    line: &str,
+    lineno: usize,
+    has_weights: bool,
+    ec: &mut EqClass,
+) -> Result<(), EqParseError> {
+    let mut iter = line.split_ascii_whitespace();
+    let nt: usize = parse_field(iter.next(), lineno, "label count")?;
+    let expected = if has_weights { 2 * nt + 2 } else { nt + 2 };
+    let found = line.split_ascii_whitespace().count();
+    if found != expected {
+        return Err(EqParseError::TokenCount {
+            line: lineno,
+            labels: nt,
+            found,
+        });
+    }
+    ec.labels.clear();
+    ec.weights.clear();
+    for _ in 0..nt {
+        ec.labels
+            .push(parse_field(iter.next(), lineno, "target label")?);
+    }
+    if has_weights {
+        for _ in 0..nt {
+            ec.weights.push(parse_field(iter.next(), lineno, "weight")?);
+        }
+    } else {
+        ec.weights.resize(nt, 1.0 / nt as f64);
+    }
+    ec.count = parse_field(iter.next(), lineno, "count")?;
+    Ok(())
+}
+
+/// Parse one token of line `lineno` as `expected`.
+fn parse_field<T: std::str::FromStr>(
+    tok: Option<&str>,
+    lineno: usize,
+    expected: &str,
+) -> Result<T, EqParseError> {
+    let tok = tok.unwrap_or("");
+    tok.parse().map_err(|_| EqParseError::InvalidField {
+        line: lineno,
+        expected: expected.to_string(),
+        found: tok.to_string(),
+    })
+}
+
+/*
+ * Byte-level parsing, for input that is already in memory (e.g. a
+ * memory-mapped file). Integers are parsed straight from the bytes and only
+ * target names and weights go through `str`.
+ */
+
+/// Lines of an in-memory eq-class file, numbered from 1.
+struct ByteLines<'a> {
+    rest: &'a [u8],
+    line: usize,
+}
+
+impl<'a> ByteLines<'a> {
+    /// The next line without its line ending.
+    fn next_line(&mut self, expected: &str) -> Result<&'a [u8], EqParseError> {
+        self.line += 1;
+        if self.rest.is_empty() {
+            return Err(EqParseError::UnexpectedEof {
+                line: self.line,
+                expected: expected.to_string(),
+            });
+        }
+        let end = self
+            .rest
+            .iter()
+            .position(|&b| b == b'\n')
+            .unwrap_or(self.rest.len());
+        let mut line = &self.rest[..end];
+        self.rest = &self.rest[(end + 1).min(self.rest.len())..];
+        while let [head @ .., b'\r'] = line {
+            line = head;
+        }
+        Ok(line)
+    }
+}
+
+fn byte_tokens(line: &[u8]) -> impl Iterator<Item = &[u8]> {
+    line.split(|b| b.is_ascii_whitespace())
+        .filter(|t| !t.is_empty())
+}
+
+fn invalid_bytes(tok: &[u8], lineno: usize, expected: &str) -> EqParseError {
+    EqParseError::InvalidField {
+        line: lineno,
+        expected: expected.to_string(),
+        found: String::from_utf8_lossy(tok).into_owned(),
+    }
+}
+
+/// Parse a decimal integer without going through `str`.
+fn parse_u64_bytes(tok: Option<&[u8]>, lineno: usize, expected: &str) -> Result<u64, EqParseError> {
+    let tok = tok.unwrap_or(b"");
+    if tok.is_empty() {
+        return Err(invalid_bytes(tok, lineno, expected));
+    }
+    let mut n: u64 = 0;
+    for &b in tok {
+        n = if b.is_ascii_digit() {
+            n.checked_mul(10)
+                .and_then(|n| n.checked_add((b - b'0') as u64))
+        } else {
+            None
+        }
+        .ok_or_else(|| invalid_bytes(tok, lineno, expected))?;
+    }
+    Ok(n)
+}
+
+fn parse_f64_bytes(tok: Option<&[u8]>, lineno: usize, expected: &str) -> Result<f64, EqParseError> {
+    let tok = tok.unwrap_or(b"");
+    std::str::from_utf8(tok)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| invalid_bytes(tok, lineno, expected))
+}
+
+/// The byte-level counterpart of [`parse_eq_classes`].
+fn parse_eq_slice(bytes: &[u8]) -> Result<EqClassCollection, EqParseError> {
+    let mut lines = ByteLines {
+        rest: bytes,
+        line: 0,
+    };
+    let mut header_count = |what: &str| -> Result<usize, EqParseError> {
+        let line = lines.next_line(what)?;
+        Ok(parse_u64_bytes(byte_tokens(line).next(), lines.line, what)? as usize)
+    };
+    let num_target = header_count("number of targets")?;
+    let num_eq = header_count("number of equivalence classes")?;
+
+    let mut exp = EqClassCollection::new();
+    for _ in 0..num_target {
+        let line = lines.next_line("target name")?;
+        let name = std::str::from_utf8(line)
+            .map_err(|_| invalid_bytes(line, lines.line, "target name"))?;
+        exp.targets.push(name.to_string());
+    }
+    exp.ntarget = num_target;
+    exp.neq = num_eq;
+
+    let mut has_weights = None;
+    let list = &mut exp.classes;
+    for i in 0..num_eq {
+        if lines.rest.iter().all(|b| b.is_ascii_whitespace()) {
+            return Err(EqParseError::ClassCount {
+                declared: num_eq,
+                found: i,
+            });
+        }
+        let line = lines.next_line("equivalence class")?;
+        let lineno = lines.line;
+        let found = byte_tokens(line).count();
+        let mut toks = byte_tokens(line);
+        let nt = parse_u64_bytes(toks.next(), lineno, "label count")? as usize;
+        let weighted = *has_weights.get_or_insert(found == 2 * nt + 2);
+        let expected = if weighted { 2 * nt + 2 } else { nt + 2 };
+        if found != expected {
+            return Err(EqParseError::TokenCount {
+                line: lineno,
+                labels: nt,
+                found,
+            });
+        }
+        for _ in 0..nt {
+            let tok = toks.next();
+            let label = parse_u64_bytes(tok, lineno, "target label")?;
+            list.labels.push(
+                Label::try_from(label)
+                    .map_err(|_| invalid_bytes(tok.unwrap_or(b""), lineno, "target label"))?,
+            );
+        }
+        if weighted {
+            for _ in 0..nt {
+                list.weights
+                    .push(parse_f64_bytes(toks.next(), lineno, "weight")?);
+            }
+        } else {
+            list.weights
+                .extend(std::iter::repeat_n(1.0 / nt as f64, nt));
+        }
+        let tok = toks.next();
+        let count = parse_u64_bytes(tok, lineno, "count")?;
+        if count > u64::from(u32::MAX) {
+            return Err(invalid_bytes(tok.unwrap_or(b""), lineno, "count"));
+        }
+        list.counts.push(count as u32);
+        list.offsets.push(list.labels.len());
+    }
+    let extra = lines
+        .rest
+        .split(|&b| b == b'\n')
+        .filter(|l| !l.iter().all(|b| b.is_ascii_whitespace()))
+        .count();
+    if extra > 0 {
+        return Err(EqParseError::ClassCount {
+            declared: num_eq,
+            found: num_eq + extra,
+        });
+    }
+    Ok(exp)
+}
+
+/// The header of an eq-class file: the target names and the number of
+/// classes that follow.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EqClassHeader {
+    pub targets: Vec<String>,
+    pub neq: usize,
+}
+
+/// Stream the classes of an eq-class file (plain or gzipped) through `f`
+/// one at a time, reusing a single scratch class, so aggregates can be
+/// computed without materializing the whole collection. Returns the header.
+pub fn for_each_eq_class<P, F>(path: P, mut f: F) -> Result<EqClassHeader, io::Error>
+where
+    P: AsRef<Path>,
+    F: FnMut(EqClassView),
+{
+    let mut reader = EqFileReader::new(open_eq_file(path.as_ref())?);
+    let (targets, neq) = reader.read_header()?;
+
+    let mut scratch = EqClass {
+        labels: Vec::new(),
+        weights: Vec::new(),
+        count: 0,
+    };
+    for i in 0..neq {
+        reader.next_declared_class(i, neq, &mut scratch)?;
+        f(EqClassView {
+            labels: &scratch.labels,
+            weights: &scratch.weights,
+            count: scratch.count,
+        });
+    }
+    reader.finish(neq)?;
+    Ok(EqClassHeader { targets, neq })
+}
+
+/// Parse the leading integer of an eq-class header line, ignoring any
+/// trailing tokens (e.g. `12  # comment`).
+fn parse_header_count(line: &str, lineno: usize, what: &str) -> Result<usize, EqParseError> {
+    parse_field(line.split_ascii_whitespace().next(), lineno, what)
+}
+
+impl Default for EqClassCollection {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Collections are equal when their targets, counts and classes are; the
+/// recorded sort order and the cached name index are not compared. Weights
+/// are compared exactly.
+impl PartialEq for EqClassCollection {
+    fn eq(&self, other: &Self) -> bool {
+        self.targets == other.targets
+            && self.ntarget == other.ntarget
+            && self.neq == other.neq
+            && self.classes == other.classes
+    }
+}
+
+impl<'a> IntoIterator for &'a EqClassList {
+    type Item = EqClassView<'a>;
+    type IntoIter = IterEqClassList<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a EqClassCollection {
+    type Item = EqClassView<'a>;
+    type IntoIter = IterEqClassList<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.classes.iter()
+    }
+}
+
+/// Owning iterator over a collection's classes, yielding each as an
+/// [`EqClass`].
+pub struct IntoIterEqClasses {
+    inner: EqClassList,
+    pos: usize,
+}
+
+impl Iterator for IntoIterEqClasses {
+    type Item = EqClass;
+    fn next(&mut self) -> Option<Self::Item> {
+        let ec = self.inner.get(self.pos).map(|ec| EqClass {
+            labels: ec.labels.to_vec(),
+            weights: ec.weights.to_vec(),
+            count: ec.count,
+        });
+        self.pos += 1;
+        ec
+    }
+}
+
+impl IntoIterator for EqClassCollection {
+    type Item = EqClass;
+    type IntoIter = IntoIterEqClasses;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIterEqClasses {
+            inner: self.classes,
+            pos: 0,
+        }
+    }
+}
+
+/// Builds an [`EqClassCollection`] in code, e.g. for tests and
+/// simulations. Targets must be added before the classes that refer to
+/// them.
+#[derive(Debug)]
+pub struct EqClassCollectionBuilder {
+    targets: Vec<String>,
+    classes: EqClassList,
+}
+
+impl EqClassCollectionBuilder {
+    pub fn new() -> EqClassCollectionBuilder {
+        EqClassCollectionBuilder {
+            targets: Vec::new(),
+            classes: EqClassList::new(),
+        }
+    }
+
+    /// Add a target, returning its label. Fails with
+    /// [`SeineError::TargetOutOfRange`], adding nothing, once every
+    /// [`Label`] is taken.
+    pub fn add_target<S: Into<String>>(&mut self, name: S) -> Result<Label, SeineError> {
+        let index = self.targets.len();
+        let label = Label::try_from(index).map_err(|_| SeineError::TargetOutOfRange {
+            index,
+            ntarget: (Label::MAX as usize).saturating_add(1),
+        })?;
+        self.targets.push(name.into());
+        Ok(label)
+    }
+
+    /// Add a class over already-added targets, with one weight per label.
+    pub fn add_class(
+        &mut self,
+        labels: Vec<Label>,
+        weights: Vec<f64>,
+        count: u32,
+    ) -> Result<(), SeineError> {
+        let invalid = |reason: String| SeineError::InvalidEqClass {
+            class: self.classes.len(),
+            reason,
+        };
+        if labels.is_empty() {
+            return Err(invalid("a class needs at least one label".to_string()));
+        }
+        if weights.len() != labels.len() {
+            return Err(invalid(format!(
+                "{} weights for {} labels",
+                weights.len(),
+                labels.len()
+            )));
+        }
+        if let Some(&l) = labels.iter().find(|&&l| l as usize >= self.targets.len()) {
+            return Err(invalid(format!(
+                "label {} is not one of the {} targets",
+                l,
+                self.targets.len()
+            )));
+        }
+        self.classes.push(EqClass {
+            labels,
+            weights,
+            count,
+        });
+        Ok(())
+    }
+
+    /// [`add_class`](Self::add_class) for an already assembled class.
+    pub fn push(&mut self, ec: EqClass) -> Result<(), SeineError> {
+        self.add_class(ec.labels, ec.weights, ec.count)
+    }
+
+    pub fn build(self) -> EqClassCollection {
+        EqClassCollection {
+            ntarget: self.targets.len(),
+            neq: self.classes.len(),
+            targets: self.targets,
+            classes: self.classes,
+            order: None,
+            name_index: None,
+        }
+    }
+}
+
+impl Default for EqClassCollectionBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A configurable eq-class file loader, for options that
+/// [`EqClassCollection::from_path`] does not expose.
+#[derive(Debug, Clone, Default)]
+pub struct EqClassReader {
+    sanitize: bool,
+}
+
+/// The outcome of [`EqClassReader::read`].
+#[derive(Debug)]
+pub struct EqClassLoad {
+    pub collection: EqClassCollection,
+    /// Classes whose weights had to be repaired (always 0 unless sanitizing).
+    pub num_repaired: usize,
+}
+
+impl EqClassReader {
+    pub fn new() -> EqClassReader {
+        EqClassReader::default()
+    }
+
+    /// When enabled, NaN or negative weights are replaced by 0 and the
+    /// affected classes renormalized to sum to 1 (uniformly if nothing
+    /// positive is left), so partially corrupt files can still be used.
+    pub fn sanitize(mut self, sanitize: bool) -> EqClassReader {
+        self.sanitize = sanitize;
+        self
+    }
+
+    pub fn read<P: AsRef<Path>>(&self, path: P) -> Result<EqClassLoad, io::Error> {
+        let mut collection = EqClassCollection::from_path(&path)?;
+        let num_repaired = if self.sanitize {
+            sanitize_weights(&mut collection.classes)
+        } else {
+            0
+        };
+        Ok(EqClassLoad {
+            collection,
+            num_repaired,
+        })
+    }
+}
+
+/// Repair NaN/negative weights in place, returning how many classes
+/// needed it.
+fn sanitize_weights(classes: &mut EqClassList) -> usize {
+    let mut repaired = 0;
+    for i in 0..classes.len() {
+        let w = &mut classes.weights[classes.offsets[i]..classes.offsets[i + 1]];
+        if w.iter().all(|&x| x >= 0.0) {
+            continue;
+        }
+        repaired += 1;
+        for x in w.iter_mut().filter(|x| x.is_nan() || **x < 0.0) {
+            *x = 0.0;
+        }
+        let total: f64 = w.iter().sum();
+        let n = w.len() as f64;
+        for x in w.iter_mut() {
+            *x = if total > 0.0 { *x / total } else { 1.0 / n };
+        }
+    }
+    repaired
+}
+
+/// Convert an eq-class file to the CSR layout of [`EqClassCollection::to_csr`]
+/// while parsing, keeping only the current class and the row pointers in
+/// memory.
+///
+/// The output is a flat little-endian stream: `nrows: u64`, `ncols: u64`,
+/// then one `(column: u64, value: f64)` pair per nonzero in row order, and
+/// finally the `nrows + 1` row pointers as `u64`. Read it back with
+/// [`CsrMatrix::read_streamed`].
+pub fn eq_classes_to_csr_streaming<R: BufRead, W: Write>(
+    reader: R,
+    mut writer: W,
+) -> Result<(), io::Error> {
+    let mut reader = EqFileReader::new(reader);
+    let (targets, num_eq) = reader.read_header()?;
+    writer.write_all(&(num_eq as u64).to_le_bytes())?;
+    writer.write_all(&(targets.len() as u64).to_le_bytes())?;
+
+    let mut indptr = Vec::<u64>::with_capacity(num_eq + 1);
+    indptr.push(0);
+    let mut ec = EqClass {
+        labels: Vec::new(),
+        weights: Vec::new(),
+        count: 0,
+    };
+    for i in 0..num_eq {
+        reader.next_declared_class(i, num_eq, &mut ec)?;
+        for (l, w) in ec.labels.iter().zip(&ec.weights) {
+            writer.write_all(&(*l as u64).to_le_bytes())?;
+            writer.write_all(&w.to_le_bytes())?;
+        }
+        indptr.push(indptr.last().unwrap() + ec.labels.len() as u64);
+    }
+    reader.finish(num_eq)?;
+    for p in indptr {
+        writer.write_all(&p.to_le_bytes())?;
+    }
+    writer.flush()
+}
+
+/// Copy an eq-class file from `reader` to `writer`, keeping only the classes
+/// for which `predicate` holds. Kept class lines are written verbatim, so
+/// weighted and unweighted files stay in their own format.
+///
+/// The class-count line has to reflect the filter, so the input is read
+/// twice: once to count the kept classes and, after seeking back to where
+/// `reader` started, once to copy them. Only the current class is held in
+/// memory. `predicate` must give the same answer on both passes. For a
+/// gzipped file, which cannot seek, use [`filter_eq_file`].
+pub fn filter_eq_classes_streaming<R, W, F>(
+    mut reader: R,
+    writer: W,
+    predicate: F,
+) -> Result<(), io::Error>
+where
+    R: BufRead + Seek,
+    W: Write,
+    F: Fn(&EqClassView) -> bool,
+{
+    let start = reader.stream_position()?;
+    let kept = count_kept_classes(&mut reader, &predicate)?;
+    reader.seek(SeekFrom::Start(start))?;
+    write_kept_classes(reader, writer, kept, &predicate)
+}
+
+/// [`filter_eq_classes_streaming`] on the (plain or gzipped) eq-class file
+/// at `path`, which is opened once per pass instead of seeking.
+pub fn filter_eq_file<P, W, F>(path: P, writer: W, predicate: F) -> Result<(), io::Error>
+where
+    P: AsRef<Path>,
+    W: Write,
+    F: Fn(&EqClassView) -> bool,
+{
+    let path = path.as_ref();
+    let kept = count_kept_classes(open_eq_file(path)?, &predicate)?;
+    write_kept_classes(open_eq_file(path)?, writer, kept, &predicate)
+}
+
+/// The header of an eq-class file and how many of its classes `predicate`
+/// keeps, checking the class count on the way.
+fn count_kept_classes<R, F>(reader: R, predicate: &F) -> Result<(EqClassHeader, usize), io::Error>
+where
+    R: BufRead,
+    F: Fn(&EqClassView) -> bool,
+{
+    let mut reader = EqFileReader::new(reader);
+    let (targets, neq) = reader.read_header()?;
+    let mut ec = EqClass {
+        labels: Vec::new(),
+        weights: Vec::new(),
+        count: 0,
+    };
+    let mut num_kept = 0_usize;
+    for i in 0..neq {
+        reader.next_declared_class(i, neq, &mut ec)?;
+        let view = EqClassView {
+            labels: &ec.labels,
+            weights: &ec.weights,
+            count: ec.count,
+        };
+        if predicate(&view) {
+            num_kept += 1;
+        }
+    }
+    reader.finish(neq)?;
+    Ok((EqClassHeader { targets, neq }, num_kept))
+}
+
+/// The second pass of [`filter_eq_classes_streaming`]: write the filtered
+/// header, then copy the kept class lines from `reader`.
+fn write_kept_classes<R, W, F>(
+    reader: R,
+    mut writer: W,
+    (header, num_kept): (EqClassHeader, usize),
+    predicate: &F,
+) -> Result<(), io::Error>
+where
+    R: BufRead,
+    W: Write,
+    F: Fn(&EqClassView) -> bool,
+{
+    writeln!(writer, "{}", header.targets.len())?;
+    writeln!(writer, "{}", num_kept)?;
+    for name in &header.targets {
+        writeln!(writer, "{}", name)?;
+    }
+    let mut reader = EqFileReader::new(reader);
+    reader.read_header()?;
+    let mut ec = EqClass {
+        labels: Vec::new(),
+        weights: Vec::new(),
+        count: 0,
+    };
+    for i in 0..header.neq {
+        reader.next_declared_class(i, header.neq, &mut ec)?;
+        let view = EqClassView {
+            labels: &ec.labels,
+            weights: &ec.weights,
+            count: ec.count,
+        };
+        if predicate(&view) {
+            writer.write_all(reader.buf.as_bytes())?;
+            writer.write_all(b"\n")?;
+        }
+    }
+    writer.flush()
+}
+
+/// Reads from single-label classes, summed per gene. Genes with no unique
+/// reads are omitted, as are transcripts missing from `t2g`.
+pub fn unique_reads_per_gene(ecs: &EqClassCollection, t2g: &Tx2Gene) -> HashMap<String, u64> {
+    let mut reads = HashMap::<String, u64>::new();
+    for ec in ecs.classes.iter().filter(|ec| ec.labels.len() == 1) {
+        if let Some(gene) = t2g.gene(&ecs.targets[ec.labels[0] as usize]) {
+            *reads.entry(gene.to_string()).or_insert(0) += ec.count as u64;
+        }
+    }
+    reads
+}
+
+/// For each gene, the other genes it shares a multi-label eq class with.
+/// Genes that never co-occur with another gene are omitted, as are
+/// transcripts missing from `t2g`.
+pub fn gene_ambiguity_partners(
+    ecs: &EqClassCollection,
+    t2g: &Tx2Gene,
+) -> HashMap<String, HashSet<String>> {
+    let mut partners = HashMap::<String, HashSet<String>>::new();
+    for ec in ecs.classes.iter().filter(|ec| ec.labels.len() > 1) {
+        let mut genes: Vec<&str> = ec
+            .labels
+            .iter()
+            .filter_map(|&l| t2g.gene(&ecs.targets[l as usize]))
+            .collect();
+        genes.sort_unstable();
+        genes.dedup();
+        for &g in &genes {
+            for &h in genes.iter().filter(|&&h| h != g) {
+                partners
+                    .entry(g.to_string())
+                    .or_default()
+                    .insert(h.to_string());
+            }
+        }
+    }
+    partners
+}
+
+/// Distribute each class's count across its labels proportionally to
+/// `weight * lengths[label]`, a simple length prior that is much cheaper than
+/// running the EM. Classes whose products all vanish are split uniformly.
+/// The result is indexed by target and has length `lengths.len()`.
+pub fn length_weighted_counts(ecs: &EqClassCollection, lengths: &[u32]) -> Vec<f64> {
+    let mut counts = vec![0.0_f64; lengths.len()];
+    for ec in ecs.classes.iter() {
+        let denom: f64 = ec
+            .labels
+            .iter()
+            .zip(ec.weights)
+            .map(|(&l, w)| w * lengths[l as usize] as f64)
+            .sum();
+        for (&l, w) in ec.labels.iter().zip(ec.weights) {
+            counts[l as usize] += if denom > 0.0 {
+                ec.count as f64 * w * lengths[l as usize] as f64 / denom
+            } else {
+                ec.count as f64 / ec.labels.len() as f64
+            };
+        }
+    }
+    counts
+}
+
+/// Per-target counts (e.g. from [`EqClassCollection::naive_counts`] or
+/// the EM) divided by effective length in kilobases, taken from `quant` by
+/// position. Targets with an effective length of 0 get 0. Fails with
+/// [`SeineError::TargetCountMismatch`] unless there is one count per quant
+/// row.
+pub fn counts_per_effective_kb(
+    naive_or_em_counts: &[f64],
+    quant: &QuantTable,
+) -> Result<Vec<f64>, SeineError> {
+    if naive_or_em_counts.len() != quant.efflen.len() {
+        return Err(SeineError::TargetCountMismatch {
+            expected: quant.efflen.len(),
+            found: naive_or_em_counts.len(),
+        });
+    }
+    Ok(naive_or_em_counts
+        .iter()
+        .zip(&quant.efflen)
+        .map(|(c, &e)| if e > 0.0 { c / (e / 1000.0) } else { 0.0 })
+        .collect())
+}
+
+/// How two collections over the same targets differ, with classes keyed by
+/// their sorted label sets. Each list is sorted by label set.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EqClassDiff {
+    pub only_in_a: Vec<Vec<Label>>,
+    pub only_in_b: Vec<Vec<Label>>,
+    /// Shared classes whose counts differ, as `(labels, count_a, count_b)`.
+    pub count_changes: Vec<(Vec<Label>, u32, u32)>,
+}
+
+/// Compare two collections class by class. Fails unless both name the same
+/// targets in the same order.
+pub fn eq_class_diff(
+    a: &EqClassCollection,
+    b: &EqClassCollection,
+) -> Result<EqClassDiff, SeineError> {
+    check_same_targets(&a.targets, &b.targets)?;
+    let by_labels = |ecs: &EqClassCollection| {
+        let mut m = HashMap::<Vec<Label>, u32>::new();
+        for ec in ecs.classes.iter() {
+            let mut key = ec.labels.to_vec();
+            key.sort_unstable();
+            *m.entry(key).or_insert(0) += ec.count;
+        }
+        m
+    };
+    let (ma, mb) = (by_labels(a), by_labels(b));
+
+    let mut diff = EqClassDiff::default();
+    for (labels, &ca) in &ma {
+        match mb.get(labels) {
+            None => diff.only_in_a.push(labels.clone()),
+            Some(&cb) if cb != ca => diff.count_changes.push((labels.clone(), ca, cb)),
+            Some(_) => {}
+        }
+    }
+    diff.only_in_b = mb
+        .keys()
+        .filter(|l| !ma.contains_key(*l))
+        .cloned()
+        .collect();
+    diff.only_in_a.sort();
+    diff.only_in_b.sort();
+    diff.count_changes.sort();
+    Ok(diff)
+}
+
+/*******************************************************************************/
+/*                         Cohorts                                             */
+/*******************************************************************************/
+
+/// Check that `found` names the same targets as `expected`, in order.
+fn check_same_targets(expected: &[String], found: &[String]) -> Result<(), SeineError> {
+    if expected.len() != found.len() {
+        return Err(SeineError::TargetCountMismatch {
+            expected: expected.len(),
+            found: found.len(),
+        });
+    }
+    match expected.iter().zip(found).position(|(a, b)| a != b) {
+        Some(index) => Err(SeineError::TargetNameMismatch {
+            index,
+            expected: expected[index].clone(),
+            found: found[index].clone(),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Target names interned to dense ids, shared by the samples of an
+/// experiment so their labels can be compared directly; see
+/// [`EqClassCollection::from_path_with_dict`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TargetDict {
+    names: Vec<String>,
+    ids: HashMap<String, usize>,
+}
+
+impl TargetDict {
+    pub fn new() -> TargetDict {
+        TargetDict::default()
+    }
+
+    /// A dictionary of `names`, numbered in order. A repeated name keeps
+    /// its first id.
+    pub fn from_names<S: AsRef<str>>(names: &[S]) -> TargetDict {
+        let mut dict = TargetDict::new();
+        for name in names {
+            dict.intern(name.as_ref());
+        }
+        dict
+    }
+
+    /// The id of `name`, adding it with the next free id if it is new.
+    pub fn intern(&mut self, name: &str) -> usize {
+        if let Some(&id) = self.ids.get(name) {
+            return id;
+        }
+        self.ids.insert(name.to_string(), self.names.len());
+        self.names.push(name.to_string());
+        self.names.len() - 1
+    }
+
+    pub fn id(&self, name: &str) -> Option<usize> {
+        self.ids.get(name).copied()
+    }
+
+    pub fn name(&self, id: usize) -> Option<&str> {
+        self.names.get(id).map(|n| n.as_str())
+    }
+
+    /// Every name, in id order.
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// The id of each of `names`, e.g. the rows of a [`QuantTable`], failing
+    /// with [`SeineError::UnknownTarget`] on the first name not in the
+    /// dictionary.
+    pub fn ids_of<S: AsRef<str>>(&self, names: &[S]) -> Result<Vec<usize>, SeineError> {
+        names
+            .iter()
+            .map(|n| {
+                let n = n.as_ref();
+                self.id(n)
+                    .ok_or_else(|| SeineError::UnknownTarget(n.to_string()))
+            })
+            .collect()
+    }
+}
+
+/// The equivalence classes of several samples quantified against the same
+/// reference, sharing a single target list.
+#[derive(Debug, Default)]
+pub struct CohortEqClasses {
+    pub targets: Vec<String>,
+    pub sample_ids: Vec<String>,
+    pub classes: Vec<EqClassList>,
+}
+
+impl CohortEqClasses {
+    /// Load every sample listed in a headerless `sample_id\tpath` manifest.
+    /// Relative paths are resolved against the manifest's directory. All
+    /// samples must have identical target lists.
+    pub fn from_manifest<P: AsRef<Path>>(path: P) -> Result<CohortEqClasses, SeineError> {
+        let path = path.as_ref();
+        let base = path.parent().unwrap_or_else(|| Path::new(""));
+        let mut rdr = csv::ReaderBuilder::new()
+            .delimiter(b'\t')
+            .has_headers(false)
+            .from_reader(File::open(path)?);
+
+        let mut cohort = CohortEqClasses::default();
+        for record in rdr.deserialize() {
+            let (id, eq_path): (String, PathBuf) = record?;
+            let exp = EqClassCollection::from_path(&base.join(eq_path))?;
+            if cohort.sample_ids.is_empty() {
+                cohort.targets = exp.targets;
+            } else {
+                check_same_targets(&cohort.targets, &exp.targets)?;
+            }
+            cohort.sample_ids.push(id);
+            cohort.classes.push(exp.classes);
+        }
+        Ok(cohort)
+    }
+
+    pub fn targets(&self) -> &[String] {
+        &self.targets
+    }
+
+    /// The classes of the sample named `id`.
+    pub fn sample(&self, id: &str) -> Option<&EqClassList> {
+        self.sample_ids
+            .iter()
+            .position(|s| s == id)
+            .map(|i| &self.classes[i])
+    }
+
+    pub fn num_samples(&self) -> usize {
+        self.sample_ids.len()
+    }
+}
+
+/// Per-sample counts for every distinct label set across a cohort, from
+/// [`EqClassCollection::merge`]. Label sets are sorted and numbered in order
+/// of first appearance; weights are not kept.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MergedEqClasses {
+    pub targets: Vec<String>,
+    pub label_sets: Vec<Vec<Label>>,
+    /// `counts[i][s]` is the count of `label_sets[i]` in sample `s`, or 0
+    /// where the sample has no such class.
+    pub counts: Vec<Vec<u32>>,
+}
+
+/// A dictionary of the distinct label sets seen across samples, built one
+/// sample at a time. Label sets are sorted and given dense ids in order of
+/// first appearance, so the ids are reproducible for a fixed sample order.
+/// As with [`EqClassCollection::merge`], the samples must share a
+/// reference; labels are compared as target ids.
+#[derive(Debug, Clone, Default)]
+pub struct GlobalEqClassDict {
+    label_sets: Vec<Vec<Label>>,
+    ids: HashMap<Vec<Label>, usize>,
+    num_samples: usize,
+}
+
+impl GlobalEqClassDict {
+    pub fn new() -> GlobalEqClassDict {
+        GlobalEqClassDict::default()
+    }
+
+    /// Add a sample's classes, returning the global id of each local class
+    /// in class order. Label sets not seen before get the next free ids.
+    pub fn add_sample(&mut self, coll: &EqClassCollection) -> Vec<usize> {
+        self.num_samples += 1;
+        coll.classes
+            .iter()
+            .map(|ec| {
+                let mut key = ec.labels.to_vec();
+                key.sort_unstable();
+                let label_sets = &mut self.label_sets;
+                *self.ids.entry(key).or_insert_with_key(|key| {
+                    label_sets.push(key.clone());
+                    label_sets.len() - 1
+                })
+            })
+            .collect()
+    }
+
+    /// The number of distinct label sets.
+    pub fn len(&self) -> usize {
+        self.label_sets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.label_sets.is_empty()
+    }
+
+    pub fn num_samples(&self) -> usize {
+        self.num_samples
+    }
+
+    /// The sorted label set with global id `id`.
+    pub fn label_set(&self, id: usize) -> Option<&[Label]> {
+        self.label_sets.get(id).map(|l| l.as_slice())
+    }
+}
+
+/*******************************************************************************/
+/*                         Quants                                              */
+/*******************************************************************************/
+
+/// The quant.sf column names, in the order salmon writes them.
+pub const QUANT_HEADER: [&str; 5] = ["Name", "Length", "EffectiveLength", "TPM", "NumReads"];
+
+/// Check that the first line of the quant.sf at `path` is exactly
+/// [`QUANT_HEADER`], tab-separated.
+pub fn verify_quant_header<P: AsRef<Path>>(path: P) -> Result<(), SeineError> {
+    let path = path.as_ref();
+    let mut line = String::new();
+    BufReader::new(decompress_if_gzipped(File::open(path)?)?).read_line(&mut line)?;
+    let found: Vec<&str> = line
+        .trim_end_matches(&['\r', '\n'][..])
+        .split('\t')
+        .collect();
+    if found != QUANT_HEADER {
+        return Err(SeineError::HeaderMismatch {
+            path: path.to_path_buf(),
+            expected: QUANT_HEADER.join("\t"),
+            found: found.join("\t"),
+        });
+    }
+    Ok(())
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct QuantRecord {
+    #[serde(rename = "Name")]
+    pub name: String,
+
+    #[serde(rename = "Length")]
+    pub len: u32,
+
+    #[serde(rename = "EffectiveLength")]
+    pub efflen: f64,
+
+    #[serde(rename = "TPM")]
+    pub tpm: f64,
+
+    #[serde(rename = "NumReads")]
+    pub num_reads: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct QuantEntry {
+    pub len: u32,
+    pub efflen: f64,
+    pub tpm: f64,
+    pub num_reads: f64,
+}
+
+/// Which quant.sf value a comparison or ranking uses. The `Log` variants
+/// apply `log2(x + 1)` first, which keeps a few highly expressed
+/// transcripts from dominating.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuantValue {
+    Tpm,
+    NumReads,
+    LogTpm,
+    LogNumReads,
+}
+
+impl QuantValue {
+    pub fn of(self, e: &QuantEntry) -> f64 {
+        self.apply(e.tpm, e.num_reads)
+    }
+
+    pub fn of_record(self, r: &QuantRecord) -> f64 {
+        self.apply(r.tpm, r.num_reads)
+    }
+
+    /// The value for row `row` of `t`.
+    pub fn of_row(self, t: &QuantTable, row: usize) -> f64 {
+        self.apply(t.tpm[row], t.num_reads[row])
+    }
+
+    fn apply(self, tpm: f64, num_reads: f64) -> f64 {
+        match self {
+            QuantValue::Tpm => tpm,
+            QuantValue::NumReads => num_reads,
+            QuantValue::LogTpm => (tpm + 1.0).log2(),
+            QuantValue::LogNumReads => (num_reads + 1.0).log2(),
+        }
+    }
+}
+
+/// A quant.sf row split into the transcript name and its values.
+#[derive(Debug)]
+pub struct NamedQuantEntry {
+    pub name: String,
+    pub entry: QuantEntry,
+}
+
+impl From<QuantRecord> for NamedQuantEntry {
+    fn from(r: QuantRecord) -> Self {
+        NamedQuantEntry {
+            name: r.name,
+            entry: QuantEntry {
+                len: r.len,
+                efflen: r.efflen,
+                tpm: r.tpm,
+                num_reads: r.num_reads,
+            },
+        }
+    }
+}
+
+/// Lazily yields the rows of a quant.sf in file order, without
+/// materializing the whole table.
+pub struct NamedQuantReader {
+    records: csv::DeserializeRecordsIntoIter<Box<dyn Read>, QuantRecord>,
+}
+
+impl NamedQuantReader {
+    pub fn from_path<P: AsRef<Path>>(p: P) -> Result<NamedQuantReader, csv::Error> {
+        Ok(NamedQuantReader {
+            records: quant_reader(p.as_ref())?.into_deserialize(),
+        })
+    }
+}
+
+impl Iterator for NamedQuantReader {
+    type Item = Result<NamedQuantEntry, csv::Error>;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.records.next().map(|r| r.map(NamedQuantEntry::from))
+    }
+}
+
+/// Lazily yield the rows of the quant.sf (optionally gzipped) at `p` as
+/// [`QuantRecord`]s in file order, holding one row in memory at a time.
+pub fn quant_records<P: AsRef<Path>>(
+    p: P,
+) -> Result<impl Iterator<Item = Result<QuantRecord, csv::Error>>, csv::Error> {
+    Ok(quant_reader(p.as_ref())?.into_deserialize())
+}
+
+/// Reads per effective base (`num_reads / efflen`) for each record, in input
+/// order. This is the rate that TPM normalizes; a non-positive effective
+/// length gives a rate of 0.
+pub fn coverage(records: &[QuantRecord]) -> Vec<f64> {
+    records
+        .iter()
+        .map(|r| {
+            if r.efflen > 0.0 {
+                r.num_reads / r.efflen
+            } else {
+                0.0
+            }
+        })
+        .collect()
+}
+
+/// TPM from per-target rates (e.g. [`coverage`]): each rate's share of the
+/// total, scaled to sum to 1e6. All rates 0 gives all TPMs 0.
+pub fn tpm_from_rates(rates: &[f64]) -> Vec<f64> {
+    let total: f64 = rates.iter().sum();
+    rates
+        .iter()
+        .map(|&r| if total > 0.0 { r / total * 1e6 } else { 0.0 })
+        .collect()
+}
+
+/// The pseudocount conventionally added before log-transforming TPM or
+/// counts, so that zeros map to 0.
+pub const DEFAULT_PSEUDOCOUNT: f64 = 1.0;
+
+/// `log2(tpm + pseudocount)` for each record, in input order. Use
+/// [`DEFAULT_PSEUDOCOUNT`] unless a model calls for something else.
+pub fn log_tpm(records: &[QuantRecord], pseudocount: f64) -> Vec<f64> {
+    records
+        .iter()
+        .map(|r| (r.tpm + pseudocount).log2())
+        .collect()
+}
+
+/// A row ranked for [`QuantTable::top_n`]. Orders better rows first (larger
+/// value, then smaller name), so a max-heap keeps the worst on top.
+struct Ranked<'a> {
+    value: f64,
+    name: &'a str,
+}
+
+impl Ranked<'_> {
+    fn key(&self) -> f64 {
+        if self.value.is_nan() {
+            f64::NEG_INFINITY
+        } else {
+            self.value
+        }
+    }
+}
+
+impl Ord for Ranked<'_> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        other
+            .key()
+            .total_cmp(&self.key())
+            .then_with(|| self.name.cmp(other.name))
+    }
+}
+
+impl PartialOrd for Ranked<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Ranked<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == std::cmp::Ordering::Equal
+    }
+}
+
+impl Eq for Ranked<'_> {}
+
+/// quant.sf as parallel columns in file order, which is also the order of
+/// the bootstrap columns and ambig_info rows, with a name index for
+/// lookups.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct QuantTable {
+    pub names: Vec<String>,
+    pub len: Vec<u32>,
+    pub efflen: Vec<f64>,
+    pub tpm: Vec<f64>,
+    pub num_reads: Vec<f64>,
+    index: HashMap<String, usize>,
+}
+
+impl QuantTable {
+    pub fn from_path<P: AsRef<Path>>(p: P) -> Result<QuantTable, csv::Error> {
+        let mut table = QuantTable::default();
+        for rec in read_quant_records(p.as_ref())? {
+            table.push(rec);
+        }
+        Ok(table)
+    }
+
+    /// Append a row. A repeated name keeps pointing at its first row.
+    pub fn push(&mut self, rec: QuantRecord) {
+        self.index
+            .entry(rec.name.clone())
+            .or_insert(self.names.len());
+        self.names.push(rec.name);
+        self.len.push(rec.len);
+        self.efflen.push(rec.efflen);
+        self.tpm.push(rec.tpm);
+        self.num_reads.push(rec.num_reads);
+    }
+
+    /// Write the table as a tab-separated quant.sf with the
+    /// [`QUANT_HEADER`] columns, floats at full precision. With
+    /// `renormalize`, TPMs are rescaled to sum to 1e6 on the way out, which
+    /// restores the TPM invariant after rows have been filtered away.
+    /// An empty table still gets its header line. The file is written by
+    /// [`QuantWriter`] with `full_precision`.
+    pub fn write_to_path<P: AsRef<Path>>(&self, p: P, renormalize: bool) -> Result<(), csv::Error> {
+        let tpm = if renormalize {
+            tpm_from_rates(&self.tpm)
+        } else {
+            self.tpm.clone()
+        };
+        let records: Vec<QuantRecord> = (0..self.len())
+            .map(|i| QuantRecord {
+                name: self.names[i].clone(),
+                len: self.len[i],
+                efflen: self.efflen[i],
+                tpm: tpm[i],
+                num_reads: self.num_reads[i],
+            })
+            .collect();
+        let mut w = io::BufWriter::new(File::create(p)?);
+        QuantWriter::new()
+            .full_precision()
+            .write(&records, &mut w)?;
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// The rows that are not decoys, per `mi`'s `num_valid_targets`.
+    pub fn valid_target_range(&self, mi: &MetaInfo) -> Result<Range<usize>, SeineError> {
+        valid_prefix(self.len(), mi)
+    }
+
+    /// The table without its decoy rows. TPMs are kept as they are; see
+    /// [`write_to_path`](Self::write_to_path) to renormalize them.
+    pub fn drop_decoys(&self, mi: &MetaInfo) -> Result<QuantTable, SeineError> {
+        let mut table = QuantTable::default();
+        for i in self.valid_target_range(mi)? {
+            table.push(QuantRecord {
+                name: self.names[i].clone(),
+                len: self.len[i],
+                efflen: self.efflen[i],
+                tpm: self.tpm[i],
+                num_reads: self.num_reads[i],
+            });
+        }
+        Ok(table)
+    }
+
+    /// The row of transcript `name`.
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.index.get(name).copied()
+    }
+
+    /// Roll transcripts up to genes, in order of each gene's first
+    /// transcript: reads and TPM are summed, and lengths are the
+    /// read-weighted mean over the gene's transcripts, or the plain mean if
+    /// none of them has reads. Transcripts missing from `t2g` are dropped
+    /// when `unmapped` is `None` and pooled under that gene name otherwise.
+    pub fn aggregate_to_gene(&self, t2g: &Tx2Gene, unmapped: Option<&str>) -> QuantTable {
+        struct Acc {
+            reads: f64,
+            tpm: f64,
+            weighted_len: f64,
+            weighted_efflen: f64,
+            len: f64,
+            efflen: f64,
+            n: usize,
+        }
+        let mut order = Vec::<&str>::new();
+        let mut genes = HashMap::<&str, Acc>::new();
+        for i in 0..self.len() {
+            let gene = match t2g.gene(&self.names[i]).or(unmapped) {
+                Some(g) => g,
+                None => continue,
+            };
+            let acc = genes.entry(gene).or_insert_with(|| {
+                order.push(gene);
+                Acc {
+                    reads: 0.0,
+                    tpm: 0.0,
+                    weighted_len: 0.0,
+                    weighted_efflen: 0.0,
+                    len: 0.0,
+                    efflen: 0.0,
+                    n: 0,
+                }
+            });
+            let reads = self.num_reads[i];
+            acc.reads += reads;
+            acc.tpm += self.tpm[i];
+            acc.weighted_len += reads * self.len[i] as f64;
+            acc.weighted_efflen += reads * self.efflen[i];
+            acc.len += self.len[i] as f64;
+            acc.efflen += self.efflen[i];
+            acc.n += 1;
+        }
+
+        let mut table = QuantTable::default();
+        for gene in order {
+            let a = &genes[gene];
+            let (len, efflen) = if a.reads > 0.0 {
+                (a.weighted_len / a.reads, a.weighted_efflen / a.reads)
+            } else {
+                (a.len / a.n as f64, a.efflen / a.n as f64)
+            };
+            table.push(QuantRecord {
+                name: gene.to_string(),
+                len: len.round() as u32,
+                efflen,
+                tpm: a.tpm,
+                num_reads: a.reads,
+            });
+        }
+        table
+    }
+
+    /// The `n` transcripts with the largest `by` values, as `(name, value)`
+    /// in descending order with ties broken by name, or every transcript if
+    /// there are fewer than `n`. NaN values rank last. Selection keeps a
+    /// bounded heap of `n` rows rather than sorting the whole table.
+    pub fn top_n(&self, n: usize, by: QuantValue) -> Vec<(&str, f64)> {
+        let mut heap = BinaryHeap::with_capacity(n.min(self.len()) + 1);
+        for row in 0..self.len() {
+            heap.push(Ranked {
+                value: by.of_row(self, row),
+                name: &self.names[row],
+            });
+            if heap.len() > n {
+                heap.pop();
+            }
+        }
+        heap.into_sorted_vec()
+            .into_iter()
+            .map(|r| (r.name, r.value))
+            .collect()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct LengthRecord {
+    #[serde(rename = "Name")]
+    name: String,
+    #[serde(rename = "Length")]
+    len: u32,
+}
+
+/// Transcript lengths from a quant.sf, deserializing only the `Name` and
+/// `Length` columns.
+pub fn read_transcript_lengths<P: AsRef<Path>>(
+    path: P,
+) -> Result<HashMap<String, u32>, csv::Error> {
+    let mut lengths = HashMap::new();
+    for rec in quant_reader(path.as_ref())?.deserialize() {
+        let rec: LengthRecord = rec?;
+        lengths.insert(rec.name, rec.len);
+    }
+    Ok(lengths)
+}
+
+/// Sum technical replicates of one sample (e.g. lanes quantified
+/// separately): reads are added per transcript, lengths are taken from the
+/// first replicate, and TPM is recomputed from the summed reads. Every
+/// replicate must quantify the same transcripts.
+pub fn sum_quant_replicates(
+    maps: &[HashMap<String, QuantEntry>],
+) -> Result<HashMap<String, QuantEntry>, SeineError> {
+    let first = match maps.first() {
+        Some(m) => m,
+        None => return Ok(HashMap::new()),
+    };
+    for (i, m) in maps.iter().enumerate().skip(1) {
+        if m.len() != first.len() {
+            return Err(SeineError::TargetCountMismatch {
+                expected: first.len(),
+                found: m.len(),
+            });
+        }
+        if let Some(name) = first.keys().find(|k| !m.contains_key(*k)) {
+            return Err(SeineError::MissingTarget {
+                name: name.clone(),
+                input: i,
+            });
+        }
+    }
+
+    let mut merged: HashMap<String, QuantEntry> = first.clone();
+    for m in &maps[1..] {
+        for (name, e) in merged.iter_mut() {
+            e.num_reads += m[name].num_reads;
+        }
+    }
+    let rate = |e: &QuantEntry| {
+        if e.efflen > 0.0 {
+            e.num_reads / e.efflen
+        } else {
+            0.0
+        }
+    };
+    let tpm = tpm_from_rates(&merged.values().map(rate).collect::<Vec<_>>());
+    // an unmodified map iterates in the same order both times
+    for (e, tpm) in merged.values_mut().zip(tpm) {
+        e.tpm = tpm;
+    }
+    Ok(merged)
+}
+
+/// Assign each record (in input order) an expression tier: 0 for TPM 0,
+/// otherwise `1..=n_bins` by the quantile of its TPM among the expressed
+/// transcripts, so higher bins hold higher TPM. Tied TPMs share a bin.
+pub fn tpm_quantile_bins(records: &[QuantRecord], n_bins: usize) -> Vec<usize> {
+    assert!(n_bins > 0, "need at least one expressed bin");
+    let mut expressed: Vec<f64> = records.iter().map(|r| r.tpm).filter(|&t| t > 0.0).collect();
+    expressed.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    let n = expressed.len();
+    records
+        .iter()
+        .map(|r| {
+            if r.tpm > 0.0 {
+                // rank of the first record with this TPM, so ties stay together
+                let rank = expressed.partition_point(|&t| t < r.tpm);
+                1 + rank * n_bins / n
+            } else {
+                0
+            }
+        })
+        .collect()
+}
+
+/// The share of the total `by` value held by the top `top_frac` of
+/// transcripts (at least one when `top_frac > 0`), a simple
+/// library-complexity measure: values near 1 mean a few transcripts soak up
+/// most of the signal. NaN if the total is 0.
+pub fn top_fraction(records: &[QuantRecord], top_frac: f64, by: QuantValue) -> f64 {
+    let mut values: Vec<f64> = records.iter().map(|r| by.of_record(r)).collect();
+    let total: f64 = values.iter().sum();
+    if total <= 0.0 {
+        return f64::NAN;
+    }
+    values.sort_by(|a, b| b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal));
+    let k = (top_frac.clamp(0.0, 1.0) * values.len() as f64).ceil() as usize;
+    values[..k].iter().sum::<f64>() / total
+}
+
+/// What is wrong with a quant.sf row; see [`quant_integrity`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IntegrityIssueKind {
+    ZeroLength,
+    NonPositiveEffectiveLength,
+    /// The effective length exceeds the length by more than rounding.
+    EffectiveLengthExceedsLength,
+    NonFiniteTpm,
+    NonFiniteNumReads,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IntegrityIssue {
+    pub name: String,
+    pub kind: IntegrityIssueKind,
+}
+
+/// Sanity-check quant.sf rows, reporting every problem found, in record
+/// order: a zero length, a non-positive effective length, an effective
+/// length above `len + 1`, or a non-finite TPM or read count. A record can
+/// have several issues.
+pub fn quant_integrity(records: &[QuantRecord]) -> Vec<IntegrityIssue> {
+    let mut issues = Vec::new();
+    for r in records {
+        let mut flag = |kind| {
+            issues.push(IntegrityIssue {
+                name: r.name.clone(),
+                kind,
+            })
+        };
+        if r.len == 0 {
+            flag(IntegrityIssueKind::ZeroLength);
+        }
+        if r.efflen.is_nan() || r.efflen <= 0.0 {
+            flag(IntegrityIssueKind::NonPositiveEffectiveLength);
+        } else if r.efflen > r.len as f64 + 1.0 {
+            flag(IntegrityIssueKind::EffectiveLengthExceedsLength);
+        }
+        if !r.tpm.is_finite() {
+            flag(IntegrityIssueKind::NonFiniteTpm);
+        }
+        if !r.num_reads.is_finite() {
+            flag(IntegrityIssueKind::NonFiniteNumReads);
+        }
+    }
+    issues
+}
+
+/*******************************************************************************/
+/*                         Ambiguity Info                                      */
+/*******************************************************************************/
+
+const AMBIG_HEADER: [&str; 2] = ["UniqueCount", "AmbigCount"];
+
+#[derive(Debug, Deserialize)]
+struct AmbigRecord {
+    #[serde(rename = "UniqueCount")]
+    unique: u32,
+    #[serde(rename = "AmbigCount")]
+    ambig: u32,
+}
+
+/// Per-transcript unique and ambiguous read counts from
+/// `aux_info/ambig_info.tsv`, in quant.sf order.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AmbigInfo {
+    pub unique: Vec<u32>,
+    pub ambig: Vec<u32>,
+}
+
+impl AmbigInfo {
+    pub fn from_path<P: AsRef<Path>>(p: P) -> Result<AmbigInfo, csv::Error> {
+        let mut rdr = csv::ReaderBuilder::new()
+            .delimiter(b'\t')
+            .from_reader(File::open(p.as_ref())?);
+        let header: Vec<&str> = rdr.headers()?.iter().collect();
+        if header != AMBIG_HEADER {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{}: expected columns `{}`, found `{}`",
+                    p.as_ref().display(),
+                    AMBIG_HEADER.join("\t"),
+                    header.join("\t")
+                ),
+            )
+            .into());
+        }
+        let mut info = AmbigInfo::default();
+        for rec in rdr.deserialize() {
+            let rec: AmbigRecord = rec?;
+            info.unique.push(rec.unique);
+            info.ambig.push(rec.ambig);
+        }
+        Ok(info)
+    }
+
+    pub fn len(&self) -> usize {
+        self.unique.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.unique.is_empty()
+    }
+
+    /// The fraction of transcript `i`'s reads that were unique, or 0 if it
+    /// had none.
+    pub fn fraction_unique(&self, i: usize) -> f64 {
+        let total = self.unique[i] as f64 + self.ambig[i] as f64;
+        if total > 0.0 {
+            self.unique[i] as f64 / total
+        } else {
+            0.0
+        }
+    }
+}
+
+impl SalmonFiles {
+    pub fn read_ambig(&self) -> Result<AmbigInfo, SeineError> {
+        if !self.ambig_file.exists() {
+            return Err(SeineError::MissingFile(self.ambig_file.clone()));
+        }
+        Ok(AmbigInfo::from_path(&self.ambig_file)?)
+    }
+}
+
+/*******************************************************************************/
+/*                         Sample QC                                           */
+/*******************************************************************************/
+
+/// Headline health numbers for one salmon run, from meta_info.json and
+/// ambig_info.tsv. Fields are `None` when the file (or, for older salmon
+/// versions, the field) that provides them is missing. Displays as a
+/// one-line report with `NA` for unknown values.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SampleQc {
+    pub num_processed: Option<u64>,
+    pub num_mapped: Option<u64>,
+    pub percent_mapped: Option<f64>,
+    pub frag_length_mean: Option<f64>,
+    pub frag_length_sd: Option<f64>,
+    pub num_eq_classes: Option<u32>,
+    pub samp_type: Option<SampType>,
+    /// Fractions of all reads in ambig_info.tsv that were unique or
+    /// ambiguous; both are 0 if it records no reads.
+    pub unique_fraction: Option<f64>,
+    pub ambig_fraction: Option<f64>,
+}
+
+impl SampleQc {
+    /// Summarize the run. Missing files leave their fields unset; files
+    /// that exist but cannot be parsed are errors.
+    pub fn from_salmon_files(files: &SalmonFiles) -> Result<SampleQc, SeineError> {
+        let mut qc = SampleQc::default();
+        if files.mi_file.exists() {
+            let mi = files.read_meta_info()?;
+            qc.num_processed = mi.num_processed;
+            qc.num_mapped = mi.num_mapped;
+            qc.percent_mapped = mi.percent_mapped;
+            qc.frag_length_mean = mi.frag_length_mean;
+            qc.frag_length_sd = mi.frag_length_sd;
+            qc.num_eq_classes = Some(mi.num_eq_classes);
+            qc.samp_type = Some(mi.sampling_type());
+        }
+        if files.ambig_file.exists() {
+            let ambig = files.read_ambig()?;
+            let unique: f64 = ambig.unique.iter().map(|&u| u as f64).sum();
+            let total = unique + ambig.ambig.iter().map(|&a| a as f64).sum::<f64>();
+            let (u, a) = if total > 0.0 {
+                (unique / total, 1.0 - unique / total)
+            } else {
+                (0.0, 0.0)
+            };
+            qc.unique_fraction = Some(u);
+            qc.ambig_fraction = Some(a);
+        }
+        Ok(qc)
+    }
+}
+
+impl std::fmt::Display for SampleQc {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        fn na<T: std::fmt::Display>(v: Option<T>) -> String {
+            v.map_or_else(|| "NA".to_string(), |v| v.to_string())
+        }
+        let pct = |v: Option<f64>| na(v.map(|x| format!("{:.1}%", x)));
+        let one = |v: Option<f64>| na(v.map(|x| format!("{:.1}", x)));
+        write!(
+            f,
+            "mapped {} ({}/{}), fragment length {} ± {}, unique {}, ambiguous {}, {} eq classes",
+            pct(self.percent_mapped),
+            na(self.num_mapped),
+            na(self.num_processed),
+            one(self.frag_length_mean),
+            one(self.frag_length_sd),
+            pct(self.unique_fraction.map(|x| 100.0 * x)),
+            pct(self.ambig_fraction.map(|x| 100.0 * x)),
+            na(self.num_eq_classes)
+        )
+    }
+}
+
+/*******************************************************************************/
+/*                         Writers                                             */
+/*******************************************************************************/
+
+/// Format `x` with `digits` significant digits, like C's `%g`.
+pub fn format_significant(x: f64, digits: usize) -> String {
+    let digits = digits.max(1);
+    if x == 0.0 || !x.is_finite() {
+        return format!("{}", x);
+    }
+    let exp = x.abs().log10().floor() as i32;
+    if exp < -4 || exp >= digits as i32 {
+        let s = format!("{:.*e}", digits - 1, x);
+        // trim trailing zeros in the mantissa, as %g does
+        match s.find('e') {
+            Some(e) if s[..e].contains('.') => {
+                let mantissa = s[..e].trim_end_matches('0').trim_end_matches('.');
+                format!("{}{}", mantissa, &s[e..])
+            }
+            _ => s,
+        }
+    } else {
+        let decimals = (digits as i32 - 1 - exp).max(0) as usize;
+        let s = format!("{:.*}", decimals, x);
+        if s.contains('.') {
+            s.trim_end_matches('0').trim_end_matches('.').to_string()
+        } else {
+            s
+        }
+    }
+}
+
+/// Writes quant records in salmon's tab-separated quant.sf format.
+///
+/// By default floats are formatted exactly as salmon does (`EffectiveLength`
+/// and `NumReads` with 3 decimals, `TPM` with 6). Setting a `precision`
+/// instead emits that many significant digits for every float column, and
+/// `full_precision` the shortest form that reads back exactly.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct QuantWriter {
+    precision: Option<usize>,
+    full_precision: bool,
+}
+
+impl QuantWriter {
+    pub fn new() -> QuantWriter {
+        QuantWriter::default()
+    }
+
+    /// Emit `digits` significant digits for the float columns.
+    pub fn precision(mut self, digits: usize) -> QuantWriter {
+        self.precision = Some(digits);
+        self.full_precision = false;
+        self
+    }
+
+    /// Emit every float in its shortest exact form, so the file reads back
+    /// to the same values.
+    pub fn full_precision(mut self) -> QuantWriter {
+        self.full_precision = true;
+        self.precision = None;
+        self
+    }
+
+    pub fn format_float(&self, x: f64, salmon_decimals: usize) -> String {
+        match self.precision {
+            Some(p) => format_significant(x, p),
+            None if self.full_precision => format!("{}", x),
+            None => format!("{:.*}", salmon_decimals, x),
+        }
+    }
+
+    /// Write a header line followed by one line per record.
+    pub fn write<W: Write>(&self, records: &[QuantRecord], w: &mut W) -> Result<(), io::Error> {
+        writeln!(w, "{}", QUANT_HEADER.join("\t"))?;
+        for r in records {
+            writeln!(
+                w,
+                "{}\t{}\t{}\t{}\t{}",
+                r.name,
+                r.len,
+                self.format_float(r.efflen, 3),
+                self.format_float(r.tpm, 6),
+                self.format_float(r.num_reads, 3)
+            )?;
+        }
+        w.flush()
+    }
+}
+
+/// Write one JSON object per record (JSON Lines), keyed by the quant.sf
+/// column names.
+pub fn write_quant_jsonl<W: Write>(records: &[QuantRecord], w: &mut W) -> Result<(), io::Error> {
+    for r in records {
+        serde_json::to_writer(&mut *w, r)?;
+        w.write_all(b"\n")?;
+    }
+    w.flush()
+}
+
+/// Write gene-level entries (e.g. aggregated from transcripts) as a
+/// standard quant.sf, one row per gene ordered by name.
+pub fn write_gene_quant<W: Write>(
+    gene_quant: &HashMap<String, QuantEntry>,
+    w: &mut W,
+) -> Result<(), io::Error> {
+    let mut genes: Vec<&String> = gene_quant.keys().collect();
+    genes.sort();
+    let records: Vec<QuantRecord> = genes
+        .into_iter()
+        .map(|g| {
+            let e = &gene_quant[g];
+            QuantRecord {
+                name: g.clone(),
+                len: e.len,
+                efflen: e.efflen,
+                tpm: e.tpm,
+                num_reads: e.num_reads,
+            }
+        })
+        .collect();
+    QuantWriter::new().write(&records, w)
+}
+
+/*******************************************************************************/
+/*                         Fragment Lengths                                    */
+/*******************************************************************************/
+
+/// The smallest effective length assigned to any transcript.
+pub const MIN_EFFECTIVE_LENGTH: f64 = 1.0;
+
+/// A fragment-length distribution: `probs[l]` is the probability of a
+/// fragment of length `l`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FragmentLengthDist {
+    pub probs: Vec<f64>,
+}
+
+impl FragmentLengthDist {
+    pub fn new(probs: Vec<f64>) -> FragmentLengthDist {
+        FragmentLengthDist { probs }
+    }
+
+    /// The most probable fragment length (the smallest, on ties).
+    pub fn mode(&self) -> usize {
+        let mut best = 0;
+        for (l, &p) in self.probs.iter().enumerate() {
+            if p > self.probs[best] {
+                best = l;
+            }
+        }
+        best
+    }
+
+    /// The mean fragment length.
+    pub fn mean(&self) -> f64 {
+        self.conditional_mean(self.probs.len())
+    }
+
+    /// The mean of the fragment lengths no longer than `max_len`, or 0 if
+    /// none of them has any mass.
+    fn conditional_mean(&self, max_len: usize) -> f64 {
+        let upto = (max_len + 1).min(self.probs.len());
+        let (mut mass, mut total) = (0.0, 0.0);
+        for (l, &p) in self.probs[..upto].iter().enumerate() {
+            mass += p;
+            total += l as f64 * p;
+        }
+        if mass > 0.0 {
+            total / mass
+        } else {
+            0.0
+        }
+    }
+
+    /// Salmon-style effective length: the transcript length minus the mean
+    /// of the fragments that fit in it. Transcripts shorter than the mode
+    /// can end up with little or no room, so the result is clamped to at
+    /// least [`MIN_EFFECTIVE_LENGTH`].
+    pub fn effective_length(&self, len: u32) -> f64 {
+        let efflen = len as f64 - self.conditional_mean(len as usize);
+        efflen.max(MIN_EFFECTIVE_LENGTH)
+    }
+}
+
+/// Recompute every record's effective length from `fld`, then recompute TPM
+/// from the existing read counts and the new effective lengths.
+pub fn recompute_efflen(records: &mut [QuantRecord], fld: &FragmentLengthDist) {
+    for r in records.iter_mut() {
+        r.efflen = fld.effective_length(r.len);
+    }
+    let tpm = tpm_from_rates(&coverage(records));
+    for (r, tpm) in records.iter_mut().zip(tpm) {
+        r.tpm = tpm;
+    }
+}
+
+impl SalmonFiles {
+    /// The observed fragment-length distribution, from `aux_info/fld.gz`.
+    /// Salmon writes a histogram of sampled lengths as little-endian 32-bit
+    /// counts; these are normalized so that entry `l` is the probability of
+    /// a fragment of length `l`. A run without the file (single-end
+    /// libraries may lack it) fails with [`SeineError::NotAvailable`].
+    pub fn try_read_fragment_length_dist(&self) -> Result<Vec<f64>, SeineError> {
+        let raw = read_aux_gz(&self.fld_file)?;
+        if raw.len() % 4 != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "fld.gz holds {} bytes, not a whole number of counts",
+                    raw.len()
+                ),
+            )
+            .into());
+        }
+        let counts: Vec<f64> = raw
+            .chunks_exact(4)
+            .map(|c| i32::from_le_bytes([c[0], c[1], c[2], c[3]]).max(0) as f64)
+            .collect();
+        let total: f64 = counts.iter().sum();
+        Ok(counts
+            .into_iter()
+            .map(|c| if total > 0.0 { c / total } else { 0.0 })
+            .collect())
+    }
+
+    /// [`try_read_fragment_length_dist`](Self::try_read_fragment_length_dist)
+    /// as an `io::Error`; a missing file is `NotFound` wrapping
+    /// [`SeineError::NotAvailable`].
+    pub fn read_fragment_length_dist(&self) -> Result<Vec<f64>, io::Error> {
+        Ok(self.try_read_fragment_length_dist()?)
+    }
+
+    /// [`read_fragment_length_dist`](Self::read_fragment_length_dist) as a
+    /// [`FragmentLengthDist`], for its mean and mode.
+    pub fn fragment_length_dist(&self) -> Result<FragmentLengthDist, io::Error> {
+        Ok(FragmentLengthDist::new(self.read_fragment_length_dist()?))
+    }
+}
+
+/// The decompressed contents of an optional gzipped file in `aux_info`. A
+/// missing file is reported as [`SeineError::NotAvailable`].
+fn read_aux_gz(path: &Path) -> Result<Vec<u8>, SeineError> {
+    let file = File::open(path).map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => SeineError::NotAvailable(path.to_path_buf()),
+        _ => SeineError::Io(e),
+    })?;
+    let mut raw = Vec::new();
+    decompress_if_gzipped(file)?.read_to_end(&mut raw)?;
+    Ok(raw)
+}
+
+/*******************************************************************************/
+/*                         Bias Models                                         */
+/*******************************************************************************/
+
+/// The bias models salmon writes to `aux_info` when run with `--gcBias`
+/// (and `--seqBias`, `--posBias`).
+#[derive(Debug, Clone)]
+pub struct BiasModels {
+    pub obs_gc_file: PathBuf,
+    pub exp_gc_file: PathBuf,
+}
+
+/// Observed and expected fragment GC content, as parallel per-bin weights
+/// (bin `i` covers GC fraction `i / (nbins - 1)`), each normalized to sum
+/// to 1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GcBias {
+    pub observed: Vec<f64>,
+    pub expected: Vec<f64>,
+}
+
+impl GcBias {
+    pub fn num_bins(&self) -> usize {
+        self.observed.len()
+    }
+
+    /// Observed over expected weight per bin: above 1 where fragments of
+    /// that GC content are over-represented. NaN for bins with no expected
+    /// weight.
+    pub fn ratio(&self) -> Vec<f64> {
+        self.observed
+            .iter()
+            .zip(&self.expected)
+            .map(|(o, e)| if *e > 0.0 { o / e } else { f64::NAN })
+            .collect()
+    }
+}
+
+impl BiasModels {
+    /// The bias models in the aux directory `aux_dir`.
+    pub fn new<P: AsRef<Path>>(aux_dir: P) -> BiasModels {
+        let aux_dir = aux_dir.as_ref();
+        BiasModels {
+            obs_gc_file: aux_dir.join("obs_gc.gz"),
+            exp_gc_file: aux_dir.join("exp_gc.gz"),
+        }
+    }
+
+    /// Read `obs_gc.gz` and `exp_gc.gz`. The GC model is a matrix of
+    /// weights with one row per conditioning context and one column per GC
+    /// bin; rows are summed into a single distribution. A run without
+    /// `--gcBias` fails with [`SeineError::NotAvailable`].
+    pub fn try_read_gc_bias(&self) -> Result<GcBias, SeineError> {
+        let observed = read_gc_model(&self.obs_gc_file)?;
+        let expected = read_gc_model(&self.exp_gc_file)?;
+        if observed.len() != expected.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "observed GC model has {} bins but the expected one has {}",
+                    observed.len(),
+                    expected.len()
+                ),
+            )
+            .into());
+        }
+        Ok(GcBias { observed, expected })
+    }
+
+    /// [`try_read_gc_bias`](Self::try_read_gc_bias) as an `io::Error`; a
+    /// missing model is `NotFound` wrapping [`SeineError::NotAvailable`].
+    pub fn read_gc_bias(&self) -> Result<GcBias, io::Error> {
+        Ok(self.try_read_gc_bias()?)
+    }
+}
+
+/// Decode one of salmon's binary GC models: an i32 flag (0 if weights are
+/// stored as logs), the i64 row and column counts, then the weights as
+/// column-major doubles. Anything after the matrix (the per-row totals) is
+/// ignored. Returns the per-bin sums of the rows, normalized to 1. A header
+/// naming no rows or a matrix larger than the file is `InvalidData`.
+fn read_gc_model(path: &Path) -> Result<Vec<f64>, SeineError> {
+    let raw = read_aux_gz(path)?;
+    let invalid = |msg: String| SeineError::Io(io::Error::new(io::ErrorKind::InvalidData, msg));
+    let word = |at: usize| -> [u8; 8] {
+        let mut b = [0u8; 8];
+        b.copy_from_slice(&raw[at..at + 8]);
+        b
+    };
+    if raw.len() < 20 {
+        return Err(invalid(format!(
+            "{} is too short for a GC model",
+            path.display()
+        )));
+    }
+    let log_space = i32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]) == 0;
+    let (rows, cols) = (i64::from_le_bytes(word(4)), i64::from_le_bytes(word(12)));
+    // the matrix must fit in the file, which also bounds `cols` once
+    // there is at least one row
+    let fits = |r: usize, c: usize| {
+        r.checked_mul(c)
+            .and_then(|n| n.checked_mul(8))
+            .and_then(|n| n.checked_add(20))
+            .is_some_and(|n| n <= raw.len())
+    };
+    let (rows, cols) = match (usize::try_from(rows), usize::try_from(cols)) {
+        (Ok(r), Ok(c)) if r > 0 && fits(r, c) => (r, c),
+        _ => {
+            return Err(invalid(format!(
+                "{} declares a {} x {} GC model but holds {} bytes",
+                path.display(),
+                rows,
+                cols,
+                raw.len()
+            )))
+        }
+    };
+    let mut bins = vec![0.0; cols];
+    for (i, bin) in bins.iter_mut().enumerate() {
+        for r in 0..rows {
+            let w = f64::from_le_bytes(word(20 + (i * rows + r) * 8));
+            *bin += if log_space { w.exp() } else { w };
+        }
+    }
+    let total: f64 = bins.iter().sum();
+    if total > 0.0 {
+        bins.iter_mut().for_each(|b| *b /= total);
+    }
+    Ok(bins)
+}
+
+impl SalmonFiles {
+    /// The bias models in this run's aux directory.
+    pub fn bias_models(&self) -> BiasModels {
+        BiasModels::new(&self.aux_dir)
+    }
+}
+
+/*******************************************************************************/
+/*                         Transcript to Gene                                  */
+/*******************************************************************************/
+
+/// A transcript-to-gene mapping. Genes are numbered in the order they are
+/// first seen.
+#[derive(Debug, Clone, Default)]
+pub struct Tx2Gene {
+    pub genes: Vec<String>,
+    gene_ids: HashMap<String, usize>,
+    gene_of: HashMap<String, usize>,
+}
+
+impl Tx2Gene {
+    pub fn new() -> Tx2Gene {
+        Tx2Gene::default()
+    }
+
+    /// Read a headerless, two-column tab-separated `transcript\tgene` file.
+    pub fn from_path<P: AsRef<Path>>(p: P) -> Result<Tx2Gene, csv::Error> {
+        let file = File::open(p)?;
+        let mut rdr = csv::ReaderBuilder::new()
+            .delimiter(b'\t')
+            .has_headers(false)
+            .from_reader(file);
+
+        let mut t2g = Tx2Gene::new();
+        for record in rdr.deserialize() {
+            let (tx, gene): (String, String) = record?;
+            t2g.insert(tx, gene);
+        }
+        Ok(t2g)
+    }
+
+    /// Map `tx` to `gene`, replacing any previous gene for `tx`.
+    pub fn insert(&mut self, tx: String, gene: String) {
+        let genes = &mut self.genes;
+        let idx = *self.gene_ids.entry(gene).or_insert_with_key(|g| {
+            genes.push(g.clone());
+            genes.len() - 1
+        });
+        self.gene_of.insert(tx, idx);
+    }
+
+    pub fn gene_index(&self, tx: &str) -> Option<usize> {
+        self.gene_of.get(tx).copied()
+    }
+
+    pub fn gene(&self, tx: &str) -> Option<&str> {
+        self.gene_index(tx).map(|i| self.genes[i].as_str())
+    }
+
+    pub fn num_genes(&self) -> usize {
+        self.genes.len()
+    }
+}
+
+impl<S: Into<String>> std::iter::FromIterator<(S, S)> for Tx2Gene {
+    fn from_iter<I: IntoIterator<Item = (S, S)>>(iter: I) -> Self {
+        let mut t2g = Tx2Gene::new();
+        for (tx, gene) in iter {
+            t2g.insert(tx.into(), gene.into());
+        }
+        t2g
+    }
+}
+
+/// Per-gene effective length: the mean of its isoforms' effective lengths
+/// weighted by their read counts, or the plain mean when the gene has no
+/// reads. This is the length offset tximport hands to edgeR/limma-voom.
+/// Transcripts missing from `t2g` are skipped.
+pub fn gene_effective_lengths(records: &[QuantRecord], t2g: &Tx2Gene) -> HashMap<String, f64> {
+    // gene -> (sum reads*efflen, sum reads, sum efflen, n isoforms)
+    let mut acc = HashMap::<&str, (f64, f64, f64, usize)>::new();
+    for r in records {
+        if let Some(gene) = t2g.gene(&r.name) {
+            let a = acc.entry(gene).or_insert((0.0, 0.0, 0.0, 0));
+            a.0 += r.num_reads * r.efflen;
+            a.1 += r.num_reads;
+            a.2 += r.efflen;
+            a.3 += 1;
+        }
+    }
+    acc.into_iter()
+        .map(|(gene, (weighted, reads, efflen, n))| {
+            let len = if reads > 0.0 {
+                weighted / reads
+            } else {
+                efflen / n as f64
+            };
+            (gene.to_string(), len)
+        })
+        .collect()
+}
+
+/// Shannon entropy (in bits) of the gene-level TPM distribution: TPM is
+/// summed per gene, normalized to proportions `p`, and `-Σ p log2 p` is
+/// taken over genes with `p > 0`. Transcripts missing from `t2g` are
+/// skipped; 0 if no mapped transcript has any TPM.
+pub fn gene_expression_entropy(records: &[QuantRecord], t2g: &Tx2Gene) -> f64 {
+    let mut gene_tpm = vec![0.0_f64; t2g.num_genes()];
+    for r in records {
+        if let Some(g) = t2g.gene_index(&r.name) {
+            gene_tpm[g] += r.tpm;
+        }
+    }
+    let total: f64 = gene_tpm.iter().sum();
+    if total <= 0.0 {
+        return 0.0;
+    }
+    gene_tpm
+        .iter()
+        .filter(|&&t| t > 0.0)
+        .map(|&t| {
+            let p = t / total;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+/*******************************************************************************/
+/*                         Transcript Groups                                   */
+/*******************************************************************************/
+
+/// How the transcripts in a groups file were identified.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GroupIds {
+    /// Lines hold numeric target indices.
+    Indices,
+    /// Lines hold transcript names; ids index this list, in order of first
+    /// appearance.
+    Names(Vec<String>),
+}
+
+/// Collapsed transcript groups from a grouped-salmon/terminus `groups.txt`,
+/// where each line is a retained representative followed by the
+/// transcripts collapsed into it (separated by tabs, spaces or commas).
+#[derive(Debug, Clone, PartialEq)]
+pub struct TranscriptGroups {
+    pub ids: GroupIds,
+    members: HashMap<usize, Vec<usize>>,
+    representative: HashMap<usize, usize>,
+    /// Name to id for a name-based file, backing `id_of`.
+    name_index: HashMap<String, usize>,
+}
+
+impl TranscriptGroups {
+    /// Read a groups file. Lines are treated as indices if every token is
+    /// numeric and as names otherwise; an empty file gives no groups.
+    pub fn from_path<P: AsRef<Path>>(p: P) -> Result<TranscriptGroups, io::Error> {
+        let mut text = String::new();
+        decompress_if_gzipped(File::open(p)?)?.read_to_string(&mut text)?;
+        let lines: Vec<Vec<&str>> = text
+            .lines()
+            .map(|l| {
+                l.split(|c: char| c == ',' || c.is_whitespace())
+                    .filter(|t| !t.is_empty())
+                    .collect::<Vec<&str>>()
+            })
+            .filter(|toks| !toks.is_empty())
+            .collect();
+
+        let numeric = lines.iter().flatten().all(|t| t.parse::<usize>().is_ok());
+        let mut names = Vec::<String>::new();
+        let mut name_ids = HashMap::<&str, usize>::new();
+        let mut groups = TranscriptGroups {
+            ids: GroupIds::Indices,
+            members: HashMap::new(),
+            representative: HashMap::new(),
+            name_index: HashMap::new(),
+        };
+        for toks in &lines {
+            let ids: Vec<usize> = toks
+                .iter()
+                .map(|&t| {
+                    if numeric {
+                        t.parse().unwrap()
+                    } else {
+                        *name_ids.entry(t).or_insert_with(|| {
+                            names.push(t.to_string());
+                            names.len() - 1
+                        })
+                    }
+                })
+                .collect();
+            let rep = ids[0];
+            for &m in &ids[1..] {
+                groups.representative.insert(m, rep);
+            }
+            groups.members.entry(rep).or_default().extend(&ids[1..]);
+        }
+        if !numeric {
+            groups.name_index = name_ids
+                .into_iter()
+                .map(|(name, id)| (name.to_string(), id))
+                .collect();
+            groups.ids = GroupIds::Names(names);
+        }
+        Ok(groups)
+    }
+
+    /// The number of groups.
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    /// The transcripts collapsed into `representative` (not including it),
+    /// empty if it does not head a group.
+    pub fn members(&self, representative: usize) -> &[usize] {
+        self.members
+            .get(&representative)
+            .map_or(&[], |m| m.as_slice())
+    }
+
+    /// The representative transcript `t` was collapsed into; a
+    /// representative is its own. `None` for ungrouped transcripts.
+    pub fn representative_of(&self, t: usize) -> Option<usize> {
+        if self.members.contains_key(&t) {
+            Some(t)
+        } else {
+            self.representative.get(&t).copied()
+        }
+    }
+
+    /// The id of transcript `name` in a name-based file.
+    pub fn id_of(&self, name: &str) -> Option<usize> {
+        self.name_index.get(name).copied()
+    }
+}
+
+/// The total `num_reads` of each group in a name-based groups file, keyed by
+/// representative: its own reads plus those of every transcript collapsed
+/// into it. Transcripts missing from `quant` contribute nothing. Index-based
+/// groups cannot be matched to names and fail with
+/// [`SeineError::IndexedGroups`].
+pub fn collapsed_read_mass(
+    quant: &HashMap<String, QuantEntry>,
+    groups: &TranscriptGroups,
+) -> Result<HashMap<String, f64>, SeineError> {
+    let names = match &groups.ids {
+        GroupIds::Names(names) => names,
+        GroupIds::Indices => return Err(SeineError::IndexedGroups),
+    };
+    let reads = |id: usize| quant.get(&names[id]).map_or(0.0, |q| q.num_reads);
+    Ok(groups
+        .members
+        .iter()
+        .map(|(&rep, members)| {
+            let mass = reads(rep) + members.iter().map(|&m| reads(m)).sum::<f64>();
+            (names[rep].clone(), mass)
+        })
+        .collect())
+}
+
+/// The non-comment lines of a collapse trace, as `(line number, tokens)`.
+/// Blank lines and lines starting with `#` are skipped; tokens are
+/// separated by tabs, spaces or commas as in `groups.txt`.
+fn log_records(text: &str) -> impl Iterator<Item = (usize, Vec<&str>)> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, l)| !l.trim_start().starts_with('#'))
+        .map(|(i, l)| {
+            let toks: Vec<&str> = l
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|t| !t.is_empty())
+                .collect();
+            (i + 1, toks)
+        })
+        .filter(|(_, toks)| !toks.is_empty())
+}
+
+fn read_log_text(p: &Path) -> Result<String, io::Error> {
+    let mut text = String::new();
+    decompress_if_gzipped(File::open(p)?)?.read_to_string(&mut text)?;
+    Ok(text)
+}
+
+/// One collapse from `collapsed.log`: the group a set of transcripts was
+/// merged into.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CollapseRecord {
+    pub group: String,
+    pub members: Vec<String>,
+}
+
+/// The collapse trace grouped salmon / terminus writes to `collapsed.log`:
+/// one line per collapse, the group id followed by its member ids.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CollapseLog {
+    pub records: Vec<CollapseRecord>,
+}
+
+impl CollapseLog {
+    pub fn from_path<P: AsRef<Path>>(p: P) -> Result<CollapseLog, io::Error> {
+        let text = read_log_text(p.as_ref())?;
+        let records = log_records(&text)
+            .map(|(_, toks)| CollapseRecord {
+                group: toks[0].to_string(),
+                members: toks[1..].iter().map(|t| t.to_string()).collect(),
+            })
+            .collect();
+        Ok(CollapseLog { records })
+    }
+}
+
+/// One line of `delta.log`: the ids it concerns and the delta (the change
+/// in the collapse objective, compared against the threshold) recorded for
+/// them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeltaRecord {
+    pub ids: Vec<String>,
+    pub delta: f64,
+}
+
+/// The per-collapse deltas grouped salmon / terminus writes to `delta.log`:
+/// one line per record, the ids followed by the numeric delta as the last
+/// token.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DeltaLog {
+    pub records: Vec<DeltaRecord>,
+}
+
+impl DeltaLog {
+    /// Read a delta log. A record whose last token is not a number is an
+    /// `InvalidData` error naming its line.
+    pub fn from_path<P: AsRef<Path>>(p: P) -> Result<DeltaLog, io::Error> {
+        let text = read_log_text(p.as_ref())?;
+        let mut records = Vec::new();
+        for (line, toks) in log_records(&text) {
+            let (last, ids) = toks.split_last().unwrap();
+            let delta = last.parse().map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "delta.log line {}: expected a delta, found `{}`",
+                        line, last
+                    ),
+                )
+            })?;
+            records.push(DeltaRecord {
+                ids: ids.iter().map(|t| t.to_string()).collect(),
+                delta,
+            });
+        }
+        Ok(DeltaLog { records })
+    }
+}
+
+impl SalmonFiles {
+    pub fn read_collapse_log(&self) -> Result<CollapseLog, SeineError> {
+        if !self.collapsed_log_file.exists() {
+            return Err(SeineError::MissingFile(self.collapsed_log_file.clone()));
+        }
+        Ok(CollapseLog::from_path(&self.collapsed_log_file)?)
+    }
+
+    pub fn read_delta_log(&self) -> Result<DeltaLog, SeineError> {
+        if !self.delta_file.exists() {
+            return Err(SeineError::MissingFile(self.delta_file.clone()));
+        }
+        Ok(DeltaLog::from_path(&self.delta_file)?)
+    }
+}
+
+/*******************************************************************************/
+/*                         Bootstraps                                          */
+/*******************************************************************************/
+
+/// Inferential replicates (bootstraps or Gibbs samples) for a run, stored as
+/// a row-major `num_bootstraps x num_targets` matrix: each row is one
+/// replicate, each column one target in quant.sf order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bootstraps {
+    /// Target names labelling the columns, empty if they were not loaded.
+    pub names: Vec<String>,
+    pub nboot: usize,
+    pub ntarget: usize,
+    pub values: Vec<f64>,
+}
+
+impl Bootstraps {
+    pub fn new(nboot: usize, ntarget: usize, values: Vec<f64>) -> Bootstraps {
+        assert_eq!(
+            values.len(),
+            nboot * ntarget,
+            "bootstrap values do not match the matrix dimensions"
+        );
+        Bootstraps {
+            names: Vec::new(),
+            nboot,
+            ntarget,
+            values,
+        }
+    }
+
+    /// Read the inferential replicates of a salmon run from
+    /// `aux_info/bootstrap/bootstraps.gz`, sized by `num_bootstraps` and
+    /// `num_valid_targets` from meta_info.json. Values are little-endian
+    /// doubles, except for Gibbs samples from salmon before 0.8, which were
+    /// written as 32-bit integers. Target names are taken from `names.tsv.gz`
+    /// when present.
+    ///
+    /// A run that records no replicates fails with
+    /// [`SeineError::NoInferentialReplicates`], without opening the
+    /// bootstrap files; one that records replicates but lacks bootstraps.gz
+    /// fails with [`SeineError::MissingFile`].
+    pub fn try_from_salmon_files(files: &SalmonFiles) -> Result<Bootstraps, SeineError> {
+        let invalid = |msg: String| SeineError::Io(io::Error::new(io::ErrorKind::InvalidData, msg));
+        let mi = files.read_meta_info()?;
+        if !mi.has_inferential_replicates() {
+            return Err(SeineError::NoInferentialReplicates(files.prefix.clone()));
+        }
+        if !files.bootstrap_file.exists() {
+            return Err(SeineError::MissingFile(files.bootstrap_file.clone()));
+        }
+        let nboot = mi.num_bootstraps as usize;
+        let ntarget = mi.num_valid_targets as usize;
+
+        let mut raw = Vec::new();
+        GzDecoder::new(File::open(&files.bootstrap_file)?).read_to_end(&mut raw)?;
+        let width = if mi.writes_integer_samples() { 4 } else { 8 };
+        let expected = nboot
+            .checked_mul(ntarget)
+            .and_then(|n| n.checked_mul(width));
+        if expected != Some(raw.len()) {
+            return Err(invalid(format!(
+                "bootstraps.gz holds {} bytes, expected {} replicates x {} targets x {} bytes",
+                raw.len(),
+                nboot,
+                ntarget,
+                width
+            )));
+        }
+        let values = if width == 4 {
+            raw.chunks_exact(4)
+                .map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]) as f64)
+                .collect()
+        } else {
+            raw.chunks_exact(8)
+                .map(|c| {
+                    let mut b = [0u8; 8];
+                    b.copy_from_slice(c);
+                    f64::from_le_bytes(b)
+                })
+                .collect()
+        };
+
+        let mut boot = Bootstraps::new(nboot, ntarget, values);
+        let names = files.read_bootstrap_names()?;
+        if !names.is_empty() {
+            if names.len() != ntarget {
+                return Err(invalid(format!(
+                    "names.tsv.gz lists {} targets, expected {}",
+                    names.len(),
+                    ntarget
+                )));
+            }
+            boot.names = names;
+        }
+        Ok(boot)
+    }
+
+    /// [`try_from_salmon_files`](Self::try_from_salmon_files) as an
+    /// `io::Error`: a run without replicates or without bootstraps.gz is
+    /// `NotFound` wrapping the [`SeineError`], bad data `InvalidData`.
+    pub fn from_salmon_files(files: &SalmonFiles) -> Result<Bootstraps, io::Error> {
+        Ok(Bootstraps::try_from_salmon_files(files)?)
+    }
+
+    pub fn num_bootstraps(&self) -> usize {
+        self.nboot
+    }
+
+    pub fn num_targets(&self) -> usize {
+        self.ntarget
+    }
+
+    /// The values of replicate `b`, one per target.
+    pub fn replicate(&self, b: usize) -> &[f64] {
+        &self.values[b * self.ntarget..(b + 1) * self.ntarget]
+    }
+
+    /// The value of target `t` in each replicate, in replicate order.
+    pub fn target_values(&self, t: usize) -> impl Iterator<Item = f64> + '_ {
+        assert!(t < self.ntarget, "target index out of range");
+        self.values.iter().skip(t).step_by(self.ntarget).copied()
+    }
+
+    /// The mean of each target across replicates.
+    pub fn means(&self) -> Vec<f64> {
+        let mut sums = vec![0.0_f64; self.ntarget];
+        for rep in self.values.chunks(self.ntarget.max(1)) {
+            for (s, v) in sums.iter_mut().zip(rep) {
+                *s += v;
+            }
+        }
+        sums.iter().map(|s| s / self.nboot as f64).collect()
+    }
+
+    /// For each target, the fraction of replicates in which its value is
+    /// exactly zero. A high fraction marks an uncertain detection. Every
+    /// fraction is 0 when there are no replicates.
+    pub fn zero_fraction(&self) -> Vec<f64> {
+        let mut zeros = vec![0_usize; self.ntarget];
+        for rep in self.values.chunks(self.ntarget.max(1)) {
+            for (z, &v) in zeros.iter_mut().zip(rep) {
+                if v == 0.0 {
+                    *z += 1;
+                }
+            }
+        }
+        zeros
+            .into_iter()
+            .map(|z| {
+                if self.nboot == 0 {
+                    0.0
+                } else {
+                    z as f64 / self.nboot as f64
+                }
+            })
+            .collect()
+    }
+
+    /// Per-target mean and sample variance of the targets in `cols`. Each
+    /// replicate's values for those targets are contiguous, so this reads
+    /// one slice per replicate, and sums run in replicate order as in
+    /// [`means`](Self::means).
+    fn summary_of(&self, cols: std::ops::Range<usize>) -> Vec<BootstrapStat> {
+        let width = cols.len();
+        let rows = || {
+            self.values
+                .chunks(self.ntarget.max(1))
+                .map(|rep| &rep[cols.clone()])
+        };
+        let mut sums = vec![0.0_f64; width];
+        for rep in rows() {
+            for (s, v) in sums.iter_mut().zip(rep) {
+                *s += v;
+            }
+        }
+        let means: Vec<f64> = sums.iter().map(|s| s / self.nboot as f64).collect();
+        let mut ss = vec![0.0_f64; width];
+        for rep in rows() {
+            for ((s, v), m) in ss.iter_mut().zip(rep).zip(&means) {
+                *s += (v - m) * (v - m);
+            }
+        }
+        means
+            .into_iter()
+            .zip(ss)
+            .map(|(mean, s)| BootstrapStat {
+                mean,
+                variance: sample_variance(s, self.nboot),
+            })
+            .collect()
+    }
+
+    /// Per-target mean, variance and inferential relative variance, using
+    /// the fishpond/swish convention
+    /// `InfRV = max(var - mean, 0) / (mean + 5) + 0.01`. Targets with zero
+    /// mean get an InfRV of 0. The mean and variance are computed from the
+    /// full matrix; see [`BootstrapAccumulator`] for a streaming equivalent.
+    pub fn summarize(&self) -> Vec<BootstrapSummary> {
+        self.summary_of(0..self.ntarget)
+            .into_iter()
+            .map(BootstrapSummary::from_stat)
+            .collect()
+    }
+
+    /// [`summarize`](Self::summarize) on several threads, each taking a
+    /// contiguous block of targets. The values are replicate-major, so
+    /// each thread reads its block's slice of every replicate (a strided
+    /// walk) instead of working on a transposed copy: that saves copying
+    /// the whole matrix, at the cost of touching every replicate from every
+    /// thread. Each target's sums run in the same order as the serial path,
+    /// so the result is bit-identical to `summarize`.
+    pub fn summarize_parallel(&self) -> Vec<BootstrapSummary> {
+        let nthreads = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let chunk = self.ntarget.div_ceil(nthreads).max(1);
+        let blocks: Vec<Vec<BootstrapStat>> = std::thread::scope(|s| {
+            let workers: Vec<_> = (0..self.ntarget)
+                .step_by(chunk)
+                .map(|lo| {
+                    let hi = (lo + chunk).min(self.ntarget);
+                    s.spawn(move || self.summary_of(lo..hi))
+                })
+                .collect();
+            workers.into_iter().map(|w| w.join().unwrap()).collect()
+        });
+        blocks
+            .into_iter()
+            .flatten()
+            .map(BootstrapSummary::from_stat)
+            .collect()
+    }
+
+    /// For each target, the quantiles of its replicates at `probs` (each in
+    /// `[0, 1]`), linearly interpolating between order statistics. Empty
+    /// replicates give NaN.
+    pub fn quantiles(&self, probs: &[f64]) -> Vec<Vec<f64>> {
+        (0..self.ntarget)
+            .map(|t| {
+                let mut v: Vec<f64> = self.target_values(t).collect();
+                v.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+                probs
+                    .iter()
+                    .map(|&p| {
+                        if v.is_empty() {
+                            return f64::NAN;
+                        }
+                        let h = p.clamp(0.0, 1.0) * (v.len() - 1) as f64;
+                        let lo = h.floor() as usize;
+                        let hi = (lo + 1).min(v.len() - 1);
+                        v[lo] + (h - lo as f64) * (v[hi] - v[lo])
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+/// Mean and variance of one target's inferential replicates, as returned by
+/// [`BootstrapAccumulator::finish`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BootstrapStat {
+    pub mean: f64,
+    /// Sample variance (`n - 1` denominator); 0 with fewer than two
+    /// replicates.
+    pub variance: f64,
+}
+
+/// Per-target summary returned by [`Bootstraps::summarize`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BootstrapSummary {
+    pub mean: f64,
+    pub variance: f64,
+    /// Inferential relative variance.
+    pub infrv: f64,
+}
+
+impl BootstrapSummary {
+    fn from_stat(st: BootstrapStat) -> BootstrapSummary {
+        let infrv = if st.mean == 0.0 {
+            0.0
+        } else {
+            (st.variance - st.mean).max(0.0) / (st.mean + 5.0) + 0.01
+        };
+        BootstrapSummary {
+            mean: st.mean,
+            variance: st.variance,
+            infrv,
+        }
+    }
+}
+
+fn sample_variance(sum_sq: f64, n: usize) -> f64 {
+    if n < 2 {
+        0.0
+    } else {
+        sum_sq / (n - 1) as f64
+    }
+}
+
+/// Streaming per-target mean and variance (Welford's algorithm), for
+/// reading bootstraps replicate by replicate without holding the whole
+/// matrix.
+#[derive(Debug, Clone)]
+pub struct BootstrapAccumulator {
+    n: usize,
+    mean: Vec<f64>,
+    m2: Vec<f64>,
+}
+
+impl BootstrapAccumulator {
+    pub fn new(ntarget: usize) -> BootstrapAccumulator {
+        BootstrapAccumulator {
+            n: 0,
+            mean: vec![0.0; ntarget],
+            m2: vec![0.0; ntarget],
+        }
+    }
+
+    /// Fold in one replicate, which must hold a value for every target.
+    pub fn push_replicate(&mut self, rep: &[f64]) {
+        assert_eq!(
+            rep.len(),
+            self.mean.len(),
+            "replicate length does not match the number of targets"
+        );
+        self.n += 1;
+        let n = self.n as f64;
+        for ((m, m2), &x) in self.mean.iter_mut().zip(self.m2.iter_mut()).zip(rep) {
+            let delta = x - *m;
+            *m += delta / n;
+            *m2 += delta * (x - *m);
+        }
+    }
+
+    pub fn finish(self) -> Vec<BootstrapStat> {
+        let n = self.n;
+        self.mean
+            .into_iter()
+            .zip(self.m2)
+            .map(|(mean, m2)| BootstrapStat {
+                mean,
+                variance: sample_variance(m2, n),
+            })
+            .collect()
+    }
+}
+
+/// The Pearson correlation matrix (`samples x samples`, as rows) of one
+/// target's replicate vectors across samples, as used when comparing
+/// inferential replicates swish-style. All samples need the same number of
+/// replicates, and `target_idx` must be a target of every sample
+/// ([`SeineError::TargetOutOfRange`] otherwise); a constant replicate
+/// vector correlates as NaN.
+pub fn cross_sample_irv(
+    boots: &[Bootstraps],
+    target_idx: usize,
+) -> Result<Vec<Vec<f64>>, SeineError> {
+    if let Some(b) = boots.iter().find(|b| target_idx >= b.ntarget) {
+        return Err(SeineError::TargetOutOfRange {
+            index: target_idx,
+            ntarget: b.ntarget,
+        });
+    }
+    if let Some(first) = boots.first() {
+        if let Some(b) = boots.iter().find(|b| b.nboot != first.nboot) {
+            return Err(SeineError::LayoutMismatch {
+                what: "replicates",
+                expected: first.nboot,
+                found: b.nboot,
+            });
+        }
+    }
+    let vectors: Vec<Vec<f64>> = boots
+        .iter()
+        .map(|b| b.target_values(target_idx).collect())
+        .collect();
+    Ok(vectors
+        .iter()
+        .map(|x| vectors.iter().map(|y| stats::pearson(x, y)).collect())
+        .collect())
+}
+
+/// Inferential replicates of several runs stacked into a row-major
+/// `samples x replicates x targets` tensor.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BootstrapTensor {
+    /// Target names shared by every run, empty if none were recorded.
+    pub names: Vec<String>,
+    pub nsample: usize,
+    pub nboot: usize,
+    pub ntarget: usize,
+    pub values: Vec<f64>,
+}
+
+impl BootstrapTensor {
+    pub fn get(&self, sample: usize, boot: usize, target: usize) -> f64 {
+        self.values[(sample * self.nboot + boot) * self.ntarget + target]
+    }
+
+    /// The `replicates x targets` block of one sample, row-major.
+    pub fn sample(&self, sample: usize) -> &[f64] {
+        let n = self.nboot * self.ntarget;
+        &self.values[sample * n..(sample + 1) * n]
+    }
+}
+
+/// Load the inferential replicates of the salmon runs in `dirs` and stack
+/// them in order. Every run must have the same number of replicates and
+/// targets, and, where names were recorded, the same targets in the same
+/// order.
+pub fn load_bootstrap_tensor(dirs: &[PathBuf]) -> Result<BootstrapTensor, SeineError> {
+    let mut tensor = BootstrapTensor {
+        names: Vec::new(),
+        nsample: 0,
+        nboot: 0,
+        ntarget: 0,
+        values: Vec::new(),
+    };
+    for dir in dirs {
+        let boot = Bootstraps::try_from_salmon_files(&SalmonFiles::try_new(dir)?)?;
+        if tensor.nsample == 0 {
+            tensor.nboot = boot.nboot;
+            tensor.ntarget = boot.ntarget;
+            tensor.names = boot.names;
+        } else {
+            if boot.nboot != tensor.nboot {
+                return Err(SeineError::LayoutMismatch {
+                    what: "replicates",
+                    expected: tensor.nboot,
+                    found: boot.nboot,
+                });
+            }
+            if boot.ntarget != tensor.ntarget {
+                return Err(SeineError::TargetCountMismatch {
+                    expected: tensor.ntarget,
+                    found: boot.ntarget,
+                });
+            }
+            if !tensor.names.is_empty() && !boot.names.is_empty() {
+                check_same_targets(&tensor.names, &boot.names)?;
+            }
+        }
+        tensor.values.extend(boot.values);
+        tensor.nsample += 1;
+    }
+    Ok(tensor)
+}
+
+/// Pearson correlation between each target's bootstrap mean and its point
+/// estimate (e.g. quant.sf `NumReads`). Values near 1 mean the replicates
+/// track the point estimates; much lower values flag a problem.
+pub fn bootstrap_point_agreement(boot: &Bootstraps, point_counts: &[f64]) -> f64 {
+    stats::pearson(&boot.means(), point_counts)
+}
+
+/// Log2 fold change of `target` between two runs, with a standard error
+/// taken from their inferential replicates. Replicates are paired by index;
+/// each pair gives `log2((a + 1) / (b + 1))`, using
+/// [`DEFAULT_PSEUDOCOUNT`] so zero counts stay finite. The result is the
+/// mean of those ratios and their sample standard deviation, which is the
+/// replicate-based standard error of the fold change. The SE is 0 with a
+/// single replicate. Panics if the runs have different numbers of
+/// replicates, or none.
+pub fn lfc_with_se(a_boot: &Bootstraps, b_boot: &Bootstraps, target: usize) -> (f64, f64) {
+    assert_eq!(
+        a_boot.nboot, b_boot.nboot,
+        "fold changes need the same number of replicates in both runs"
+    );
+    assert!(a_boot.nboot > 0, "fold changes need at least one replicate");
+    let ratios: Vec<f64> = a_boot
+        .target_values(target)
+        .zip(b_boot.target_values(target))
+        .map(|(a, b)| ((a + DEFAULT_PSEUDOCOUNT) / (b + DEFAULT_PSEUDOCOUNT)).log2())
+        .collect();
+    let lfc = stats::mean(&ratios);
+    let sum_sq: f64 = ratios.iter().map(|r| (r - lfc) * (r - lfc)).sum();
+    (lfc, sample_variance(sum_sq, ratios.len()).sqrt())
+}
+
+/// Gene-level inferential replicates, a row-major `genes x replicates`
+/// matrix.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeneBootstraps {
+    pub genes: Vec<String>,
+    pub nboot: usize,
+    pub values: Vec<f64>,
+}
+
+impl GeneBootstraps {
+    /// The replicate values of gene `g`.
+    pub fn gene_values(&self, g: usize) -> &[f64] {
+        &self.values[g * self.nboot..(g + 1) * self.nboot]
+    }
+}
+
+impl Bootstraps {
+    /// Sum the transcript columns of each gene within every replicate.
+    /// `quant_names` gives the transcript name of each column. Genes appear
+    /// in the order their first transcript does; transcripts missing from
+    /// `t2g` are dropped.
+    pub fn aggregate_to_gene(&self, t2g: &Tx2Gene, quant_names: &[String]) -> GeneBootstraps {
+        assert_eq!(
+            quant_names.len(),
+            self.ntarget,
+            "one transcript name is required per bootstrap column"
+        );
+        let mut gene_row = HashMap::<usize, usize>::new();
+        let mut genes = Vec::<String>::new();
+        let rows: Vec<Option<usize>> = quant_names
+            .iter()
+            .map(|tx| {
+                t2g.gene_index(tx).map(|g| {
+                    *gene_row.entry(g).or_insert_with(|| {
+                        genes.push(t2g.genes[g].clone());
+                        genes.len() - 1
+                    })
+                })
+            })
+            .collect();
+
+        let mut values = vec![0.0_f64; genes.len() * self.nboot];
+        for b in 0..self.nboot {
+            let rep = &self.values[b * self.ntarget..(b + 1) * self.ntarget];
+            for (t, row) in rows.iter().enumerate() {
+                if let Some(r) = row {
+                    values[r * self.nboot + b] += rep[t];
+                }
+            }
+        }
+        GeneBootstraps {
+            genes,
+            nboot: self.nboot,
+            values,
+        }
+    }
+}
+
+/// Gene TPM `(mean, sd)` across inferential replicates. Each replicate's
+/// counts are turned into transcript TPM using `efflen` (normalizing over
+/// all transcripts) and summed per gene before summarizing, so the spread
+/// reflects the replicates rather than an approximation. `names` labels the
+/// bootstrap columns; transcripts missing from `t2g` only contribute to the
+/// normalization. The sd uses `n - 1` and is 0 with fewer than two
+/// replicates.
+pub fn gene_tpm_with_uncertainty(
+    boots: &Bootstraps,
+    efflen: &[f64],
+    t2g: &Tx2Gene,
+    names: &[String],
+) -> HashMap<String, (f64, f64)> {
+    assert_eq!(
+        efflen.len(),
+        boots.ntarget,
+        "one effective length per target"
+    );
+    let rate = |count: f64, len: f64| if len > 0.0 { count / len } else { 0.0 };
+
+    let mut tpm_boot = boots.values.clone();
+    for rep in tpm_boot.chunks_mut(boots.ntarget.max(1)) {
+        let rates: Vec<f64> = rep
+            .iter()
+            .zip(efflen)
+            .map(|(&v, &len)| rate(v, len))
+            .collect();
+        rep.copy_from_slice(&tpm_from_rates(&rates));
+    }
+    let mut tpm = Bootstraps::new(boots.nboot, boots.ntarget, tpm_boot);
+    tpm.names = boots.names.clone();
+    let genes = tpm.aggregate_to_gene(t2g, names);
+
+    genes
+        .genes
+        .iter()
+        .enumerate()
+        .map(|(g, name)| {
+            let values = genes.gene_values(g);
+            let mean = stats::mean(values);
+            let ss: f64 = values.iter().map(|v| (v - mean) * (v - mean)).sum();
+            let sd = sample_variance(ss, values.len()).sqrt();
+            (name.clone(), (mean, sd))
+        })
+        .collect()
+}
+
+/// Check that the bootstrap columns line up with `quant`: the same number of
+/// targets and, when the bootstrap names are known, the same names in the
+/// same order. The first disagreement is reported.
+pub fn check_bootstrap_alignment(
+    boot: &Bootstraps,
+    quant: &[QuantRecord],
+) -> Result<(), SeineError> {
+    if boot.num_targets() != quant.len() {
+        return Err(SeineError::TargetCountMismatch {
+            expected: quant.len(),
+            found: boot.num_targets(),
+        });
+    }
+    for (index, (name, rec)) in boot.names.iter().zip(quant).enumerate() {
+        if *name != rec.name {
+            return Err(SeineError::TargetNameMismatch {
+                index,
+                expected: rec.name.clone(),
+                found: name.clone(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/*******************************************************************************/
+/*                         Count Matrices                                      */
+/*******************************************************************************/
+
+/// A dense feature-by-sample matrix of counts (rows are features such as
+/// transcripts or genes, columns are samples), stored row-major.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CountMatrix {
+    pub features: Vec<String>,
+    pub samples: Vec<String>,
+    pub data: Vec<f64>,
+}
+
+impl CountMatrix {
+    pub fn new(features: Vec<String>, samples: Vec<String>, data: Vec<f64>) -> CountMatrix {
+        assert_eq!(
+            data.len(),
+            features.len() * samples.len(),
+            "count matrix data does not match its dimensions"
+        );
+        CountMatrix {
+            features,
+            samples,
+            data,
+        }
+    }
+
+    pub fn nrows(&self) -> usize {
+        self.features.len()
+    }
+
+    pub fn ncols(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn get(&self, feature: usize, sample: usize) -> f64 {
+        self.data[feature * self.ncols() + sample]
+    }
+
+    pub fn row(&self, feature: usize) -> &[f64] {
+        let n = self.ncols();
+        &self.data[feature * n..(feature + 1) * n]
+    }
+
+    /// Detection calls, `true` where an entry is at least `min_reads`. The
+    /// result is row-major with the same layout as `data`.
+    pub fn to_presence_matrix(&self, min_reads: f64) -> Vec<bool> {
+        self.data.iter().map(|&x| x >= min_reads).collect()
+    }
+
+    /// For each feature, the fraction of samples in which it is detected,
+    /// i.e. has at least `min_reads` (the same call as
+    /// `to_presence_matrix`). With no samples every feature gets 0.
+    pub fn prevalence(&self, min_reads: f64) -> Vec<f64> {
+        let n = self.ncols();
+        (0..self.nrows())
+            .map(|f| {
+                if n == 0 {
+                    return 0.0;
+                }
+                let detected = self.row(f).iter().filter(|&&x| x >= min_reads).count();
+                detected as f64 / n as f64
+            })
+            .collect()
+    }
+
+    /// Replace every entry `x` with `log2(x + pseudocount)`; see
+    /// [`DEFAULT_PSEUDOCOUNT`].
+    pub fn log_transform(&mut self, pseudocount: f64) {
+        self.data
+            .iter_mut()
+            .for_each(|x| *x = (*x + pseudocount).log2());
+    }
+
+    /// Per-feature, per-sample `log2((count + 1) / (ref + 1))`, where `ref`
+    /// is the geometric mean of the feature across samples (0 if any sample
+    /// has a zero count). The pseudocount of 1 on both sides keeps zero
+    /// counts finite, so a feature absent everywhere has a fold change of 0.
+    pub fn log2fc_vs_reference(&self) -> CountMatrix {
+        let mut data = Vec::with_capacity(self.data.len());
+        for f in 0..self.nrows() {
+            let row = self.row(f);
+            let geo_mean = if row.iter().any(|&c| c <= 0.0) {
+                0.0
+            } else {
+                (row.iter().map(|c| c.ln()).sum::<f64>() / row.len() as f64).exp()
+            };
+            data.extend(row.iter().map(|c| ((c + 1.0) / (geo_mean + 1.0)).log2()));
+        }
+        CountMatrix::new(self.features.clone(), self.samples.clone(), data)
+    }
+
+    /// Per-feature coefficient of variation across samples, the sample
+    /// standard deviation over the mean. Features with a zero mean give NaN.
+    pub fn row_cv(&self) -> Vec<f64> {
+        (0..self.nrows())
+            .map(|f| {
+                let row = self.row(f);
+                let mean = stats::mean(row);
+                if mean == 0.0 || mean.is_nan() {
+                    return f64::NAN;
+                }
+                let sum_sq: f64 = row.iter().map(|x| (x - mean) * (x - mean)).sum();
+                sample_variance(sum_sq, row.len()).sqrt() / mean
+            })
+            .collect()
+    }
+
+    /// Indices of the (at most) `n` features with the largest
+    /// [`row_cv`](Self::row_cv), highest first. NaN features are skipped and
+    /// ties keep feature order.
+    pub fn top_cv_features(&self, n: usize) -> Vec<usize> {
+        let cv = self.row_cv();
+        let mut ranked: Vec<usize> = (0..cv.len()).filter(|&f| !cv[f].is_nan()).collect();
+        ranked.sort_by(|&a, &b| cv[b].partial_cmp(&cv[a]).unwrap());
+        ranked.truncate(n);
+        ranked
+    }
+}
+
+/// A compressed sparse row matrix.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct CsrMatrix {
+    pub nrows: usize,
+    pub ncols: usize,
+    /// Row `i` occupies `indices[indptr[i]..indptr[i + 1]]`.
+    pub indptr: Vec<usize>,
+    pub indices: Vec<usize>,
+    pub data: Vec<f64>,
+}
+
+impl CsrMatrix {
+    pub fn nnz(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Read a matrix written by [`eq_classes_to_csr_streaming`].
+    pub fn read_streamed<R: Read>(mut reader: R) -> Result<CsrMatrix, io::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "truncated CSR stream");
+        let word = |i: usize| -> Result<[u8; 8], io::Error> {
+            let mut w = [0_u8; 8];
+            w.copy_from_slice(bytes.get(i * 8..(i + 1) * 8).ok_or_else(invalid)?);
+            Ok(w)
+        };
+
+        let nrows = u64::from_le_bytes(word(0)?) as usize;
+        let ncols = u64::from_le_bytes(word(1)?) as usize;
+        // The header fixes the layout, so the file must be exactly
+        // `3 + nrows + 2 * nnz` whole words long.
+        let nwords = bytes.len() / 8;
+        let nnz = nwords
+            .checked_sub(3)
+            .and_then(|w| w.checked_sub(nrows))
+            .ok_or_else(invalid)?
+            / 2;
+        if nwords * 8 != bytes.len() || 3 + nrows + 2 * nnz != nwords {
+            return Err(invalid());
+        }
+
+        let mut m = CsrMatrix {
+            nrows,
+            ncols,
+            indptr: Vec::with_capacity(nrows + 1),
+            indices: Vec::with_capacity(nnz),
+            data: Vec::with_capacity(nnz),
+        };
+        for e in 0..nnz {
+            m.indices
+                .push(u64::from_le_bytes(word(2 + 2 * e)?) as usize);
+            m.data.push(f64::from_le_bytes(word(3 + 2 * e)?));
+        }
+        for r in 0..=nrows {
+            m.indptr
+                .push(u64::from_le_bytes(word(2 + 2 * nnz + r)?) as usize);
+        }
+        Ok(m)
+    }
+}
+
+/*******************************************************************************/
+/*                         Experiments                                         */
+/*******************************************************************************/
+
+/// A tab-separated reader over a quant.sf, plain or gzipped.
+fn quant_reader(p: &Path) -> Result<csv::Reader<Box<dyn Read>>, csv::Error> {
+    let reader = decompress_if_gzipped(File::open(p)?)?;
+    Ok(csv::ReaderBuilder::new()
+        .delimiter(b'\t')
+        .from_reader(reader))
+}
+
+/// Read every row of a quant.sf, in file order.
+fn read_quant_records(p: &Path) -> Result<Vec<QuantRecord>, csv::Error> {
+    quant_reader(p)?.deserialize().collect()
+}
+
+/// Everything loaded from a single salmon output directory, with all target
+/// orderings checked against quant.sf.
+#[derive(Debug)]
+pub struct Experiment {
+    pub files: SalmonFiles,
+    pub quant: Vec<QuantRecord>,
+    pub meta_info: MetaInfo,
+    /// `None` if salmon was run without `--dumpEq`.
+    pub eq_classes: Option<EqClassCollection>,
+    /// `None` if no inferential replicates were written.
+    pub bootstraps: Option<Bootstraps>,
+    /// `None` if aux_info/ambig_info.tsv is absent.
+    pub ambig: Option<AmbigInfo>,
+}
+
+impl Experiment {
+    /// Load quant.sf and meta_info.json (both required), plus the
+    /// equivalence classes, the inferential replicates (when meta_info.json
+    /// records any) and ambig_info.tsv if present. The eq-class and
+    /// bootstrap targets must match the quant.sf rows in order (see
+    /// [`check_bootstrap_alignment`]); ambig_info.tsv carries no names, so
+    /// only its length is checked.
+    pub fn load_full<P: AsRef<Path>>(dir: P) -> Result<Experiment, SeineError> {
+        let files = SalmonFiles::try_new(dir)?;
+        let quant = read_quant_records(&files.quant_file)?;
+        let meta_info = files.read_meta_info()?;
+
+        let eq_classes = if files.eq_file.exists() {
+            let ecs = EqClassCollection::from_path(&files.eq_file)?;
+            let names: Vec<String> = quant.iter().map(|r| r.name.clone()).collect();
+            check_same_targets(&names, &ecs.targets)?;
+            Some(ecs)
+        } else {
+            None
+        };
+
+        let bootstraps = if meta_info.has_inferential_replicates() && files.has_bootstraps() {
+            let boot = Bootstraps::try_from_salmon_files(&files)?;
+            check_bootstrap_alignment(&boot, &quant)?;
+            Some(boot)
+        } else {
+            None
+        };
+
+        let ambig = if files.ambig_file.exists() {
+            let ambig = files.read_ambig()?;
+            if ambig.len() != quant.len() {
+                return Err(SeineError::TargetCountMismatch {
+                    expected: quant.len(),
+                    found: ambig.len(),
+                });
+            }
+            Some(ambig)
+        } else {
+            None
+        };
+
+        Ok(Experiment {
+            files,
+            quant,
+            meta_info,
+            eq_classes,
+            bootstraps,
+            ambig,
+        })
+    }
+}
+
+/// The quant.sf values of several runs against the same transcriptome, as
+/// target-by-sample [`CountMatrix`]es (one row per target, one column per
+/// sample, in the order given).
+///
+/// Rows are targets, not samples: reusing [`CountMatrix`] keeps the
+/// features-by-samples layout DE tools expect and gives the cohort its
+/// per-feature helpers (`prevalence`, `row_cv`, `log2fc_vs_reference`)
+/// for free. `num_reads.row(t)` is target `t` across the cohort and
+/// `num_reads.get(t, s)` one sample's value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CohortQuants {
+    pub num_reads: CountMatrix,
+    pub tpm: CountMatrix,
+}
+
+impl CohortQuants {
+    /// Load the quant.sf of every salmon run in `dirs`. Samples are named
+    /// by their directory's final component. Every quant.sf must list the
+    /// same targets in the same order; a sample that does not fails with
+    /// [`SeineError::InSample`] naming its directory and wrapping the
+    /// target count or name mismatch.
+    pub fn from_dirs(dirs: &[PathBuf]) -> Result<CohortQuants, SeineError> {
+        let mut targets = Vec::new();
+        let mut samples = Vec::with_capacity(dirs.len());
+        let mut columns = Vec::with_capacity(dirs.len());
+        for dir in dirs {
+            let quant = read_quant_records(&SalmonFiles::try_new(dir)?.quant_file)?;
+            let names: Vec<String> = quant.iter().map(|r| r.name.clone()).collect();
+            if samples.is_empty() {
+                targets = names;
+            } else {
+                check_same_targets(&targets, &names).map_err(|e| SeineError::InSample {
+                    dir: dir.clone(),
+                    source: Box::new(e),
+                })?;
+            }
+            let name = dir.file_name().unwrap_or(dir.as_os_str());
+            samples.push(name.to_string_lossy().into_owned());
+            columns.push(quant);
+        }
+
+        let mut num_reads = Vec::with_capacity(targets.len() * columns.len());
+        let mut tpm = Vec::with_capacity(targets.len() * columns.len());
+        for t in 0..targets.len() {
+            for quant in &columns {
+                num_reads.push(quant[t].num_reads);
+                tpm.push(quant[t].tpm);
+            }
+        }
+        Ok(CohortQuants {
+            num_reads: CountMatrix::new(targets.clone(), samples.clone(), num_reads),
+            tpm: CountMatrix::new(targets, samples, tpm),
+        })
+    }
+
+    pub fn sample_names(&self) -> &[String] {
+        &self.num_reads.samples
+    }
+
+    pub fn target_names(&self) -> &[String] {
+        &self.num_reads.features
+    }
+}
+
+/*******************************************************************************/
+/*                         Archives                                            */
+/*******************************************************************************/
+
+/// A salmon output directory packed into a tar archive (optionally gzipped,
+/// as `sample.tar.gz`), read without extracting it.
+///
+/// [`open`](Self::open) makes one pass over the archive to index its
+/// members; each reader then decompresses the stream again up to the member
+/// it needs. Members may sit under a top-level directory (`sample/quant.sf`)
+/// or at the root. Plain files, GNU long names and pax `path` records are
+/// understood; links and other entry types are ignored.
+#[derive(Debug, Clone)]
+pub struct SalmonArchive {
+    pub path: PathBuf,
+    /// `(member path, data offset, size)` in archive order, offsets into the
+    /// decompressed stream.
+    entries: Vec<(String, u64, u64)>,
+}
+
+const TAR_BLOCK: u64 = 512;
+
+/// A NUL-terminated tar header field.
+fn tar_str(field: &[u8]) -> String {
+    let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+/// Upper bound on a GNU long-name or pax extended header, which is read
+/// into memory whole.
+const TAR_MAX_EXT_HEADER: u64 = 1 << 20;
+
+/// A NUL- or space-padded octal tar header field, or a GNU base-256 one
+/// (high bit of the first byte set), as used for members of 8 GiB or more.
+fn tar_octal(field: &[u8]) -> Result<u64, io::Error> {
+    if let Some((&first, rest)) = field.split_first() {
+        if first & 0x80 != 0 {
+            let bad = || io::Error::new(io::ErrorKind::InvalidData, "bad base-256 tar size field");
+            // 0x40 marks a negative value, which is never a valid size
+            if first & 0x40 != 0 {
+                return Err(bad());
+            }
+            let mut value = u64::from(first & 0x3f);
+            for &b in rest {
+                if value >> 56 != 0 {
+                    return Err(bad());
+                }
+                value = value << 8 | u64::from(b);
+            }
+            return Ok(value);
+        }
+    }
+    let digits = tar_str(field);
+    let digits = digits.trim_matches(|c| c == ' ' || c == '\0');
+    if digits.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(digits, 8).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("bad tar size field {:?}", digits),
+        )
+    })
+}
+
+/// The `path` record of a pax extended header, if any.
+fn pax_path(data: &[u8]) -> Option<String> {
+    let text = String::from_utf8_lossy(data);
+    text.lines()
+        .filter_map(|rec| rec.split_once(' ').map(|(_, kv)| kv))
+        .find_map(|kv| kv.strip_prefix("path=").map(String::from))
+}
+
+impl SalmonArchive {
+    /// Index the members of the tar (or tar.gz) archive at `p`.
+    pub fn open<P: AsRef<Path>>(p: P) -> Result<SalmonArchive, io::Error> {
+        let path = p.as_ref().to_path_buf();
+        let mut r = decompress_if_gzipped(File::open(&path)?)?;
+        let mut entries = Vec::new();
+        let mut pos = 0u64;
+        let mut long_name = None;
+        let mut header = [0u8; TAR_BLOCK as usize];
+        loop {
+            match r.read_exact(&mut header) {
+                Ok(()) => {}
+                // tolerate archives that stop without the closing zero blocks
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e),
+            }
+            pos += TAR_BLOCK;
+            if header.iter().all(|&b| b == 0) {
+                break;
+            }
+            let size = tar_octal(&header[124..136])?;
+            let padded = size
+                .div_ceil(TAR_BLOCK)
+                .checked_mul(TAR_BLOCK)
+                .ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidData, "tar member too large")
+                })?;
+            match header[156] {
+                b'L' | b'x' => {
+                    if size > TAR_MAX_EXT_HEADER {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("tar extended header of {} bytes is too large", size),
+                        ));
+                    }
+                    let mut data = vec![0u8; padded as usize];
+                    r.read_exact(&mut data)?;
+                    data.truncate(size as usize);
+                    long_name = if header[156] == b'L' {
+                        Some(tar_str(&data))
+                    } else {
+                        pax_path(&data)
+                    };
+                }
+                kind => {
+                    if kind == b'0' || kind == 0 {
+                        let name = long_name.take().unwrap_or_else(|| {
+                            let name = tar_str(&header[0..100]);
+                            match &header[257..262] {
+                                b"ustar" if header[345] != 0 => {
+                                    format!("{}/{}", tar_str(&header[345..500]), name)
+                                }
+                                _ => name,
+                            }
+                        });
+                        let name = name.trim_start_matches("./").to_string();
+                        entries.push((name, pos, size));
+                    }
+                    long_name = None;
+                    let skipped = io::copy(&mut r.by_ref().take(padded), &mut io::sink())?;
+                    if skipped < padded {
+                        return Err(io::Error::new(
+                            io::ErrorKind::UnexpectedEof,
+                            "truncated tar member",
+                        ));
+                    }
+                }
+            }
+            pos += padded;
+        }
+        Ok(SalmonArchive { path, entries })
+    }
+
+    /// The member paths, in archive order.
+    pub fn members(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(|(name, _, _)| name.as_str())
+    }
+
+    /// The first member at `rel` (relative to the salmon output directory),
+    /// either at the archive root or under one top-level directory.
+    fn find(&self, rel: &str) -> Option<(u64, u64)> {
+        self.entries
+            .iter()
+            .find(|(name, _, _)| {
+                name == rel || name.split_once('/').is_some_and(|(_, rest)| rest == rel)
+            })
+            .map(|&(_, offset, size)| (offset, size))
+    }
+
+    /// Whether the archive holds `rel`.
+    pub fn contains(&self, rel: &str) -> bool {
+        self.find(rel).is_some()
+    }
+
+    /// The contents of member `rel`, as stored (not decompressed). A missing
+    /// member is reported as [`SeineError::MissingFile`] with the archive
+    /// path joined to `rel`.
+    pub fn read_member(&self, rel: &str) -> Result<Vec<u8>, SeineError> {
+        let (offset, size) = self
+            .find(rel)
+            .ok_or_else(|| SeineError::MissingFile(self.path.join(rel)))?;
+        let mut r = decompress_if_gzipped(File::open(&self.path)?)?;
+        io::copy(&mut r.by_ref().take(offset), &mut io::sink())?;
+        // `size` comes from the header, so let the read grow the buffer
+        // rather than reserving it up front
+        let mut data = Vec::new();
+        r.take(size).read_to_end(&mut data)?;
+        if (data.len() as u64) < size {
+            return Err(
+                io::Error::new(io::ErrorKind::UnexpectedEof, "truncated tar member").into(),
+            );
+        }
+        Ok(data)
+    }
+
+    /// The member holding the first of `names` present, decompressed if it
+    /// is gzipped.
+    fn read_decompressed(&self, names: &[&str]) -> Result<Vec<u8>, SeineError> {
+        let rel = names.iter().find(|n| self.contains(n)).unwrap_or(&names[0]);
+        let data = self.read_member(rel)?;
+        if !data.starts_with(&[0x1f, 0x8b]) {
+            return Ok(data);
+        }
+        let mut out = Vec::new();
+        GzDecoder::new(&data[..]).read_to_end(&mut out)?;
+        Ok(out)
+    }
+
+    /// quant.sf (or quant.sf.gz), as [`QuantTable::from_path`] reads it.
+    pub fn read_quant(&self) -> Result<QuantTable, SeineError> {
+        let data = self.read_decompressed(&["quant.sf", "quant.sf.gz"])?;
+        let mut table = QuantTable::default();
+        let mut rdr = csv::ReaderBuilder::new()
+            .delimiter(b'\t')
+            .from_reader(&data[..]);
+        for rec in rdr.deserialize() {
+            table.push(rec?);
+        }
+        Ok(table)
+    }
+
+    /// aux_info/meta_info.json, as [`MetaInfo::from_path`] reads it.
+    pub fn read_meta_info(&self) -> Result<MetaInfo, SeineError> {
+        let rel = "aux_info/meta_info.json";
+        let data = self.read_member(rel)?;
+        serde_json::from_slice(&data).map_err(|source| SeineError::MalformedMetaInfo {
+            path: self.path.join(rel),
+            source,
+        })
+    }
+
+    /// The equivalence classes, from whichever of `eq_classes.txt.gz` and
+    /// `eq_classes.txt` is in aux_info.
+    pub fn read_eq_classes(&self) -> Result<EqClassCollection, SeineError> {
+        let names = ["aux_info/eq_classes.txt.gz", "aux_info/eq_classes.txt"];
+        let rel = names.iter().find(|n| self.contains(n)).unwrap_or(&names[1]);
+        Ok(EqClassCollection::from_slice(&self.read_member(rel)?)?)
+    }
+}
 
 /*******************************************************************************/
 /*                         Extension Traits                                    */
 /*******************************************************************************/
 
-pub trait FromPathExt {
-    fn from_path<P: AsRef<Path>>(p: P) -> Result<Self, csv::Error>
-    where
-        Self: Sized;
-}
+pub trait FromPathExt {
+    fn from_path<P: AsRef<Path>>(p: P) -> Result<Self, csv::Error>
+    where
+        Self: Sized;
+}
+
+impl FromPathExt for HashMap<String, QuantEntry> {
+    fn from_path<P: AsRef<Path>>(p: P) -> Result<HashMap<String, QuantEntry>, csv::Error> {
+        let mut rdr = quant_reader(p.as_ref())?;
+
+        let mut quant_map = HashMap::new();
+
+        for quant_record in rdr.deserialize() {
+            let quant_record: QuantRecord = quant_record?;
+            let quant_entry = QuantEntry {
+                len: quant_record.len,
+                efflen: quant_record.efflen,
+                tpm: quant_record.tpm,
+                num_reads: quant_record.num_reads,
+            };
+
+            quant_map.insert(quant_record.name.to_string(), quant_entry);
+        }
+
+        Ok(quant_map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    fn collection(ntarget: usize, classes: &[(&[Label], &[f64], u32)]) -> EqClassCollection {
+        let mut exp = EqClassCollection::new();
+        exp.ntarget = ntarget;
+        exp.targets = (0..ntarget).map(|t| format!("tx{}", t)).collect();
+        for (labels, weights, count) in classes {
+            exp.classes.push(EqClass {
+                labels: labels.to_vec(),
+                weights: weights.to_vec(),
+                count: *count,
+            });
+        }
+        exp.neq = exp.classes.len();
+        exp
+    }
+
+    #[test]
+    fn ec_list_get() {
+        let mut ecs = EqClassList::new();
+
+        assert!(ecs.is_empty());
+
+        let ec = EqClass {
+            labels: vec![0, 1, 2],
+            weights: vec![0.2, 0.3, 0.5],
+            count: 15,
+        };
+
+        ecs.push(ec);
+
+        assert_eq!(ecs.len(), 1);
+
+        assert!(ecs.get(1).is_none());
+
+        assert_eq!(ecs.iter().count(), ecs.len());
+
+        let ec = ecs.get(0).unwrap();
+        assert_eq!(ec.labels, vec![0, 1, 2]);
+        assert_eq!(ec.weights, vec![0.2, 0.3, 0.5]);
+        assert_eq!(ec.count, 15);
+    }
+
+    #[test]
+    fn cluster_targets_by_shared_classes() {
+        let exp = collection(
+            5,
+            &[
+                (&[0, 1], &[0.5, 0.5], 4),
+                (&[0, 1], &[0.3, 0.7], 2),
+                (&[2, 3], &[0.5, 0.5], 3),
+                (&[1, 2], &[0.5, 0.5], 1),
+            ],
+        );
+
+        // {0,1} share two classes; {2,3} and {1,2} only one each
+        assert_eq!(
+            exp.cluster_targets(2),
+            vec![vec![0, 1], vec![2], vec![3], vec![4]]
+        );
+        assert_eq!(exp.cluster_targets(1), vec![vec![0, 1, 2, 3], vec![4]]);
+
+        let exp = collection(4, &[(&[0, 1], &[0.5, 0.5], 4), (&[2, 3], &[0.5, 0.5], 3)]);
+        assert_eq!(exp.cluster_targets(1), vec![vec![0, 1], vec![2, 3]]);
+    }
+
+    #[test]
+    fn target_class_contributions_per_class() {
+        let exp = collection(
+            3,
+            &[
+                (&[0, 1], &[0.25, 0.75], 8),
+                (&[2], &[1.0], 5),
+                (&[1, 2], &[0.5, 0.5], 4),
+            ],
+        );
+
+        assert_eq!(exp.target_class_contributions(1), vec![(0, 6.0), (2, 2.0)]);
+        assert_eq!(exp.target_class_contributions(0), vec![(0, 2.0)]);
+        assert!(exp.target_class_contributions(7).is_empty());
+    }
+
+    fn tmp_path(name: &str) -> PathBuf {
+        static CLEAN: std::sync::Once = std::sync::Once::new();
+        let root = std::env::temp_dir().join(format!("seine-test-{}", std::process::id()));
+        // pids get reused, so drop whatever an earlier run left behind
+        CLEAN.call_once(|| {
+            let _ = std::fs::remove_dir_all(&root);
+        });
+        root.join(name)
+    }
+
+    fn write_tmp(name: &str, contents: &[u8]) -> PathBuf {
+        let p = tmp_path(name);
+        create_dir_all(p.parent().unwrap()).unwrap();
+        write(&p, contents).unwrap();
+        p
+    }
+
+    #[test]
+    fn header_counts_ignore_trailing_tokens() {
+        let p = write_tmp(
+            "header_comment_eq_classes.txt",
+            b"2  # comment\n1 extra\ntxA\ntxB\n2 0 1 0.4 0.6 7\n",
+        );
+        let exp = EqClassCollection::from_path(&p).unwrap();
+        assert_eq!(exp.ntarget, 2);
+        assert_eq!(exp.neq, 1);
+        assert_eq!(exp.targets, vec!["txA", "txB"]);
+        assert_eq!(exp.get(0).unwrap().count, 7);
+
+        let p = write_tmp("header_bad_eq_classes.txt", b"two\n1\ntxA\ntxB\n");
+        let err = EqClassCollection::from_path(&p).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("`two`"));
+    }
+
+    #[test]
+    fn count_matrix_log2fc_vs_reference() {
+        let m = CountMatrix::new(
+            vec!["g1".to_string(), "g2".to_string()],
+            vec!["s1".to_string(), "s2".to_string()],
+            vec![1.0, 4.0, 0.0, 3.0],
+        );
+        let fc = m.log2fc_vs_reference();
+
+        // g1 reference is sqrt(1 * 4) = 2
+        assert!((fc.get(0, 0) - (2.0_f64 / 3.0).log2()).abs() < 1e-12);
+        assert!((fc.get(0, 1) - (5.0_f64 / 3.0).log2()).abs() < 1e-12);
+        // g2 has a zero, so its reference is 0
+        assert_eq!(fc.get(1, 0), 0.0);
+        assert_eq!(fc.get(1, 1), 2.0);
+    }
+
+    #[test]
+    fn sort_by_count_records_original_order() {
+        let mut exp = collection(
+            3,
+            &[
+                (&[0], &[1.0], 2),
+                (&[1], &[1.0], 9),
+                (&[2], &[1.0], 5),
+                (&[0, 1], &[0.5, 0.5], 9),
+            ],
+        );
+        assert!(exp.current_order().is_none());
+
+        let order = exp.sort_by_count_desc();
+        assert_eq!(order, vec![1, 3, 2, 0]);
+        assert_eq!(exp.current_order(), Some(&order[..]));
+        let counts: Vec<u32> = exp.classes.iter().map(|ec| ec.count).collect();
+        assert_eq!(counts, vec![9, 9, 5, 2]);
+
+        // map back: the class now at position i was originally at order[i]
+        let mut original = vec![0; order.len()];
+        for (i, &o) in order.iter().enumerate() {
+            original[o] = exp.get(i).unwrap().labels[0];
+        }
+        assert_eq!(original, vec![0, 1, 2, 0]);
+
+        // sorting again still refers back to file order
+        assert_eq!(exp.sort_by_count_desc(), order);
+    }
+
+    #[test]
+    fn length_weighted_counts_two_labels() {
+        let exp = collection(3, &[(&[0, 1], &[0.5, 0.5], 10), (&[2], &[1.0], 4)]);
+        // equal weights, lengths 100 and 300 -> 1/4 and 3/4 of the class
+        let counts = length_weighted_counts(&exp, &[100, 300, 50]);
+        assert_eq!(counts, vec![2.5, 7.5, 4.0]);
+    }
+
+    fn quant_rec(name: &str, len: u32, efflen: f64, tpm: f64, num_reads: f64) -> QuantRecord {
+        QuantRecord {
+            name: name.to_string(),
+            len,
+            efflen,
+            tpm,
+            num_reads,
+        }
+    }
+
+    #[test]
+    fn bootstrap_alignment() {
+        let quant = vec![
+            quant_rec("txA", 100, 80.0, 0.0, 0.0),
+            quant_rec("txB", 200, 180.0, 0.0, 0.0),
+        ];
+        let mut boot = Bootstraps::new(2, 2, vec![1.0, 2.0, 3.0, 4.0]);
+        assert!(check_bootstrap_alignment(&boot, &quant).is_ok());
+
+        boot.names = vec!["txA".to_string(), "txB".to_string()];
+        assert!(check_bootstrap_alignment(&boot, &quant).is_ok());
+
+        boot.names[1] = "txC".to_string();
+        match check_bootstrap_alignment(&boot, &quant) {
+            Err(SeineError::TargetNameMismatch { index, found, .. }) => {
+                assert_eq!(index, 1);
+                assert_eq!(found, "txC");
+            }
+            r => panic!("unexpected result {:?}", r),
+        }
+
+        match check_bootstrap_alignment(&boot, &quant[..1]) {
+            Err(SeineError::TargetCountMismatch { expected, found }) => {
+                assert_eq!((expected, found), (1, 2));
+            }
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+
+    #[test]
+    fn target_proportions_sum_to_one() {
+        let exp = collection(3, &[(&[0, 1], &[0.25, 0.75], 4), (&[2], &[1.0], 4)]);
+        assert_eq!(exp.naive_counts(), vec![1.0, 3.0, 4.0]);
+        let props = exp.target_proportions();
+        assert_eq!(props, vec![0.125, 0.375, 0.5]);
+        assert!((props.iter().sum::<f64>() - 1.0).abs() < 1e-12);
+
+        let empty = collection(2, &[]);
+        assert_eq!(empty.target_proportions(), vec![0.0, 0.0]);
+    }
+
+    #[test]
+    fn streaming_csr_matches_in_memory() {
+        let text = b"3\n2\ntxA\ntxB\ntxC\n2 0 2 0.25 0.75 4\n1 1 1.0 9\n";
+        let p = write_tmp("csr_eq_classes.txt", text);
+        let exp = EqClassCollection::from_path(&p).unwrap();
+
+        let mut out = Vec::new();
+        eq_classes_to_csr_streaming(&text[..], &mut out).unwrap();
+        let streamed = CsrMatrix::read_streamed(&out[..]).unwrap();
+
+        assert_eq!(streamed, exp.to_csr());
+        assert_eq!(streamed.nnz(), 3);
+        assert_eq!(streamed.indptr, vec![0, 2, 3]);
+        assert_eq!(streamed.indices, vec![0, 2, 1]);
+
+        for cut in [1, 8, 24] {
+            let err = CsrMatrix::read_streamed(&out[..out.len() - cut]).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn gene_collection_merges_transcripts_of_a_gene() {
+        let exp = collection(3, &[(&[0, 1, 2], &[0.2, 0.3, 0.5], 10), (&[1], &[1.0], 3)]);
+        let t2g: Tx2Gene = vec![("tx0", "geneA"), ("tx1", "geneA"), ("tx2", "geneB")]
+            .into_iter()
+            .collect();
+
+        let genes = exp.to_gene_collection(&t2g);
+        assert_eq!(genes.targets, vec!["geneA", "geneB"]);
+        assert_eq!(genes.ntarget, 2);
+        assert_eq!(genes.neq, 2);
+
+        let ec = genes.get(0).unwrap();
+        assert_eq!(ec.labels, &[0, 1]);
+        assert!((ec.weights[0] - 0.5).abs() < 1e-12);
+        assert_eq!(ec.weights[1], 0.5);
+        assert_eq!(ec.count, 10);
+        assert_eq!(genes.get(1).unwrap().labels, &[0]);
+    }
+
+    #[test]
+    fn gene_collection_keeps_unmapped_names_unique() {
+        let mut exp = collection(3, &[(&[0, 1, 2], &[0.2, 0.3, 0.5], 4)]);
+        exp.targets = vec!["tx0".into(), "geneA".into(), "geneA".into()];
+        let t2g: Tx2Gene = vec![("tx0", "geneA")].into_iter().collect();
+
+        let genes = exp.to_gene_collection(&t2g);
+        assert_eq!(
+            genes.targets,
+            vec!["geneA", "geneA_unmapped", "geneA_unmapped2"]
+        );
+        assert_eq!(genes.get(0).unwrap().labels, &[0, 1, 2]);
+    }
+
+    #[test]
+    fn gini_simpson_diversity() {
+        let uniform = collection(2, &[(&[0], &[1.0], 5), (&[1], &[1.0], 5)]);
+        assert!((uniform.gini_simpson() - 0.5).abs() < 1e-12);
+
+        let single = collection(2, &[(&[1], &[1.0], 7)]);
+        assert_eq!(single.gini_simpson(), 0.0);
+    }
+
+    #[test]
+    fn meta_info_modern_and_legacy() {
+        let legacy = r#"{
+            "num_valid_targets": 3,
+            "serialized_eq_classes": false,
+            "num_bootstraps": 0,
+            "num_eq_classes": 2,
+            "eq_class_properties": [],
+            "samp_type": "none"
+        }"#;
+        let mi: MetaInfo = serde_json::from_str(legacy).unwrap();
+        assert!(mi.length_classes().is_empty());
+        assert_eq!(mi.num_decoy_fragments, None);
+        assert!(!mi.has_selective_alignment_stats());
+
+        let modern = r#"{
+            "num_valid_targets": 3,
+            "serialized_eq_classes": false,
+            "num_bootstraps": 30,
+            "num_eq_classes": 2,
+            "eq_class_properties": ["range_factorized", "gzipped"],
+            "samp_type": "bootstrap",
+            "length_classes": [523, 853, 1323, 2154, 104301],
+            "num_decoy_fragments": 12,
+            "num_fragments_filtered_vm": 340,
+            "num_alignments_below_threshold_for_mapped_fragments_vm": 5678
+        }"#;
+        let mi: MetaInfo = serde_json::from_str(modern).unwrap();
+        assert_eq!(mi.length_classes(), &[523, 853, 1323, 2154, 104301]);
+        assert_eq!(mi.num_decoy_fragments, Some(12));
+        assert_eq!(mi.num_fragments_filtered_vm, Some(340));
+        assert!(mi.has_selective_alignment_stats());
+    }
+
+    #[test]
+    fn simpson_dominance_per_class() {
+        let exp = collection(2, &[(&[0, 1], &[0.5, 0.5], 3), (&[1], &[1.0], 2)]);
+        assert_eq!(exp.get(0).unwrap().simpson_dominance(), 0.5);
+        assert_eq!(exp.get(1).unwrap().simpson_dominance(), 1.0);
+        assert_eq!(exp.mean_dominance(), 0.75);
+        assert_eq!(collection(2, &[]).mean_dominance(), 0.0);
+    }
+
+    #[test]
+    fn quant_writer_precision() {
+        let recs = vec![quant_rec("txA", 1657, 1482.0, 12.345678, 7.25431)];
+
+        let mut out = Vec::new();
+        QuantWriter::new().write(&recs, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Name\tLength\tEffectiveLength\tTPM\tNumReads\ntxA\t1657\t1482.000\t12.345678\t7.254\n"
+        );
+
+        let mut out = Vec::new();
+        QuantWriter::new()
+            .precision(3)
+            .write(&recs, &mut out)
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(out.lines().nth(1).unwrap(), "txA\t1657\t1.48e3\t12.3\t7.25");
+
+        assert_eq!(format_significant(0.000012345, 3), "1.23e-5");
+        assert_eq!(format_significant(0.5, 3), "0.5");
+        assert_eq!(format_significant(0.0, 3), "0");
+    }
+
+    #[test]
+    fn coverage_rates() {
+        let recs = vec![
+            quant_rec("txA", 1000, 800.0, 0.0, 400.0),
+            quant_rec("txB", 50, 0.0, 0.0, 3.0),
+            quant_rec("txC", 300, 100.0, 0.0, 0.0),
+        ];
+        assert_eq!(coverage(&recs), vec![0.5, 0.0, 0.0]);
+        assert_eq!(
+            tpm_from_rates(&[1.0, 3.0, 0.0]),
+            vec![250_000.0, 750_000.0, 0.0]
+        );
+        assert_eq!(tpm_from_rates(&[0.0, 0.0]), vec![0.0, 0.0]);
+    }
+
+    #[test]
+    fn cohort_from_manifest() {
+        let a = write_tmp("cohort_a.txt", b"2\n1\ntxA\ntxB\n2 0 1 0.5 0.5 4\n");
+        let b = write_tmp("cohort_b.txt", b"2\n2\ntxA\ntxB\n1 0 1.0 3\n1 1 1.0 2\n");
+        let c = write_tmp("cohort_c.txt", b"2\n0\ntxA\ntxZ\n");
+        let manifest = write_tmp(
+            "cohort_manifest.tsv",
+            format!("s1\t{}\ns2\tcohort_b.txt\n", a.display()).as_bytes(),
+        );
+
+        let cohort = CohortEqClasses::from_manifest(&manifest).unwrap();
+        assert_eq!(cohort.num_samples(), 2);
+        assert_eq!(cohort.targets(), &["txA", "txB"]);
+        assert_eq!(cohort.sample("s1").unwrap().len(), 1);
+        assert_eq!(cohort.sample("s2").unwrap().counts, vec![3, 2]);
+        assert!(cohort.sample("s3").is_none());
+
+        let manifest = write_tmp(
+            "cohort_bad_manifest.tsv",
+            format!("s1\t{}\ns3\t{}\n", b.display(), c.display()).as_bytes(),
+        );
+        match CohortEqClasses::from_manifest(&manifest) {
+            Err(SeineError::TargetNameMismatch { index, .. }) => assert_eq!(index, 1),
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+
+    #[test]
+    fn bootstraps_aggregate_to_gene() {
+        // 3 replicates x 2 transcripts of the same gene
+        let boot = Bootstraps::new(3, 2, vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        let t2g: Tx2Gene = vec![("txA", "geneX"), ("txB", "geneX")]
+            .into_iter()
+            .collect();
+        let names = vec!["txA".to_string(), "txB".to_string()];
+
+        let genes = boot.aggregate_to_gene(&t2g, &names);
+        assert_eq!(genes.genes, vec!["geneX"]);
+        assert_eq!(genes.nboot, 3);
+        assert_eq!(genes.gene_values(0), &[3.0, 7.0, 11.0]);
+    }
+
+    #[test]
+    fn detect_eq_class_weights() {
+        let p = write_tmp(
+            "weighted_eq_classes.txt",
+            b"2\n1\ntxA\ntxB\n2 0 1 0.4 0.6 7\n",
+        );
+        assert!(eq_class_has_weights(&p).unwrap());
+
+        let p = write_tmp("weightless_eq_classes.txt", b"2\n1\ntxA\ntxB\n2 0 1 7\n");
+        assert!(!eq_class_has_weights(&p).unwrap());
+
+        let p = write_tmp("empty_eq_classes.txt", b"2\n0\ntxA\ntxB\n");
+        assert!(eq_class_has_weights(&p).is_err());
+    }
+
+    #[test]
+    fn log_transformed_tpm() {
+        let recs = vec![
+            quant_rec("txA", 100, 80.0, 0.0, 0.0),
+            quant_rec("txB", 100, 80.0, 7.0, 0.0),
+        ];
+        assert_eq!(log_tpm(&recs, DEFAULT_PSEUDOCOUNT), vec![0.0, 3.0]);
+        assert_eq!(log_tpm(&recs, 0.5)[0], -1.0);
+
+        let mut m = CountMatrix::new(
+            vec!["g1".to_string()],
+            vec!["s1".to_string(), "s2".to_string()],
+            vec![3.0, 15.0],
+        );
+        m.log_transform(1.0);
+        assert_eq!(m.data, vec![2.0, 4.0]);
+    }
+
+    #[test]
+    fn count_matrix_presence_and_prevalence() {
+        let m = CountMatrix::new(
+            vec!["g1".to_string(), "g2".to_string()],
+            (1..=4).map(|s| format!("s{}", s)).collect(),
+            vec![0.0, 1.0, 5.0, 2.0, 0.0, 0.0, 0.0, 3.0],
+        );
+        assert_eq!(
+            m.to_presence_matrix(2.0),
+            vec![false, false, true, true, false, false, false, true]
+        );
+        assert_eq!(m.prevalence(2.0), vec![0.5, 0.25]);
+        assert_eq!(m.prevalence(f64::MIN_POSITIVE), vec![0.75, 0.25]);
+
+        let empty = CountMatrix::new(vec!["g1".to_string()], Vec::new(), Vec::new());
+        assert_eq!(empty.prevalence(1.0), vec![0.0]);
+    }
+
+    fn meta_info_json(num_bootstraps: u32, props: &str) -> String {
+        format!(
+            r#"{{"num_valid_targets": 2, "serialized_eq_classes": false,
+                "num_bootstraps": {}, "num_eq_classes": 1,
+                "eq_class_properties": [{}], "samp_type": "bootstrap"}}"#,
+            num_bootstraps, props
+        )
+    }
+
+    fn salmon_dir(name: &str, num_bootstraps: u32) -> PathBuf {
+        write_tmp(
+            &format!("{}/aux_info/meta_info.json", name),
+            meta_info_json(num_bootstraps, "").as_bytes(),
+        );
+        tmp_path(name)
+    }
+
+    #[test]
+    fn detect_bootstraps() {
+        let without = salmon_dir("no_boot", 0);
+        let sf = SalmonFiles::new(&without);
+        assert!(!sf.has_bootstraps());
+        assert_eq!(sf.num_bootstraps().unwrap(), 0);
+
+        let with = salmon_dir("with_boot", 20);
+        create_dir_all(with.join("aux_info/bootstrap")).unwrap();
+        write(with.join("aux_info/bootstrap/bootstraps.gz"), b"").unwrap();
+        let sf = SalmonFiles::new(&with);
+        assert!(sf.has_bootstraps());
+        assert_eq!(sf.num_bootstraps().unwrap(), 20);
+    }
+
+    #[test]
+    fn bootstrap_zero_fraction() {
+        // 4 replicates x 3 targets
+        let boot = Bootstraps::new(
+            4,
+            3,
+            vec![
+                0.0, 1.0, 2.0, //
+                0.0, 0.0, 3.0, //
+                5.0, 0.0, 1.0, //
+                0.0, 2.0, 4.0,
+            ],
+        );
+        assert_eq!(boot.zero_fraction(), vec![0.75, 0.5, 0.0]);
+    }
+
+    fn write_quant(name: &str, recs: &[QuantRecord]) -> PathBuf {
+        let mut out = Vec::new();
+        QuantWriter::new().write(recs, &mut out).unwrap();
+        write_tmp(name, &out)
+    }
+
+    #[test]
+    fn named_quant_reader_preserves_order() {
+        let recs = vec![
+            quant_rec("txB", 200, 150.0, 400000.0, 12.0),
+            quant_rec("txA", 100, 50.0, 600000.0, 6.0),
+            quant_rec("txC", 300, 250.0, 0.0, 0.0),
+        ];
+        let p = write_quant("named_quant.sf", &recs);
+
+        let entries: Vec<NamedQuantEntry> = NamedQuantReader::from_path(&p)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let names: Vec<&str> = entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["txB", "txA", "txC"]);
+
+        let map = HashMap::<String, QuantEntry>::from_path(&p).unwrap();
+        for e in &entries {
+            let m = &map[&e.name];
+            assert_eq!((e.entry.len, e.entry.num_reads), (m.len, m.num_reads));
+        }
+        assert_eq!(entries[0].entry.efflen, 150.0);
+    }
+
+    #[test]
+    fn bootstrap_means_track_point_estimates() {
+        let boot = Bootstraps::new(2, 3, vec![9.0, 20.0, 0.0, 11.0, 20.0, 2.0]);
+        assert_eq!(boot.means(), vec![10.0, 20.0, 1.0]);
+        let agree = bootstrap_point_agreement(&boot, &[10.0, 20.0, 1.0]);
+        assert!((agree - 1.0).abs() < 1e-12);
+        let disagree = bootstrap_point_agreement(&boot, &[20.0, 1.0, 10.0]);
+        assert!(disagree < 0.0);
+    }
+
+    #[test]
+    fn partition_unique_and_ambiguous() {
+        let exp = collection(
+            3,
+            &[
+                (&[0], &[1.0], 5),
+                (&[0, 1], &[0.5, 0.5], 3),
+                (&[2], &[1.0], 2),
+                (&[0, 1, 2], &[0.2, 0.3, 0.5], 1),
+            ],
+        );
+        let (unique, ambiguous) = exp.partition_by_ambiguity();
+
+        assert_eq!(unique.neq, 2);
+        assert_eq!(unique.classes.counts, vec![5, 2]);
+        assert_eq!(unique.get(1).unwrap().labels, &[2]);
+        assert_eq!(ambiguous.neq, 2);
+        assert_eq!(ambiguous.classes.counts, vec![3, 1]);
+        assert_eq!(ambiguous.get(1).unwrap().labels, &[0, 1, 2]);
+        assert_eq!(unique.targets, ambiguous.targets);
+        assert_eq!(unique.ntarget, 3);
+    }
+
+    #[test]
+    fn reweight_by_effective_length() {
+        let mut exp = collection(3, &[(&[0, 1], &[0.5, 0.5], 4), (&[1, 2], &[0.5, 0.5], 2)]);
+        exp.classes.reweight_by_length(&[100.0, 300.0, 0.0]);
+
+        let ec = exp.get(0).unwrap();
+        assert!((ec.weights[0] - 0.75).abs() < 1e-12);
+        assert!((ec.weights[1] - 0.25).abs() < 1e-12);
+        // the zero-length label is dropped from the class's mass
+        assert_eq!(exp.get(1).unwrap().weights, &[1.0, 0.0]);
+    }
+
+    #[test]
+    fn target_mtx_export() {
+        let exp = collection(3, &[(&[0, 2], &[0.25, 0.75], 4), (&[1], &[1.0], 9)]);
+        let mut out = Vec::new();
+        exp.write_target_mtx(&mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let mut lines = text.lines();
+
+        assert_eq!(
+            lines.next(),
+            Some("%%MatrixMarket matrix coordinate real general")
+        );
+        assert_eq!(lines.next(), Some("2 3 3"));
+        let entries: Vec<(usize, usize, f64)> = lines
+            .map(|l| {
+                let f: Vec<&str> = l.split(' ').collect();
+                (
+                    f[0].parse().unwrap(),
+                    f[1].parse().unwrap(),
+                    f[2].parse().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(entries, vec![(1, 1, 1.0), (1, 3, 3.0), (2, 2, 9.0)]);
+    }
+
+    #[test]
+    fn unique_reads_by_gene() {
+        let exp = collection(
+            3,
+            &[
+                (&[0], &[1.0], 5),
+                (&[1], &[1.0], 2),
+                (&[0, 2], &[0.5, 0.5], 8),
+                (&[0], &[1.0], 1),
+            ],
+        );
+        let t2g: Tx2Gene = vec![("tx0", "geneA"), ("tx1", "geneA"), ("tx2", "geneB")]
+            .into_iter()
+            .collect();
+        let reads = unique_reads_per_gene(&exp, &t2g);
+        assert_eq!(reads.len(), 1);
+        assert_eq!(reads["geneA"], 8);
+    }
+
+    #[test]
+    fn meta_info_drift() {
+        let mut a: MetaInfo = serde_json::from_str(&meta_info_json(0, "")).unwrap();
+        let mut b = a.clone();
+        assert!(meta_diff(&a, &b).is_empty());
+
+        a.salmon_version = Some("1.4.0".to_string());
+        b.salmon_version = Some("1.5.2".to_string());
+        b.num_bootstraps = 30;
+        assert_eq!(
+            meta_diff(&a, &b),
+            vec![
+                (
+                    "num_bootstraps".to_string(),
+                    "0".to_string(),
+                    "30".to_string()
+                ),
+                (
+                    "salmon_version".to_string(),
+                    "1.4.0".to_string(),
+                    "1.5.2".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn proportions_within_subset() {
+        let exp = collection(
+            3,
+            &[
+                (&[0], &[1.0], 3),
+                (&[0, 1], &[0.5, 0.5], 2),
+                (&[1, 2], &[0.5, 0.5], 10),
+                (&[2], &[1.0], 6),
+            ],
+        );
+        // the class touching target 2 is excluded: counts 4 and 1
+        assert_eq!(exp.target_proportions_within(&[1, 0]), vec![0.2, 0.8]);
+        assert_eq!(exp.target_proportions_within(&[5]), vec![0.0]);
+    }
+
+    #[test]
+    fn experiment_load_full() {
+        let dir = salmon_dir("experiment_full", 0);
+        write_quant(
+            "experiment_full/quant.sf",
+            &[
+                quant_rec("txA", 100, 80.0, 500000.0, 4.0),
+                quant_rec("txB", 200, 180.0, 500000.0, 9.0),
+            ],
+        );
+        write_tmp(
+            "experiment_full/aux_info/eq_classes.txt",
+            b"2\n1\ntxA\ntxB\n2 0 1 0.3 0.7 13\n",
+        );
+        let exp = Experiment::load_full(&dir).unwrap();
+        assert_eq!(exp.quant.len(), 2);
+        assert_eq!(exp.meta_info.num_valid_targets, 2);
+        assert_eq!(exp.eq_classes.as_ref().unwrap().neq, 1);
+
+        let dir = salmon_dir("experiment_no_eq", 0);
+        write_quant(
+            "experiment_no_eq/quant.sf",
+            &[quant_rec("txA", 100, 80.0, 1e6, 4.0)],
+        );
+        let exp = Experiment::load_full(&dir).unwrap();
+        assert!(exp.eq_classes.is_none());
+
+        write_tmp("experiment_no_eq/aux_info/eq_classes.txt", b"1\n0\ntxZ\n");
+        match Experiment::load_full(&dir) {
+            Err(SeineError::TargetNameMismatch { index, .. }) => assert_eq!(index, 0),
+            r => panic!("unexpected result {:?}", r),
+        }
+        assert!(Experiment::load_full(tmp_path("experiment_missing")).is_err());
+
+        // a leftover bootstraps.gz from a run that records no replicates
+        write_bootstraps(&tmp_path("experiment_full"), &["txA", "txB"], &[1.0, 2.0]);
+        let exp = Experiment::load_full(tmp_path("experiment_full")).unwrap();
+        assert!(exp.bootstraps.is_none());
+
+        let dir = salmon_dir("experiment_boot", 2);
+        write_quant(
+            "experiment_boot/quant.sf",
+            &[
+                quant_rec("txA", 100, 80.0, 500000.0, 4.0),
+                quant_rec("txB", 200, 180.0, 500000.0, 9.0),
+            ],
+        );
+        write_bootstraps(&dir, &["txA", "txB"], &[1.0, 2.0, 3.0, 4.0]);
+        let exp = Experiment::load_full(&dir).unwrap();
+        assert_eq!(exp.bootstraps.unwrap().num_bootstraps(), 2);
+
+        write_bootstraps(&dir, &["txA", "txC"], &[1.0, 2.0, 3.0, 4.0]);
+        match Experiment::load_full(&dir) {
+            Err(SeineError::TargetNameMismatch { index, found, .. }) => {
+                assert_eq!((index, found.as_str()), (1, "txC"))
+            }
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+
+    #[test]
+    fn recompute_effective_lengths() {
+        // fragments of length 2 or 4, equally likely
+        let fld = FragmentLengthDist::new(vec![0.0, 0.0, 0.5, 0.0, 0.5]);
+        assert_eq!(fld.mode(), 2);
+        assert_eq!(fld.mean(), 3.0);
+        assert_eq!(fld.effective_length(1), MIN_EFFECTIVE_LENGTH);
+        assert_eq!(fld.effective_length(3), 1.0);
+        assert_eq!(fld.effective_length(10), 7.0);
+
+        let mut recs = vec![
+            quant_rec("txA", 10, 0.0, 0.0, 7.0),
+            quant_rec("txB", 103, 0.0, 0.0, 100.0),
+        ];
+        recompute_efflen(&mut recs, &fld);
+        assert_eq!(recs[0].efflen, 7.0);
+        assert_eq!(recs[1].efflen, 100.0);
+        // rates 1 and 1 -> equal TPM
+        assert_eq!(recs[0].tpm, 500000.0);
+        assert_eq!(recs[1].tpm, 500000.0);
+    }
+
+    #[test]
+    fn uniquely_mappable() {
+        let exp = collection(
+            4,
+            &[
+                (&[0], &[1.0], 3),
+                (&[1, 2], &[0.5, 0.5], 2),
+                (&[2], &[1.0], 1),
+                (&[0], &[1.0], 1),
+            ],
+        );
+        // 0 is always unique, 1 always ambiguous, 2 both, 3 absent
+        assert_eq!(exp.uniquely_mappable_targets(), vec![0]);
+    }
+
+    #[test]
+    fn class_representatives_break_ties_by_index() {
+        let ecs = collection(
+            4,
+            &[
+                (&[0, 2, 3], &[0.1, 0.7, 0.2], 5),
+                (&[3, 1, 2], &[0.4, 0.4, 0.2], 2),
+            ],
+        );
+        let reps = ecs.class_representatives();
+        assert_eq!(reps, vec![Some(2), Some(1)]);
+        assert_eq!(ecs.targets[reps[0].unwrap()], "tx2");
+    }
+
+    #[test]
+    fn class_representatives_skip_empty_classes() {
+        let ecs = collection(3, &[(&[], &[], 4), (&[1, 2], &[0.5, 0.5], 1)]);
+        assert_eq!(ecs.class_representatives(), vec![None, Some(1)]);
+    }
+
+    #[test]
+    fn open_gzipped_eq_classes() {
+        let dir = tmp_path("open_gz");
+        write_tmp(
+            "open_gz/aux_info/meta_info.json",
+            meta_info_json(0, r#""gzipped""#).as_bytes(),
+        );
+        let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+        gz.write_all(b"2\n1\ntxA\ntxB\n1 0 1.0 3\n").unwrap();
+        write_tmp("open_gz/aux_info/eq_classes.txt.gz", &gz.finish().unwrap());
+
+        let sf = SalmonFiles::new(&dir);
+        let mut first = String::new();
+        BufReader::new(sf.open_eq_classes().unwrap())
+            .read_line(&mut first)
+            .unwrap();
+        assert_eq!(first, "2\n");
+
+        match sf.open_bootstraps() {
+            Err(SeineError::MissingFile(p)) => assert_eq!(p, sf.bootstrap_file),
+            _ => panic!("expected a missing-file error"),
+        }
+    }
+
+    #[test]
+    fn top_fraction_of_reads() {
+        let recs: Vec<QuantRecord> = (0..20)
+            .map(|i| {
+                let reads = if i < 2 { 30.0 } else { 5.0 };
+                quant_rec(&format!("tx{}", i), 100, 80.0, 0.0, reads)
+            })
+            .collect();
+        // top 2 of 20 hold 60 of 150 reads
+        let f = top_fraction(&recs, 0.1, QuantValue::NumReads);
+        assert!((f - 0.4).abs() < 1e-12);
+        assert!(top_fraction(&recs, 0.1, QuantValue::Tpm).is_nan());
+    }
+
+    #[test]
+    fn verify_quant_headers() {
+        let good = write_quant("good_header.sf", &[quant_rec("txA", 100, 50.0, 1.0, 2.0)]);
+        verify_quant_header(&good).unwrap();
+
+        let bad = write_tmp(
+            "reordered_header.sf",
+            b"Name\tLength\tTPM\tEffectiveLength\tNumReads\ntxA\t100\t1\t50\t2\n",
+        );
+        match verify_quant_header(&bad) {
+            Err(e @ SeineError::HeaderMismatch { .. }) => {
+                let msg = e.to_string();
+                assert!(msg.contains("reordered_header.sf"));
+                assert!(msg.contains("found `Name\tLength\tTPM"));
+            }
+            other => panic!("expected a header mismatch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn split_counts_preserves_totals() {
+        let ecs = collection(
+            3,
+            &[
+                (&[0], &[1.0], 100),
+                (&[0, 1], &[0.5, 0.5], 37),
+                (&[1, 2], &[0.2, 0.8], 0),
+            ],
+        );
+        let (train, test) = ecs.split_counts(0.7, 42);
+        assert_eq!(train.targets, ecs.targets);
+        assert_eq!(test.classes.labels, ecs.classes.labels);
+        for i in 0..ecs.classes.len() {
+            assert_eq!(
+                train.classes.counts[i] + test.classes.counts[i],
+                ecs.classes.counts[i]
+            );
+        }
+        assert!(train.classes.counts[0] > test.classes.counts[0]);
+
+        let (again, _) = ecs.split_counts(0.7, 42);
+        assert_eq!(again.classes.counts, train.classes.counts);
+    }
+
+    #[test]
+    fn quant_jsonl_round_trip() {
+        let recs = vec![
+            quant_rec("txA", 100, 50.5, 1234.5, 6.0),
+            quant_rec("txB", 200, 150.0, 0.0, 0.0),
+        ];
+        let mut out = Vec::new();
+        write_quant_jsonl(&recs, &mut out).unwrap();
+
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.starts_with(r#"{"Name":"txA","Length":100,"#));
+        let back: Vec<QuantRecord> = text
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(back.len(), 2);
+        assert_eq!(back[0].name, "txA");
+        assert_eq!(back[0].efflen, 50.5);
+        assert_eq!(back[1].tpm, 0.0);
+    }
+
+    #[test]
+    fn gene_effective_lengths_weight_by_reads() {
+        let t2g: Tx2Gene = vec![("txA", "g1"), ("txB", "g1"), ("txC", "g2"), ("txD", "g2")]
+            .into_iter()
+            .collect();
+        let recs = vec![
+            quant_rec("txA", 1100, 1000.0, 0.0, 30.0),
+            quant_rec("txB", 600, 500.0, 0.0, 10.0),
+            quant_rec("txC", 300, 200.0, 0.0, 0.0),
+            quant_rec("txD", 500, 400.0, 0.0, 0.0),
+            quant_rec("txE", 500, 400.0, 0.0, 5.0),
+        ];
+        let lens = gene_effective_lengths(&recs, &t2g);
+        assert_eq!(lens.len(), 2);
+        assert!((lens["g1"] - 875.0).abs() < 1e-12);
+        assert!((lens["g2"] - 300.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn gene_ambiguity_partners_from_shared_classes() {
+        let ecs = collection(
+            4,
+            &[
+                (&[0, 1], &[0.5, 0.5], 3),
+                (&[1, 2], &[0.5, 0.5], 4),
+                (&[3], &[1.0], 9),
+            ],
+        );
+        let t2g: Tx2Gene = vec![("tx0", "g1"), ("tx1", "g1"), ("tx2", "g2"), ("tx3", "g3")]
+            .into_iter()
+            .collect();
+        let partners = gene_ambiguity_partners(&ecs, &t2g);
+        assert_eq!(partners.len(), 2);
+        assert_eq!(partners["g1"], ["g2".to_string()].iter().cloned().collect());
+        assert_eq!(partners["g2"], ["g1".to_string()].iter().cloned().collect());
+        assert!(!partners.contains_key("g3"));
+    }
+
+    #[test]
+    fn streaming_bootstrap_stats_match_summarize() {
+        let values = vec![
+            1.0, 10.0, 0.0, //
+            3.0, 12.0, 0.0, //
+            2.0, 17.0, 0.0, //
+            6.0, 9.0, 0.0,
+        ];
+        let boot = Bootstraps::new(4, 3, values);
+        let mut acc = BootstrapAccumulator::new(3);
+        for rep in boot.values.chunks(3) {
+            acc.push_replicate(rep);
+        }
+        let streamed = acc.finish();
+        let full = boot.summarize();
+        assert!((full[0].mean - 3.0).abs() < 1e-12);
+        assert!((full[0].variance - 14.0 / 3.0).abs() < 1e-12);
+        for (s, f) in streamed.iter().zip(&full) {
+            assert!((s.mean - f.mean).abs() < 1e-12);
+            assert!((s.variance - f.variance).abs() < 1e-12);
+        }
+        assert_eq!(
+            streamed[2],
+            BootstrapStat {
+                mean: 0.0,
+                variance: 0.0
+            }
+        );
+    }
+
+    #[test]
+    fn gene_quant_round_trip() {
+        let t2g: Tx2Gene = vec![("txA", "g2"), ("txB", "g2"), ("txC", "g1")]
+            .into_iter()
+            .collect();
+        let recs = vec![
+            quant_rec("txA", 1000, 900.0, 250000.0, 30.0),
+            quant_rec("txB", 500, 400.0, 250000.0, 10.0),
+            quant_rec("txC", 2000, 1900.0, 500000.0, 75.5),
+        ];
+        let lens = gene_effective_lengths(&recs, &t2g);
+        let mut genes = HashMap::<String, QuantEntry>::new();
+        for r in &recs {
+            let g = t2g.gene(&r.name).unwrap();
+            let e = genes.entry(g.to_string()).or_insert(QuantEntry {
+                len: 0,
+                efflen: lens[g],
+                tpm: 0.0,
+                num_reads: 0.0,
+            });
+            e.len = e.len.max(r.len);
+            e.tpm += r.tpm;
+            e.num_reads += r.num_reads;
+        }
+
+        let mut out = Vec::new();
+        write_gene_quant(&genes, &mut out).unwrap();
+        assert!(String::from_utf8_lossy(&out)
+            .lines()
+            .nth(1)
+            .unwrap()
+            .starts_with("g1\t"));
+        let p = write_tmp("gene_quant.sf", &out);
+        let back = HashMap::<String, QuantEntry>::from_path(&p).unwrap();
+        assert_eq!(back, genes);
+    }
+
+    #[test]
+    fn decoy_share_is_dropped() {
+        // tx2 is a decoy
+        let ecs = collection(
+            3,
+            &[
+                (&[0, 2], &[0.25, 0.75], 8),
+                (&[0, 1, 2], &[0.2, 0.6, 0.2], 10),
+                (&[2], &[1.0], 50),
+            ],
+        );
+        let counts = ecs.target_counts_excluding_decoys(2);
+        assert_eq!(counts.len(), 2);
+        assert!((counts[0] - 10.5).abs() < 1e-12);
+        assert!((counts[1] - 7.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn targets_sharing_a_prefix() {
+        let mut ecs = collection(0, &[]);
+        ecs.targets = ["ENST02.1", "ENST01.2", "OTHER", "ENST01.1", "ENST1"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        ecs.ntarget = ecs.targets.len();
+
+        assert_eq!(ecs.targets_with_prefix("ENST01"), vec![1, 3]);
+        ecs.index_target_names();
+        assert_eq!(ecs.targets_with_prefix("ENST01"), vec![1, 3]);
+        assert_eq!(ecs.targets_with_prefix("ENST"), vec![0, 1, 3, 4]);
+        assert!(ecs.targets_with_prefix("ZZ").is_empty());
+    }
+
+    #[test]
+    fn ec_list_iter_yields_every_class_in_order() {
+        let mut ecs = EqClassList::new();
+        let classes = [
+            (vec![0], vec![1.0], 4),
+            (vec![1, 2], vec![0.25, 0.75], 9),
+            (vec![0, 2, 3], vec![0.2, 0.3, 0.5], 1),
+        ];
+        for (labels, weights, count) in classes.iter() {
+            ecs.push(EqClass {
+                labels: labels.clone(),
+                weights: weights.clone(),
+                count: *count,
+            });
+        }
+
+        let views: Vec<EqClassView> = ecs.iter().collect();
+        assert_eq!(views.len(), ecs.len());
+        for (v, (labels, weights, count)) in views.iter().zip(classes.iter()) {
+            assert_eq!(v.labels, &labels[..]);
+            assert_eq!(v.weights, &weights[..]);
+            assert_eq!(v.count, *count);
+        }
+    }
+
+    #[test]
+    fn sanitize_repairs_bad_weights() {
+        let p = write_tmp(
+            "nan_weights_eq_classes.txt",
+            b"3\n3\ntxA\ntxB\ntxC\n2 0 1 nan 0.5 4\n2 1 2 -1 -2 3\n1 2 1.0 5\n",
+        );
+        let plain = EqClassReader::new().read(&p).unwrap();
+        assert_eq!(plain.num_repaired, 0);
+        assert!(plain.collection.classes.weights[0].is_nan());
+
+        let fixed = EqClassReader::new().sanitize(true).read(&p).unwrap();
+        assert_eq!(fixed.num_repaired, 2);
+        assert_eq!(
+            fixed.collection.classes.weights,
+            vec![0.0, 1.0, 0.5, 0.5, 1.0]
+        );
+    }
+
+    fn write_bootstraps(dir: &Path, names: &[&str], values: &[f64]) {
+        let boot_dir = dir.join("aux_info/bootstrap");
+        create_dir_all(&boot_dir).unwrap();
+        let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+        for v in values {
+            gz.write_all(&v.to_le_bytes()).unwrap();
+        }
+        write(boot_dir.join("bootstraps.gz"), gz.finish().unwrap()).unwrap();
+        let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+        gz.write_all(names.join("\t").as_bytes()).unwrap();
+        write(boot_dir.join("names.tsv.gz"), gz.finish().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn read_bootstraps_from_salmon_files() {
+        let dir = salmon_dir("boot_read", 3);
+        write_bootstraps(&dir, &["txA", "txB"], &[1.0, 10.0, 2.0, 20.0, 3.5, 30.0]);
+        let boot = Bootstraps::from_salmon_files(&SalmonFiles::new(&dir)).unwrap();
+        assert_eq!(boot.num_bootstraps(), 3);
+        assert_eq!(boot.names, vec!["txA", "txB"]);
+        assert_eq!(boot.replicate(1), &[2.0, 20.0]);
+        assert_eq!(
+            boot.target_values(0).collect::<Vec<_>>(),
+            vec![1.0, 2.0, 3.5]
+        );
+
+        let short = salmon_dir("boot_short", 3);
+        write_bootstraps(&short, &["txA", "txB"], &[1.0, 2.0]);
+        let err = Bootstraps::from_salmon_files(&SalmonFiles::new(&short)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let missing = salmon_dir("boot_missing", 0);
+        let err = Bootstraps::from_salmon_files(&SalmonFiles::new(&missing)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert!(matches!(
+            Bootstraps::try_from_salmon_files(&SalmonFiles::new(&missing)),
+            Err(SeineError::NoInferentialReplicates(_))
+        ));
+
+        // dimensions whose product overflows
+        let huge = salmon_dir("boot_huge", 0);
+        write_tmp(
+            "boot_huge/aux_info/meta_info.json",
+            meta_info_json(u32::MAX, "")
+                .replace(
+                    "\"num_valid_targets\": 2",
+                    "\"num_valid_targets\": 4294967295",
+                )
+                .as_bytes(),
+        );
+        write_bootstraps(&huge, &[], &[1.0]);
+        let err = Bootstraps::from_salmon_files(&SalmonFiles::new(&huge)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let no_file = salmon_dir("boot_no_file", 3);
+        match Bootstraps::try_from_salmon_files(&SalmonFiles::new(&no_file)) {
+            Err(SeineError::MissingFile(p)) => assert!(p.ends_with("bootstraps.gz")),
+            other => panic!("expected MissingFile, got {:?}", other),
+        }
+        let err = Bootstraps::from_salmon_files(&SalmonFiles::new(&no_file)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn complexity_extrapolation_matches_observed_depth() {
+        let ecs = collection(
+            6,
+            &[
+                (&[0], &[1.0], 20),
+                (&[1], &[1.0], 1),
+                (&[2], &[1.0], 1),
+                (&[3], &[1.0], 2),
+                (&[4, 5], &[1.0, 0.0], 6),
+            ],
+        );
+        // tx5 has zero weight, so five targets are detected from 30 reads
+        let here = ecs.complexity_extrapolation(30, 7);
+        assert!((here - 5.0).abs() < 1e-9);
+
+        let shallow = ecs.complexity_extrapolation(10, 7);
+        let deep = ecs.complexity_extrapolation(300, 7);
+        assert!(shallow < here);
+        assert!(deep > here);
+        // Chao1 bound: 5 + 2^2 / (2 * 1)
+        assert!(deep <= 7.0 + 1e-9);
+    }
+
+    #[test]
+    fn bootstrap_summaries_and_quantiles() {
+        let boot = Bootstraps::new(
+            4,
+            2,
+            vec![
+                2.0, 0.0, //
+                4.0, 0.0, //
+                10.0, 0.0, //
+                8.0, 0.0,
+            ],
+        );
+        let s = boot.summarize();
+        assert!((s[0].mean - 6.0).abs() < 1e-12);
+        assert!((s[0].variance - 40.0 / 3.0).abs() < 1e-12);
+        let infrv = (40.0 / 3.0 - 6.0) / 11.0 + 0.01;
+        assert!((s[0].infrv - infrv).abs() < 1e-12);
+        assert_eq!(s[1].infrv, 0.0);
+
+        let q = boot.quantiles(&[0.0, 0.5, 0.25, 1.0]);
+        assert_eq!(q[0], vec![2.0, 6.0, 3.5, 10.0]);
+        assert_eq!(q[1], vec![0.0; 4]);
+    }
+
+    #[test]
+    fn diff_two_collections() {
+        let a = collection(
+            3,
+            &[
+                (&[0], &[1.0], 5),
+                (&[0, 1], &[0.5, 0.5], 3),
+                (&[2], &[1.0], 1),
+            ],
+        );
+        let b = collection(3, &[(&[1, 0], &[0.5, 0.5], 4), (&[0], &[1.0], 5)]);
+        let diff = eq_class_diff(&a, &b).unwrap();
+        assert_eq!(diff.only_in_a, vec![vec![2]]);
+        assert!(diff.only_in_b.is_empty());
+        assert_eq!(diff.count_changes, vec![(vec![0, 1], 3, 4)]);
+
+        assert!(eq_class_diff(&a, &collection(2, &[])).is_err());
+    }
+
+    #[test]
+    fn read_ambig_info() {
+        let dir = salmon_dir("ambig", 0);
+        write_tmp(
+            "ambig/aux_info/ambig_info.tsv",
+            b"UniqueCount\tAmbigCount\n3\t1\n0\t0\n0\t5\n",
+        );
+        let info = SalmonFiles::new(&dir).read_ambig().unwrap();
+        assert_eq!(info.unique, vec![3, 0, 0]);
+        assert_eq!(info.ambig, vec![1, 0, 5]);
+        assert_eq!(info.fraction_unique(0), 0.75);
+        assert_eq!(info.fraction_unique(1), 0.0);
+
+        let bad = write_tmp("bad_ambig_info.tsv", b"Unique\tAmbig\n3\t1\n");
+        let err = AmbigInfo::from_path(&bad).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("expected columns `UniqueCount\tAmbigCount`"));
+    }
+
+    #[test]
+    fn unique_fraction_per_target() {
+        let ecs = collection(
+            4,
+            &[
+                (&[0], &[1.0], 6),
+                (&[0, 1], &[0.5, 0.5], 4),
+                (&[2], &[1.0], 3),
+            ],
+        );
+        assert_eq!(ecs.unique_fraction(), vec![0.75, 0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn try_new_reports_bad_directories() {
+        match SalmonFiles::try_new(tmp_path("no_such_run")) {
+            Err(SeineError::NotADirectory(p)) => assert_eq!(p, tmp_path("no_such_run")),
+            other => panic!("expected NotADirectory, got {:?}", other),
+        }
+        let file = write_tmp("run_is_a_file", b"");
+        assert!(matches!(
+            SalmonFiles::try_new(&file),
+            Err(SeineError::NotADirectory(_))
+        ));
+
+        write_tmp("bad_meta/aux_info/meta_info.json", b"{ not json");
+        assert!(matches!(
+            SalmonFiles::try_new(tmp_path("bad_meta")),
+            Err(SeineError::MalformedMetaInfo { .. })
+        ));
+
+        create_dir_all(tmp_path("no_meta")).unwrap();
+        let sf = SalmonFiles::try_new(tmp_path("no_meta")).unwrap();
+        assert!(sf.eq_file.ends_with("eq_classes.txt"));
+    }
+
+    #[test]
+    fn build_collection_in_code() {
+        let mut b = EqClassCollectionBuilder::new();
+        let a = b.add_target("txA").unwrap();
+        let c = b.add_target("txB").unwrap();
+        b.add_class(vec![a], vec![1.0], 4).unwrap();
+        b.add_class(vec![a, c], vec![0.3, 0.7], 2).unwrap();
+        assert!(matches!(
+            b.add_class(vec![2], vec![1.0], 1),
+            Err(SeineError::InvalidEqClass { class: 2, .. })
+        ));
+        assert!(b.add_class(vec![0, 1], vec![1.0], 1).is_err());
+
+        let ecs = b.build();
+        assert_eq!(ecs.ntarget, 2);
+        assert_eq!(ecs.neq, 2);
+        let classes: Vec<(Vec<Label>, u32)> = ecs
+            .classes
+            .iter()
+            .map(|ec| (ec.labels.to_vec(), ec.count))
+            .collect();
+        assert_eq!(classes, vec![(vec![0], 4), (vec![0, 1], 2)]);
+    }
+
+    #[test]
+    fn eq_files_detected_by_content_not_name() {
+        let text: &[u8] = b"2\n1\ntxA\ntxB\n2 0 1 0.25 0.75 9\n";
+        let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+        gz.write_all(text).unwrap();
+        let gz = gz.finish().unwrap();
+
+        for (name, contents) in [
+            ("sample1.eq.gz", &gz[..]),
+            ("my_classes.gz", &gz[..]),
+            ("sample1.eq", text),
+        ] {
+            let p = write_tmp(name, contents);
+            let ecs = EqClassCollection::from_path(&p).unwrap();
+            assert_eq!(ecs.targets, vec!["txA", "txB"], "{}", name);
+            assert_eq!(ecs.classes.counts, vec![9], "{}", name);
+        }
+    }
+
+    #[test]
+    fn tpm_bins_by_quantile() {
+        let tpms = [0.0, 5.0, 1.0, 8.0, 0.0, 3.0, 3.0, 9.0];
+        let recs: Vec<QuantRecord> = tpms
+            .iter()
+            .enumerate()
+            .map(|(i, &t)| quant_rec(&format!("tx{}", i), 100, 80.0, t, 1.0))
+            .collect();
+        // expressed, ascending: 1 3 3 5 8 9 -> halves {1,3,3} and {5,8,9}
+        assert_eq!(tpm_quantile_bins(&recs, 2), vec![0, 2, 1, 2, 0, 1, 1, 2]);
+        assert_eq!(tpm_quantile_bins(&recs, 3), vec![0, 2, 1, 3, 0, 1, 1, 3]);
+    }
+
+    #[test]
+    fn read_eq_classes_without_weights() {
+        let p = write_tmp(
+            "unweighted_eq_classes.txt",
+            b"3\n2\ntxA\ntxB\ntxC\n1 2 7\n3 0 1 2 12\n",
+        );
+        let ecs = EqClassCollection::from_path(&p).unwrap();
+        assert_eq!(ecs.classes.labels, vec![2, 0, 1, 2]);
+        assert_eq!(ecs.classes.counts, vec![7, 12]);
+        let w = ecs.get(1).unwrap().weights;
+        assert!(w.iter().all(|&x| (x - 1.0 / 3.0).abs() < 1e-12));
+        assert_eq!(ecs.get(0).unwrap().weights, &[1.0]);
+    }
+
+    #[test]
+    fn sum_technical_replicates() {
+        let entry = |efflen: f64, num_reads: f64| QuantEntry {
+            len: 1000,
+            efflen,
+            tpm: 0.0,
+            num_reads,
+        };
+        let lane1: HashMap<String, QuantEntry> = vec![
+            ("txA".to_string(), entry(100.0, 10.0)),
+            ("txB".to_string(), entry(300.0, 0.0)),
+        ]
+        .into_iter()
+        .collect();
+        let lane2: HashMap<String, QuantEntry> = vec![
+            ("txA".to_string(), entry(100.0, 20.0)),
+            ("txB".to_string(), entry(300.0, 90.0)),
+        ]
+        .into_iter()
+        .collect();
+
+        let merged = sum_quant_replicates(&[lane1.clone(), lane2]).unwrap();
+        assert_eq!(merged["txA"].num_reads, 30.0);
+        assert_eq!(merged["txB"].num_reads, 90.0);
+        // rates 0.3 and 0.3 -> equal TPM
+        assert!((merged["txA"].tpm - 500000.0).abs() < 1e-6);
+        assert!((merged["txB"].tpm - 500000.0).abs() < 1e-6);
+
+        let mut other = lane1.clone();
+        other.remove("txB");
+        other.insert("txC".to_string(), entry(50.0, 1.0));
+        assert!(matches!(
+            sum_quant_replicates(&[lane1, other]),
+            Err(SeineError::MissingTarget { input: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn stream_eq_classes_through_callback() {
+        let text: &[u8] = b"3\n3\ntxA\ntxB\ntxC\n1 0 1.0 5\n2 0 2 0.5 0.5 4\n1 1 1.0 2\n";
+        let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+        gz.write_all(text).unwrap();
+        let gz = gz.finish().unwrap();
+
+        for (name, contents) in [("stream_eq.txt", text), ("stream_eq.txt.gz", &gz[..])] {
+            let p = write_tmp(name, contents);
+            let mut total = 0;
+            let mut mass = vec![0.0; 3];
+            let header = for_each_eq_class(&p, |ec| {
+                total += ec.count;
+                for (&l, w) in ec.labels.iter().zip(ec.weights) {
+                    mass[l as usize] += w * ec.count as f64;
+                }
+            })
+            .unwrap();
+            assert_eq!(header.targets, vec!["txA", "txB", "txC"]);
+            assert_eq!(header.neq, 3);
+            assert_eq!(total, 11);
+            assert_eq!(mass, vec![7.0, 2.0, 2.0]);
+        }
+    }
+
+    #[test]
+    fn eq_classes_from_in_memory_reader() {
+        let text: &[u8] = b"2\n2\ntxA\ntxB\n1 1 1.0 3\n2 0 1 0.5 0.5 6\n";
+        let ecs = EqClassCollection::from_reader(text, false).unwrap();
+        assert_eq!(ecs.targets, vec!["txA", "txB"]);
+        assert_eq!(ecs.classes.counts, vec![3, 6]);
+
+        let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+        gz.write_all(text).unwrap();
+        let gz = gz.finish().unwrap();
+        let ecs = EqClassCollection::from_reader(&gz[..], true).unwrap();
+        assert_eq!(ecs.classes.labels, vec![1, 0, 1]);
+    }
+
+    #[test]
+    fn long_batches_cover_every_row() {
+        let ecs = collection(
+            3,
+            &[
+                (&[0], &[1.0], 5),
+                (&[0, 1, 2], &[0.2, 0.3, 0.5], 3),
+                (&[1, 2], &[0.5, 0.5], 7),
+            ],
+        );
+        let batches: Vec<EqClassBatch> = ecs.to_long_batches(4).collect();
+        assert_eq!(
+            batches.iter().map(|b| b.len()).collect::<Vec<_>>(),
+            vec![4, 2]
+        );
+        assert_eq!(batches[0].eq_id, vec![0, 1, 1, 1]);
+        assert_eq!(batches[1].eq_id, vec![2, 2]);
+        assert_eq!(batches[1].target_id, vec![1, 2]);
+        assert_eq!(batches[1].count, vec![7, 7]);
+        let rows: usize = batches.iter().map(|b| b.len()).sum();
+        assert_eq!(rows, ecs.classes.labels.len());
+    }
+
+    fn eq_parse_error(text: &[u8]) -> EqParseError {
+        let err = EqClassCollection::from_reader(text, false).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        err.get_ref()
+            .and_then(|e| e.downcast_ref::<EqParseError>())
+            .cloned()
+            .expect("an EqParseError")
+    }
+
+    #[test]
+    fn broken_eq_files_are_reported() {
+        assert_eq!(
+            eq_parse_error(b"abc\n1\ntxA\n1 0 1.0 3\n"),
+            EqParseError::InvalidField {
+                line: 1,
+                expected: "number of targets".to_string(),
+                found: "abc".to_string(),
+            }
+        );
+        // killed mid-write: the second class is missing
+        assert_eq!(
+            eq_parse_error(b"1\n2\ntxA\n1 0 1.0 3\n"),
+            EqParseError::ClassCount {
+                declared: 2,
+                found: 1,
+            }
+        );
+        assert_eq!(
+            eq_parse_error(b"2\n2\ntxA\ntxB\n1 0 1.0 3\n2 0 1 0.5 9\n"),
+            EqParseError::TokenCount {
+                line: 6,
+                labels: 2,
+                found: 5,
+            }
+        );
+        let err = eq_parse_error(b"2\n1\ntxA\ntxB\n2 0 x 0.5 0.5 9\n");
+        assert_eq!(
+            err.to_string(),
+            "expected target label on line 5, found `x`"
+        );
+    }
+
+    #[test]
+    fn unique_class_fraction_counts_classes() {
+        let ecs = collection(
+            3,
+            &[
+                (&[0], &[1.0], 100),
+                (&[0, 1], &[0.5, 0.5], 1),
+                (&[2], &[1.0], 1),
+                (&[1, 2], &[0.5, 0.5], 1),
+            ],
+        );
+        assert_eq!(ecs.unique_class_fraction(), 0.5);
+        assert!(collection(1, &[]).unique_class_fraction().is_nan());
+    }
+
+    #[test]
+    fn quant_table_keeps_file_order() {
+        let recs = vec![
+            quant_rec("txC", 300, 250.0, 10.0, 2.0),
+            quant_rec("txA", 100, 50.0, 30.0, 1.0),
+            quant_rec("txB", 200, 150.0, 60.0, 9.0),
+        ];
+        let p = write_quant("quant_table.sf", &recs);
+        let table = QuantTable::from_path(&p).unwrap();
+        assert_eq!(table.len(), 3);
+        assert_eq!(table.names, vec!["txC", "txA", "txB"]);
+        assert_eq!(table.index_of("txB"), Some(2));
+        assert_eq!(table.index_of("txZ"), None);
+
+        let declared = ["txA", "txB", "txC"];
+        let dense: Vec<f64> = declared
+            .iter()
+            .map(|n| table.tpm[table.index_of(n).unwrap()])
+            .collect();
+        assert_eq!(dense, vec![30.0, 60.0, 10.0]);
+    }
+
+    #[test]
+    fn recompute_offsets_after_raw_edits() {
+        let mut ecs = EqClassList::new();
+        ecs.push(EqClass {
+            labels: vec![0, 1],
+            weights: vec![0.5, 0.5],
+            count: 3,
+        });
+        ecs.labels.extend_from_slice(&[2, 0, 1, 2]);
+        ecs.weights.extend_from_slice(&[1.0, 0.2, 0.3, 0.5]);
+        ecs.counts.extend_from_slice(&[4, 8]);
+
+        assert!(matches!(
+            ecs.recompute_offsets(&[2, 1, 2]),
+            Err(SeineError::LayoutMismatch { what: "labels", .. })
+        ));
+        assert_eq!(ecs.offsets, vec![0, 2]);
+
+        ecs.recompute_offsets(&[2, 1, 3]).unwrap();
+        assert_eq!(ecs.offsets, vec![0, 2, 3, 6]);
+        assert_eq!(ecs.len(), 3);
+        let last = ecs.get(2).unwrap();
+        assert_eq!(last.labels, &[0, 1, 2]);
+        assert_eq!(last.count, 8);
+    }
+
+    #[test]
+    fn read_gzipped_quant_sf() {
+        let recs = vec![
+            quant_rec("txA", 100, 50.0, 600000.0, 6.0),
+            quant_rec("txB", 200, 150.0, 400000.0, 12.0),
+        ];
+        let mut plain = Vec::new();
+        QuantWriter::new().write(&recs, &mut plain).unwrap();
+        let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+        gz.write_all(&plain).unwrap();
+        salmon_dir("gz_quant", 0);
+        write_tmp("gz_quant/quant.sf.gz", &gz.finish().unwrap());
+
+        let sf = SalmonFiles::new(tmp_path("gz_quant"));
+        assert!(sf.quant_file.ends_with("quant.sf.gz"));
+        verify_quant_header(&sf.quant_file).unwrap();
+        let map = HashMap::<String, QuantEntry>::from_path(&sf.quant_file).unwrap();
+        assert_eq!(map["txB"].num_reads, 12.0);
+        let table = QuantTable::from_path(&sf.quant_file).unwrap();
+        assert_eq!(table.names, vec!["txA", "txB"]);
+    }
+
+    #[test]
+    fn cross_sample_replicate_correlation() {
+        // target 1 is the one compared; target 0 is noise
+        let s1 = Bootstraps::new(4, 2, vec![9.0, 1.0, 0.0, 2.0, 5.0, 3.0, 1.0, 4.0]);
+        let s2 = Bootstraps::new(4, 2, vec![0.0, 2.0, 0.0, 4.0, 0.0, 6.0, 0.0, 8.0]);
+        let s3 = Bootstraps::new(4, 2, vec![0.0, 8.0, 0.0, 6.0, 0.0, 4.0, 0.0, 2.0]);
+        let m = cross_sample_irv(&[s1, s2, s3], 1).unwrap();
+        assert_eq!(m.len(), 3);
+        for (i, row) in m.iter().enumerate() {
+            assert!((row[i] - 1.0).abs() < 1e-12);
+        }
+        assert!((m[0][1] - 1.0).abs() < 1e-12);
+        assert!((m[1][2] + 1.0).abs() < 1e-12);
+        assert_eq!(m[0][2], m[2][0]);
+
+        let short = Bootstraps::new(2, 2, vec![0.0; 4]);
+        let long = Bootstraps::new(3, 2, vec![0.0; 6]);
+        assert!(cross_sample_irv(&[short, long], 0).is_err());
+
+        let narrow = Bootstraps::new(2, 1, vec![1.0, 2.0]);
+        let wide = Bootstraps::new(2, 3, vec![0.0; 6]);
+        assert!(matches!(
+            cross_sample_irv(&[wide, narrow], 2),
+            Err(SeineError::TargetOutOfRange {
+                index: 2,
+                ntarget: 1
+            })
+        ));
+    }
+
+    #[test]
+    fn quant_table_gene_rollup() {
+        let mut table = QuantTable::default();
+        for r in [
+            quant_rec("txA", 1100, 1000.0, 20.0, 30.0),
+            quant_rec("txB", 600, 500.0, 10.0, 10.0),
+            quant_rec("txC", 300, 200.0, 0.0, 0.0),
+            quant_rec("txD", 500, 400.0, 0.0, 0.0),
+            quant_rec("txE", 700, 600.0, 5.0, 2.0),
+        ] {
+            table.push(r);
+        }
+        let t2g: Tx2Gene = vec![("txC", "g2"), ("txA", "g1"), ("txB", "g1"), ("txD", "g2")]
+            .into_iter()
+            .collect();
+
+        let genes = table.aggregate_to_gene(&t2g, None);
+        assert_eq!(genes.names, vec!["g1", "g2"]);
+        assert_eq!(genes.num_reads, vec![40.0, 0.0]);
+        assert_eq!(genes.tpm, vec![30.0, 0.0]);
+        assert_eq!(genes.efflen, vec![875.0, 300.0]);
+        assert_eq!(genes.len, vec![975, 400]);
+
+        let pooled = table.aggregate_to_gene(&t2g, Some("unassigned"));
+        assert_eq!(pooled.index_of("unassigned"), Some(2));
+        assert_eq!(pooled.num_reads[2], 2.0);
+    }
+
+    #[test]
+    fn scratch_parsing_matches_per_class_push() {
+        let text: &[u8] = b"3\n4\ntxA\ntxB\ntxC\n1 0 1.0 5\n3 0 1 2 0.2 0.3 0.5 4\n\
+                            2 1 2 0.5 0.5 9\n1 2 1.0 1\n";
+        let parsed = EqClassCollection::from_reader(text, false).unwrap();
+
+        let mut expected = EqClassList::new();
+        for (labels, weights, count) in [
+            (vec![0], vec![1.0], 5),
+            (vec![0, 1, 2], vec![0.2, 0.3, 0.5], 4),
+            (vec![1, 2], vec![0.5, 0.5], 9),
+            (vec![2], vec![1.0], 1),
+        ] {
+            expected.push(EqClass {
+                labels,
+                weights,
+                count,
+            });
+        }
+        assert_eq!(parsed.classes.offsets, expected.offsets);
+        assert_eq!(parsed.classes.labels, expected.labels);
+        assert_eq!(parsed.classes.weights, expected.weights);
+        assert_eq!(parsed.classes.counts, expected.counts);
+    }
+
+    #[test]
+    fn read_transcript_groups() {
+        let p = write_tmp("groups_ids.txt", b"4\t7\t9\n2,3\n");
+        let g = TranscriptGroups::from_path(&p).unwrap();
+        assert_eq!(g.ids, GroupIds::Indices);
+        assert_eq!(g.len(), 2);
+        assert_eq!(g.members(4), &[7, 9]);
+        assert_eq!(g.representative_of(9), Some(4));
+        assert_eq!(g.representative_of(2), Some(2));
+        assert_eq!(g.representative_of(5), None);
+        assert!(g.members(7).is_empty());
+        assert_eq!(g.id_of("4"), None);
+
+        let p = write_tmp("groups_names.txt", b"txA txB txC\ntxD txE\n");
+        let g = TranscriptGroups::from_path(&p).unwrap();
+        let (a, c, e) = (
+            g.id_of("txA").unwrap(),
+            g.id_of("txC").unwrap(),
+            g.id_of("txE").unwrap(),
+        );
+        assert_eq!(g.representative_of(c), Some(a));
+        assert_eq!(g.members(g.id_of("txD").unwrap()), &[e]);
+        assert_eq!(g.id_of("txZ"), None);
+        match &g.ids {
+            GroupIds::Names(names) => assert_eq!(names.len(), 5),
+            GroupIds::Indices => panic!("expected names"),
+        }
+
+        let empty = TranscriptGroups::from_path(write_tmp("groups_empty.txt", b"")).unwrap();
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn gene_tpm_mean_and_sd() {
+        let names: Vec<String> = ["txA", "txB", "txC"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let t2g: Tx2Gene = vec![("txA", "g1"), ("txB", "g1"), ("txC", "g2")]
+            .into_iter()
+            .collect();
+        // rates with efflen (100, 100, 200): (1,1,2) then (3,1,0)
+        let boots = Bootstraps::new(2, 3, vec![100.0, 100.0, 400.0, 300.0, 100.0, 0.0]);
+        let res = gene_tpm_with_uncertainty(&boots, &[100.0, 100.0, 200.0], &t2g, &names);
+
+        // g1 TPM per replicate: 500000 and 1000000
+        let (mean, sd) = res["g1"];
+        assert!((mean - 750000.0).abs() < 1e-6);
+        assert!((sd - 250000.0 * 2.0_f64.sqrt()).abs() < 1e-6);
+        let (mean, _) = res["g2"];
+        assert!((mean - 250000.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn target_names_round_trip() {
+        let ecs = collection(4, &[(&[0, 2], &[0.5, 0.5], 3), (&[3], &[1.0], 1)]);
+        let index = ecs.target_index();
+        for name in ["tx0", "tx2", "tx3"] {
+            let label = index[name];
+            assert_eq!(ecs.label_name(label), Some(name));
+        }
+        assert_eq!(ecs.label_name(4), None);
+
+        let tx2 = index["tx2"];
+        let containing: Vec<usize> = (0..ecs.classes.len())
+            .filter(|&i| ecs.get(i).unwrap().labels.contains(&(tx2 as Label)))
+            .collect();
+        assert_eq!(containing, vec![0]);
+    }
+
+    #[test]
+    fn read_lengths_only() {
+        let p = write_quant(
+            "lengths_quant.sf",
+            &[
+                quant_rec("txA", 1500, 1320.5, 12.0, 3.0),
+                quant_rec("txB", 90, 1.0, 0.0, 0.0),
+            ],
+        );
+        let lengths = read_transcript_lengths(&p).unwrap();
+        assert_eq!(lengths.len(), 2);
+        assert_eq!(lengths["txA"], 1500);
+        assert_eq!(lengths["txB"], 90);
+    }
+
+    #[test]
+    fn classes_containing_a_target() {
+        let ecs = collection(
+            4,
+            &[
+                (&[0, 1], &[0.5, 0.5], 3),
+                (&[1], &[1.0], 2),
+                (&[2, 1], &[0.5, 0.5], 8),
+            ],
+        );
+        let hits: Vec<(usize, u32)> = ecs
+            .classes_with_target(1)
+            .map(|(i, ec)| (i, ec.count))
+            .collect();
+        assert_eq!(hits, vec![(0, 3), (1, 2), (2, 8)]);
+        assert_eq!(ecs.classes_with_target(3).count(), 0);
+        assert_eq!(ecs.classes_with_target(99).count(), 0);
+
+        let index = ecs.build_target_to_classes();
+        assert_eq!(index, vec![vec![0], vec![0, 1, 2], vec![2], vec![]]);
+    }
+
+    #[test]
+    fn sparse_triplets() {
+        let ecs = collection(3, &[(&[2], &[1.0], 4), (&[0, 2], &[0.25, 0.75], 8)]);
+        let m = ecs.to_sparse(SparseValues::WeightedCount);
+        assert_eq!((m.nrows, m.ncols), (3, 2));
+        assert_eq!(m.rows, vec![2, 0, 2]);
+        assert_eq!(m.cols, vec![0, 1, 1]);
+        assert_eq!(m.vals, vec![4.0, 2.0, 6.0]);
+        assert_eq!(
+            ecs.to_sparse(SparseValues::Weight).vals,
+            vec![1.0, 0.25, 0.75]
+        );
+    }
+
+    #[test]
+    fn collapse_identical_classes() {
+        let ecs = collection(
+            3,
+            &[
+                (&[0, 1], &[0.2, 0.8], 1),
+                (&[2], &[1.0], 5),
+                (&[1, 0], &[0.4, 0.6], 3),
+            ],
+        );
+        let merged = ecs.classes.collapse_identical().unwrap();
+        assert_eq!(merged.len(), 2);
+        let ec = merged.get(0).unwrap();
+        assert_eq!(ec.labels, &[0, 1]);
+        assert_eq!(ec.count, 4);
+        assert!((ec.weights[0] - (0.2 + 3.0 * 0.6) / 4.0).abs() < 1e-12);
+        assert!((ec.weights[1] - (0.8 + 3.0 * 0.4) / 4.0).abs() < 1e-12);
+        assert_eq!(merged.get(1).unwrap().labels, &[2]);
+
+        let unique = collection(2, &[(&[0], &[1.0], 2), (&[0, 1], &[0.5, 0.5], 1)]);
+        assert_eq!(unique.classes.collapse_identical().unwrap(), unique.classes);
+
+        let deep = collection(
+            2,
+            &[
+                (&[0], &[1.0], 7),
+                (&[0, 1], &[0.5, 0.5], u32::MAX),
+                (&[1, 0], &[0.5, 0.5], 1),
+            ],
+        );
+        assert!(matches!(
+            deep.classes.collapse_identical(),
+            Err(SeineError::InvalidEqClass { class: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn collapsed_mass_sums_members() {
+        let p = write_tmp("groups_mass.txt", b"txA txB txC\n");
+        let g = TranscriptGroups::from_path(&p).unwrap();
+        let quant: HashMap<String, QuantEntry> = [("txA", 10.0), ("txB", 2.5), ("txC", 4.0)]
+            .iter()
+            .map(|&(n, r)| {
+                let q = QuantEntry {
+                    len: 100,
+                    efflen: 100.0,
+                    tpm: 0.0,
+                    num_reads: r,
+                };
+                (n.to_string(), q)
+            })
+            .collect();
+        let mass = collapsed_read_mass(&quant, &g).unwrap();
+        assert_eq!(mass.len(), 1);
+        assert_eq!(mass["txA"], 16.5);
+
+        let p = write_tmp("groups_mass_indexed.txt", b"0 1 2\n");
+        let g = TranscriptGroups::from_path(&p).unwrap();
+        assert!(matches!(
+            collapsed_read_mass(&quant, &g),
+            Err(SeineError::IndexedGroups)
+        ));
+    }
+
+    #[test]
+    fn filter_classes_by_count() {
+        let ecs = collection(
+            3,
+            &[
+                (&[0], &[1.0], 1),
+                (&[0, 1], &[0.5, 0.5], 5),
+                (&[2], &[1.0], 2),
+                (&[1, 2], &[0.3, 0.7], 9),
+            ],
+        );
+        let kept = ecs.filter_by_count(3);
+        assert_eq!(kept.neq, 2);
+        assert_eq!(kept.classes.len(), 2);
+        assert_eq!(kept.classes.offsets, vec![0, 2, 4]);
+        let views: Vec<(Vec<Label>, u32)> = kept
+            .classes
+            .iter()
+            .map(|ec| (ec.labels.to_vec(), ec.count))
+            .collect();
+        assert_eq!(views, vec![(vec![0, 1], 5), (vec![1, 2], 9)]);
+    }
+
+    #[test]
+    fn gene_entropy_of_two_genes() {
+        let t2g: Tx2Gene = vec![("t1", "g1"), ("t2", "g1"), ("t3", "g2")]
+            .into_iter()
+            .collect();
+        let records = vec![
+            quant_rec("t1", 100, 100.0, 100_000.0, 0.0),
+            quant_rec("t2", 100, 100.0, 150_000.0, 0.0),
+            quant_rec("t3", 100, 100.0, 750_000.0, 0.0),
+            quant_rec("unmapped", 100, 100.0, 5.0, 0.0),
+        ];
+        // gene proportions 0.25 and 0.75
+        let expected = -(0.25 * 0.25_f64.log2() + 0.75 * 0.75_f64.log2());
+        assert!((gene_expression_entropy(&records, &t2g) - expected).abs() < 1e-12);
+        assert_eq!(gene_expression_entropy(&[], &t2g), 0.0);
+    }
+
+    #[test]
+    fn merge_cohort_classes() {
+        let a = collection(3, &[(&[0, 1], &[0.5, 0.5], 4), (&[2], &[1.0], 1)]);
+        let b = collection(3, &[(&[2], &[1.0], 7), (&[1, 0], &[0.2, 0.8], 3)]);
+        let c = collection(3, &[(&[1], &[1.0], 2)]);
+        let merged = EqClassCollection::merge(&[a, b, c]).unwrap();
+        assert_eq!(merged.targets.len(), 3);
+        assert_eq!(merged.label_sets, vec![vec![0, 1], vec![2], vec![1]]);
+        assert_eq!(
+            merged.counts,
+            vec![vec![4, 3, 0], vec![1, 7, 0], vec![0, 0, 2]]
+        );
+
+        let a = collection(2, &[(&[0], &[1.0], 1)]);
+        let mut b = collection(2, &[(&[0], &[1.0], 1)]);
+        b.targets[1] = "other".to_string();
+        match EqClassCollection::merge(&[a, b]) {
+            Err(SeineError::TargetNameMismatch { index, .. }) => assert_eq!(index, 1),
+            other => panic!("expected a name mismatch, got {:?}", other),
+        }
+        let short = collection(1, &[]);
+        assert!(matches!(
+            EqClassCollection::merge(&[collection(2, &[]), short]),
+            Err(SeineError::TargetCountMismatch {
+                expected: 2,
+                found: 1
+            })
+        ));
+
+        let deep = collection(
+            2,
+            &[(&[0, 1], &[0.5, 0.5], u32::MAX), (&[1, 0], &[0.5, 0.5], 1)],
+        );
+        assert!(matches!(
+            EqClassCollection::merge(&[collection(2, &[]), deep]),
+            Err(SeineError::InvalidEqClass { class: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn stream_filter_by_count() {
+        let input = "3\n3\ntxA\ntxB\ntxC\n1 0 1.0 1\n2 0 1 0.4 0.6 5\n1 2 1.0 9\n";
+        let mut out = Vec::new();
+        filter_eq_classes_streaming(io::Cursor::new(input), &mut out, |ec| ec.count >= 5).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.starts_with("3\n2\ntxA\ntxB\ntxC\n"));
+
+        let ecs = EqClassCollection::from_reader(text.as_bytes(), false).unwrap();
+        assert_eq!(ecs.neq, 2);
+        assert_eq!(ecs.classes.counts, vec![5, 9]);
+        assert_eq!(ecs.get(0).unwrap().labels, &[0, 1]);
+        assert_eq!(ecs.get(0).unwrap().weights, &[0.4, 0.6]);
+
+        // the same filter over a gzipped file, which is reopened rather
+        // than rewound
+        let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+        gz.write_all(input.as_bytes()).unwrap();
+        let p = write_tmp("eq_filter.txt.gz", &gz.finish().unwrap());
+        let mut out = Vec::new();
+        filter_eq_file(&p, &mut out, |ec| ec.count >= 5).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), text);
+    }
+
+    #[test]
+    fn validate_labels_and_layout() {
+        let ecs = collection(2, &[(&[0, 1], &[0.5, 0.5], 3)]);
+        ecs.validate().unwrap();
+
+        let p = write_tmp(
+            "eq_bad_label.txt",
+            b"2\n2\ntxA\ntxB\n1 0 1.0 2\n2 0 5 0.5 0.5 1\n",
+        );
+        EqClassCollection::from_path(&p).unwrap();
+        match EqClassCollection::from_path_validated(&p) {
+            Err(SeineError::InvalidEqClass { class, reason }) => {
+                assert_eq!(class, 1);
+                assert!(reason.contains("label 5"));
+            }
+            other => panic!("expected an invalid class, got {:?}", other),
+        }
+
+        let mut short = collection(2, &[(&[0], &[1.0], 1)]);
+        short.neq = 2;
+        assert!(matches!(
+            short.validate(),
+            Err(SeineError::LayoutMismatch {
+                what: "classes",
+                expected: 2,
+                found: 1
+            })
+        ));
+
+        let mut unordered = collection(2, &[(&[0], &[1.0], 1), (&[0, 1], &[0.5, 0.5], 1)]);
+        unordered.classes.offsets = vec![0, 3, 1];
+        unordered.classes.labels.push(1);
+        unordered.classes.weights.push(0.0);
+        match unordered.validate() {
+            Err(SeineError::InvalidEqClass { class, reason }) => {
+                assert_eq!(class, 1);
+                assert!(reason.contains("decrease"));
+            }
+            other => panic!("expected an invalid class, got {:?}", other),
+        }
+        unordered.classes.offsets = Vec::new();
+        assert!(matches!(
+            unordered.validate(),
+            Err(SeineError::InvalidEqClass { class: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn naive_counts_split_by_weight() {
+        let ecs = collection(4, &[(&[0, 1], &[0.25, 0.75], 8), (&[1, 2], &[0.5, 0.5], 2)]);
+        assert_eq!(ecs.naive_counts(), vec![2.0, 7.0, 1.0, 0.0]);
+
+        let parsed =
+            EqClassCollection::from_reader(&b"3\n1\na\nb\nc\n2 0 2 6\n"[..], false).unwrap();
+        assert_eq!(parsed.naive_counts(), vec![3.0, 0.0, 3.0]);
+    }
+
+    #[test]
+    fn count_matrix_cv() {
+        let m = CountMatrix::new(
+            vec!["g1".to_string(), "g2".to_string(), "g3".to_string()],
+            (1..=3).map(|s| format!("s{}", s)).collect(),
+            vec![1.0, 2.0, 3.0, 0.0, 0.0, 0.0, 0.0, 0.0, 6.0],
+        );
+        let cv = m.row_cv();
+        assert!((cv[0] - 0.5).abs() < 1e-12);
+        assert!(cv[1].is_nan());
+        assert!((cv[2] - 12_f64.sqrt() / 2.0).abs() < 1e-12);
+        assert_eq!(m.top_cv_features(5), vec![2, 0]);
+        assert_eq!(m.top_cv_features(1), vec![2]);
+    }
+
+    #[test]
+    fn read_cmd_info() {
+        let dir = salmon_dir("cmd_info", 0);
+        let sf = SalmonFiles::new(&dir);
+        assert!(matches!(
+            sf.read_cmd_info(),
+            Err(SeineError::MissingFile(_))
+        ));
+
+        write_tmp(
+            "cmd_info/cmd_info.json",
+            br#"{"salmon_version": "1.10.1", "index": "idx", "libType": "A",
+                "mates1": "r1.fq.gz", "mates2": ["r2a.fq.gz", "r2b.fq.gz"],
+                "threads": "8", "validateMappings": ""}"#,
+        );
+        let ci = sf.read_cmd_info().unwrap();
+        assert_eq!(ci.salmon_version.as_deref(), Some("1.10.1"));
+        assert_eq!(ci.lib_type.as_deref(), Some("A"));
+        assert_eq!(ci.mates1, Some(vec!["r1.fq.gz".to_string()]));
+        assert_eq!(ci.mates2.as_ref().map(|m| m.len()), Some(2));
+        assert_eq!(ci.unmated_reads, None);
+        assert_eq!(ci.extra["threads"], "8");
+        assert!(ci.extra.contains_key("validateMappings"));
+    }
+
+    #[test]
+    fn quant_integrity_issues() {
+        use IntegrityIssueKind::*;
+        let recs = vec![
+            quant_rec("ok", 100, 80.5, 1.0, 2.0),
+            quant_rec("rounded", 100, 100.6, 1.0, 2.0),
+            quant_rec("empty", 0, 0.0, 0.0, 0.0),
+            quant_rec("long", 100, 150.0, 1.0, 2.0),
+            quant_rec("nan", 100, 80.0, f64::NAN, f64::INFINITY),
+        ];
+        let issues = quant_integrity(&recs);
+        let found: Vec<(&str, IntegrityIssueKind)> =
+            issues.iter().map(|i| (i.name.as_str(), i.kind)).collect();
+        assert_eq!(
+            found,
+            vec![
+                ("empty", ZeroLength),
+                ("empty", NonPositiveEffectiveLength),
+                ("long", EffectiveLengthExceedsLength),
+                ("nan", NonFiniteTpm),
+                ("nan", NonFiniteNumReads),
+            ]
+        );
+    }
+
+    #[test]
+    fn meta_info_mapping_fields() {
+        let dir = salmon_dir("meta_old", 0);
+        let mi = SalmonFiles::new(&dir).read_meta_info().unwrap();
+        assert_eq!(mi.num_processed, None);
+        assert_eq!(mi.percent_mapped, None);
+        assert_eq!(mi.library_types, None);
+
+        let p = write_tmp(
+            "meta_new.json",
+            br#"{"num_valid_targets": 2, "serialized_eq_classes": false,
+                "num_bootstraps": 0, "num_eq_classes": 1,
+                "eq_class_properties": [], "samp_type": "none",
+                "num_processed": 1000, "num_mapped": 850, "percent_mapped": 85.0,
+                "num_decoy_fragments": 3, "library_types": ["ISR"],
+                "frag_length_mean": 212.5, "frag_length_sd": 40.25}"#,
+        );
+        let mi = MetaInfo::from_path(&p).unwrap();
+        assert_eq!((mi.num_processed, mi.num_mapped), (Some(1000), Some(850)));
+        assert_eq!(mi.percent_mapped, Some(85.0));
+        assert_eq!(mi.num_decoy_fragments, Some(3));
+        assert_eq!(mi.library_types, Some(vec!["ISR".to_string()]));
+        assert_eq!(
+            (mi.frag_length_mean, mi.frag_length_sd),
+            (Some(212.5), Some(40.25))
+        );
+    }
+
+    #[test]
+    fn stack_bootstrap_tensor() {
+        let a = salmon_dir("tensor_a", 2);
+        write_bootstraps(&a, &["txA", "txB"], &[1.0, 2.0, 3.0, 4.0]);
+        let b = salmon_dir("tensor_b", 2);
+        write_bootstraps(&b, &["txA", "txB"], &[5.0, 6.0, 7.0, 8.0]);
+        let t = load_bootstrap_tensor(&[a.clone(), b]).unwrap();
+        assert_eq!((t.nsample, t.nboot, t.ntarget), (2, 2, 2));
+        assert_eq!(t.names, vec!["txA", "txB"]);
+        assert_eq!(t.get(1, 0, 1), 6.0);
+        assert_eq!(t.sample(0), &[1.0, 2.0, 3.0, 4.0]);
 
-impl FromPathExt for HashMap<String, QuantEntry> {
-    fn from_path<P: AsRef<Path>>(p: P) -> Result<HashMap<String, QuantEntry>, csv::Error> {
-        // TODO error handling for csv::Error and io::Error
-        let file = File::open(p);
-        let mut rdr = csv::ReaderBuilder::new()
-            .delimiter(b'\t')
-            .from_reader(file.unwrap());
+        let renamed = salmon_dir("tensor_renamed", 2);
+        write_bootstraps(&renamed, &["txA", "txZ"], &[5.0, 6.0, 7.0, 8.0]);
+        assert!(matches!(
+            load_bootstrap_tensor(&[a.clone(), renamed]),
+            Err(SeineError::TargetNameMismatch { index: 1, .. })
+        ));
 
-        let mut quant_map = HashMap::new();
+        let fewer = salmon_dir("tensor_fewer", 1);
+        write_bootstraps(&fewer, &["txA", "txB"], &[5.0, 6.0]);
+        assert!(matches!(
+            load_bootstrap_tensor(&[a, fewer]),
+            Err(SeineError::LayoutMismatch {
+                what: "replicates",
+                expected: 2,
+                found: 1
+            })
+        ));
+    }
 
-        for quant_record in rdr.deserialize() {
-            let quant_record: QuantRecord = quant_record?;
-            let quant_entry = QuantEntry {
-                len: quant_record.len,
-                efflen: quant_record.efflen,
-                tpm: quant_record.tpm,
-                num_reads: quant_record.num_reads,
-            };
+    #[test]
+    fn sample_qc_summary() {
+        let dir = tmp_path("qc_run");
+        write_tmp(
+            "qc_run/aux_info/meta_info.json",
+            br#"{"num_valid_targets": 2, "serialized_eq_classes": false,
+                "num_bootstraps": 0, "num_eq_classes": 12,
+                "eq_class_properties": [], "samp_type": "none",
+                "num_processed": 1000, "num_mapped": 850, "percent_mapped": 85.0,
+                "frag_length_mean": 212.5, "frag_length_sd": 40.25}"#,
+        );
+        write_tmp(
+            "qc_run/aux_info/ambig_info.tsv",
+            b"UniqueCount\tAmbigCount\n30\t10\n0\t60\n",
+        );
+        let qc = SampleQc::from_salmon_files(&SalmonFiles::new(&dir)).unwrap();
+        assert_eq!(qc.num_eq_classes, Some(12));
+        assert_eq!(qc.unique_fraction, Some(0.3));
+        assert_eq!(
+            qc.to_string(),
+            "mapped 85.0% (850/1000), fragment length 212.5 ± 40.2, \
+             unique 30.0%, ambiguous 70.0%, 12 eq classes"
+        );
 
-            quant_map.insert(quant_record.name.to_string(), quant_entry);
+        let bare = tmp_path("qc_bare");
+        create_dir_all(&bare).unwrap();
+        let qc = SampleQc::from_salmon_files(&SalmonFiles::new(&bare)).unwrap();
+        assert_eq!(qc, SampleQc::default());
+        assert!(qc.to_string().starts_with("mapped NA (NA/NA)"));
+    }
+
+    #[test]
+    fn filter_classes_by_labels() {
+        let ecs = collection(
+            4,
+            &[
+                (&[0], &[1.0], 1),
+                (&[0, 1], &[0.5, 0.5], 2),
+                (&[1, 2], &[0.5, 0.5], 3),
+                (&[3], &[1.0], 4),
+            ],
+        );
+        let set: HashSet<usize> = [0, 1].iter().copied().collect();
+        let any = ecs.filter_by_labels(&set, LabelMatch::Any);
+        assert_eq!(any.classes.counts, vec![1, 2, 3]);
+        assert_eq!(any.targets, ecs.targets);
+        let all = ecs.filter_by_labels(&set, LabelMatch::All);
+        assert_eq!(all.classes.counts, vec![2]);
+        assert_eq!(all.neq, 1);
+    }
+
+    #[test]
+    fn quant_table_round_trip() {
+        let src = write_tmp(
+            "table_src.sf",
+            b"Name\tLength\tEffectiveLength\tTPM\tNumReads\n\
+              txA\t1000\t812.25\t250000.5\t40.125\n\
+              txB\t500\t312.5\t749999.5\t3\n",
+        );
+        let table = QuantTable::from_path(&src).unwrap();
+        let out = tmp_path("table_out.sf");
+        table.write_to_path(&out, false).unwrap();
+        verify_quant_header(&out).unwrap();
+        assert_eq!(QuantTable::from_path(&out).unwrap(), table);
+
+        let mut filtered = QuantTable::default();
+        filtered.push(quant_rec("txA", 1000, 812.25, 250.0, 40.125));
+        filtered.push(quant_rec("txB", 500, 312.5, 750.0, 3.0));
+        filtered.write_to_path(&out, true).unwrap();
+        let back = QuantTable::from_path(&out).unwrap();
+        assert_eq!(back.tpm, vec![250_000.0, 750_000.0]);
+        assert_eq!(back.num_reads, filtered.num_reads);
+
+        let empty = QuantTable::default();
+        empty.write_to_path(&out, true).unwrap();
+        verify_quant_header(&out).unwrap();
+        assert_eq!(QuantTable::from_path(&out).unwrap(), empty);
+    }
+
+    #[test]
+    fn binary_cache_round_trip() {
+        let p = write_tmp(
+            "eq_cache_src.txt",
+            b"3\n2\ntxA\ntxB\ntxC\n2 0 2 0.25 0.75 6\n1 1 1.0 2\n",
+        );
+        let mut ecs = EqClassCollection::from_path(&p).unwrap();
+        ecs.targets[2] = "tx_ü".into();
+        let cache = tmp_path("eq.cache");
+        ecs.save_binary(&cache).unwrap();
+        let back = EqClassCollection::load_binary(&cache).unwrap();
+        assert_eq!(back.targets, ecs.targets);
+        assert_eq!((back.ntarget, back.neq), (3, 2));
+        assert_eq!(back.classes, ecs.classes);
+
+        let mut bytes = read(&cache).unwrap();
+        let truncated = tmp_path("eq_truncated.cache");
+        write(&truncated, &bytes[..bytes.len() - 1]).unwrap();
+        let err = EqClassCollection::load_binary(&truncated).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        bytes[8] = 9;
+        write(&cache, &bytes).unwrap();
+        let err = EqClassCollection::load_binary(&cache).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("version 9"));
+        let err = EqClassCollection::load_binary(&p).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn eq_class_count_agreement() {
+        // meta_info_json declares one class
+        let dir = salmon_dir("eq_count_ok", 0);
+        write_tmp("eq_count_ok/aux_info/eq_classes.txt", b"1\n1\ntxA\n1 0 3\n");
+        check_eq_class_count(&SalmonFiles::new(&dir)).unwrap();
+
+        let dir = salmon_dir("eq_count_bad", 0);
+        write_tmp(
+            "eq_count_bad/aux_info/eq_classes.txt",
+            b"1\n2\ntxA\n1 0 3\n1 0 4\n",
+        );
+        assert!(matches!(
+            check_eq_class_count(&SalmonFiles::new(&dir)),
+            Err(SeineError::EqClassCountMismatch {
+                meta_info: 1,
+                eq_file: 2
+            })
+        ));
+    }
+
+    #[test]
+    fn collection_indexing_and_iteration() {
+        let ecs = collection(
+            3,
+            &[
+                (&[0], &[1.0], 4),
+                (&[1, 2], &[0.5, 0.5], 2),
+                (&[2], &[1.0], 7),
+            ],
+        );
+        assert_eq!(ecs.len(), 3);
+        assert!(!ecs.is_empty());
+        let mut seen = 0;
+        for (i, ec) in (&ecs).into_iter().enumerate() {
+            assert_eq!(ec.labels, ecs.class(i).labels);
+            assert_eq!(ec.count, ecs.class(i).count);
+            seen += 1;
         }
+        assert_eq!(seen, ecs.len());
+        let counts: Vec<u32> = (&ecs.classes).into_iter().map(|ec| ec.count).collect();
+        assert_eq!(counts, vec![4, 2, 7]);
 
-        Ok(quant_map)
+        let owned: Vec<EqClass> = ecs.into_iter().collect();
+        assert_eq!(owned.len(), 3);
+        assert_eq!(owned[1].labels, vec![1, 2]);
+        assert_eq!(owned[2].count, 7);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    #[should_panic(expected = "out of range")]
+    fn class_index_out_of_range() {
+        collection(1, &[(&[0], &[1.0], 1)]).class(1);
+    }
 
     #[test]
-    fn ec_list_get() {
-        let mut ecs = EqClassList::new();
+    fn fold_change_with_bootstrap_se() {
+        // target 1 in a: 7, 15, 3; in b: 3, 3, 1
+        let a = Bootstraps::new(3, 2, vec![0.0, 7.0, 0.0, 15.0, 0.0, 3.0]);
+        let b = Bootstraps::new(3, 2, vec![0.0, 3.0, 0.0, 3.0, 0.0, 1.0]);
+        // per-replicate log2 ratios of (x + 1): 1, 2, 1
+        let (lfc, se) = lfc_with_se(&a, &b, 1);
+        assert!((lfc - 4.0 / 3.0).abs() < 1e-12);
+        assert!((se - (1.0_f64 / 3.0).sqrt()).abs() < 1e-12);
+        assert_eq!(lfc_with_se(&a, &b, 0), (0.0, 0.0));
+    }
 
-        assert!(ecs.is_empty());
+    #[test]
+    fn eq_class_iter_exact_size() {
+        let ecs = collection(
+            2,
+            &[
+                (&[0], &[1.0], 1),
+                (&[1], &[1.0], 2),
+                (&[0, 1], &[0.5, 0.5], 3),
+            ],
+        );
+        let mut it = ecs.classes.iter();
+        assert_eq!(it.len(), 3);
+        assert_eq!(it.size_hint(), (3, Some(3)));
+        it.next();
+        assert_eq!(it.len(), 2);
+        it.next();
+        it.next();
+        assert_eq!(it.len(), 0);
+        assert!(it.next().is_none());
+        assert_eq!(it.len(), 0);
+        assert_eq!(EqClassList::new().iter().len(), 0);
+    }
 
-        let ec = EqClass {
-            labels: vec![0, 1, 2],
-            weights: vec![0.2, 0.3, 0.5],
-            count: 15,
+    #[test]
+    fn eq_class_equality() {
+        let a = collection(2, &[(&[0, 1], &[0.25, 0.75], 3)]);
+        let mut b = collection(2, &[(&[0, 1], &[0.25, 0.75], 3)]);
+        assert_eq!(a, b);
+        assert_eq!(a.class(0), b.class(0));
+        b.index_target_names();
+        assert_eq!(a, b);
+
+        b.classes.weights[1] += 1e-12;
+        assert_ne!(a, b);
+        assert!(a.approx_eq(&b, 1e-9));
+        assert!(!a.approx_eq(&b, 1e-15));
+        b.classes.counts[0] = 4;
+        assert!(!a.approx_eq(&b, 1e-9));
+    }
+
+    #[test]
+    fn read_bootstrap_names_in_order() {
+        let dir = salmon_dir("boot_names", 0);
+        let sf = SalmonFiles::new(&dir);
+        assert!(sf.read_bootstrap_names().unwrap().is_empty());
+
+        write_bootstraps(&dir, &["txC", "txA", "txB"], &[]);
+        assert_eq!(
+            sf.read_bootstrap_names().unwrap(),
+            vec!["txC", "txA", "txB"]
+        );
+    }
+
+    #[test]
+    fn parallel_parse_matches_serial() {
+        let mut text = String::from("4\n500\ntxA\ntxB\ntxC\ntxD\n");
+        for i in 0..500_u32 {
+            let (a, b) = (i % 4, (i * 7 + 1) % 4);
+            if a == b {
+                text.push_str(&format!("1 {} 1.0 {}\n", a, i + 1));
+            } else {
+                text.push_str(&format!("2 {} {} 0.25 0.75 {}\n", a, b, i + 1));
+            }
+        }
+        let p = write_tmp("eq_parallel.txt", text.as_bytes());
+        let serial = EqClassCollection::from_path(&p).unwrap();
+        let parallel = EqClassCollection::from_path_parallel(&p).unwrap();
+        assert_eq!(parallel, serial);
+        assert_eq!(parallel.len(), 500);
+
+        let gz = tmp_path("eq_parallel.txt.gz");
+        let mut enc = GzEncoder::new(Vec::new(), Compression::default());
+        enc.write_all(text.as_bytes()).unwrap();
+        write(&gz, enc.finish().unwrap()).unwrap();
+        assert_eq!(EqClassCollection::from_path_parallel(&gz).unwrap(), serial);
+
+        let truncated = write_tmp("eq_parallel_short.txt", b"1\n3\ntxA\n1 0 1.0 2\n");
+        let err = EqClassCollection::from_path_parallel(&truncated).unwrap_err();
+        let serial_err = EqClassCollection::from_path(&truncated).unwrap_err();
+        assert_eq!(err.to_string(), serial_err.to_string());
+    }
+
+    #[test]
+    fn slice_parse_matches_reader() {
+        let weighted = b"3\n2\ntxA\ntxB\ntxC\n2 0 2 0.25 0.75 6\r\n1 1 1.0 2";
+        let unweighted = b"2\n2\ntxA\ntxB\n2 0 1 5\n1 1 3\n";
+        for text in [&weighted[..], &unweighted[..]] {
+            let from_reader = EqClassCollection::from_reader(text, false).unwrap();
+            assert_eq!(EqClassCollection::from_slice(text).unwrap(), from_reader);
+        }
+
+        let mut enc = GzEncoder::new(Vec::new(), Compression::default());
+        enc.write_all(unweighted).unwrap();
+        let gz = enc.finish().unwrap();
+        assert_eq!(
+            EqClassCollection::from_slice(&gz).unwrap(),
+            EqClassCollection::from_reader(&unweighted[..], false).unwrap()
+        );
+
+        for bad in [
+            &b"2\n1\ntxA\ntxB\n2 0 x 0.5 0.5 1\n"[..],
+            b"1\n2\ntxA\n1 0 1.0 1\n",
+        ] {
+            let slice = EqClassCollection::from_slice(bad).unwrap_err().to_string();
+            let reader = EqClassCollection::from_reader(bad, false)
+                .unwrap_err()
+                .to_string();
+            assert_eq!(slice, reader);
+        }
+    }
+
+    /// A gzipped ustar archive of `files`, each under `prefix`.
+    fn tar_gz(prefix: &str, files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut tar = Vec::new();
+        for (name, data) in files {
+            let mut header = [0u8; 512];
+            let name = format!("{}{}", prefix, name);
+            header[..name.len()].copy_from_slice(name.as_bytes());
+            header[100..107].copy_from_slice(b"0000644");
+            header[124..135].copy_from_slice(format!("{:011o}", data.len()).as_bytes());
+            header[156] = b'0';
+            header[257..263].copy_from_slice(b"ustar\0");
+            header[148..156].copy_from_slice(b"        ");
+            let sum: u32 = header.iter().map(|&b| b as u32).sum();
+            header[148..155].copy_from_slice(format!("{:06o}\0", sum).as_bytes());
+            tar.extend_from_slice(&header);
+            tar.extend_from_slice(data);
+            tar.resize(tar.len().div_ceil(512) * 512, 0);
+        }
+        tar.resize(tar.len() + 1024, 0);
+        let mut enc = GzEncoder::new(Vec::new(), Compression::default());
+        enc.write_all(&tar).unwrap();
+        enc.finish().unwrap()
+    }
+
+    #[test]
+    fn tar_size_fields() {
+        assert_eq!(tar_octal(b"00000001750\0").unwrap(), 1000);
+        assert_eq!(tar_octal(b"\0\0\0").unwrap(), 0);
+
+        let mut field = [0u8; 12];
+        field[0] = 0x80;
+        field[7..].copy_from_slice(&[0x02, 0x00, 0x00, 0x00, 0x01]);
+        assert_eq!(tar_octal(&field).unwrap(), (2 << 32) + 1);
+
+        field[0] = 0xff;
+        assert!(tar_octal(&field).is_err());
+        field = [0xff; 12];
+        field[0] = 0x81;
+        assert!(tar_octal(&field).is_err());
+    }
+
+    #[test]
+    fn truncated_tar_member_is_an_error() {
+        let gz = tar_gz("", &[("quant.sf", &[b'x'; 700])]);
+        let mut tar = Vec::new();
+        GzDecoder::new(&gz[..]).read_to_end(&mut tar).unwrap();
+        let p = write_tmp("truncated_member.tar", &tar[..512 + 300]);
+        let err = SalmonArchive::open(&p).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        let p = write_tmp("whole_member.tar", &tar);
+        assert_eq!(
+            SalmonArchive::open(&p)
+                .unwrap()
+                .read_member("quant.sf")
+                .unwrap(),
+            vec![b'x'; 700]
+        );
+    }
+
+    #[test]
+    fn oversized_tar_extended_header_is_an_error() {
+        let mut header = [0u8; 512];
+        header[..13].copy_from_slice(b"././@LongLink");
+        header[124..135].copy_from_slice(b"77777777777");
+        header[156] = b'L';
+        let p = write_tmp("huge_longlink.tar", &header);
+        let err = SalmonArchive::open(&p).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn archive_matches_directory() {
+        let dir = salmon_dir("archived", 0);
+        let recs = vec![
+            quant_rec("tx0", 100, 50.0, 250000.0, 5.0),
+            quant_rec("tx1", 200, 150.0, 750000.0, 45.0),
+        ];
+        let quant = write_quant("archived/quant.sf", &recs);
+        let eq = write_tmp(
+            "archived/aux_info/eq_classes.txt",
+            b"2\n1\ntx0\ntx1\n2 0 1 0.5 0.5 7\n",
+        );
+        let meta = read(dir.join("aux_info/meta_info.json")).unwrap();
+        let (quant, eq) = (read(quant).unwrap(), read(eq).unwrap());
+        let files: Vec<(&str, &[u8])> = vec![
+            ("quant.sf", &quant),
+            ("aux_info/meta_info.json", &meta),
+            ("aux_info/eq_classes.txt", &eq),
+        ];
+
+        let sf = SalmonFiles::new(&dir);
+        for prefix in ["", "sample/"] {
+            let p = write_tmp("archived.tar.gz", &tar_gz(prefix, &files));
+            let archive = SalmonArchive::open(&p).unwrap();
+            assert_eq!(archive.members().count(), 3);
+            assert_eq!(
+                archive.read_quant().unwrap(),
+                QuantTable::from_path(&sf.quant_file).unwrap()
+            );
+            assert_eq!(
+                archive.read_meta_info().unwrap().num_valid_targets,
+                sf.read_meta_info().unwrap().num_valid_targets
+            );
+            assert_eq!(
+                archive.read_eq_classes().unwrap(),
+                EqClassCollection::from_path(&sf.eq_file).unwrap()
+            );
+            assert!(matches!(
+                archive.read_member("cmd_info.json"),
+                Err(SeineError::MissingFile(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn subset_targets_remaps_labels() {
+        let ecs = collection(
+            4,
+            &[
+                (&[0, 1], &[0.5, 0.5], 3),
+                (&[1, 2, 3], &[0.2, 0.3, 0.5], 4),
+                (&[0, 2], &[0.6, 0.4], 5),
+                (&[3], &[1.0], 9),
+            ],
+        );
+        // keep tx3 and tx1, in that order
+        let sub = ecs.subset_targets(&[3, 1]);
+        assert_eq!(sub.targets, vec!["tx3", "tx1"]);
+        assert_eq!((sub.ntarget, sub.neq, sub.len()), (2, 3, 3));
+        sub.validate().unwrap();
+
+        let c = sub.class(0);
+        assert_eq!((c.labels, c.weights, c.count), (&[1][..], &[1.0][..], 3));
+        let c = sub.class(1);
+        assert_eq!(c.labels, &[1, 0]);
+        assert!((c.weights[0] - 2.0 / 7.0).abs() < 1e-12);
+        assert!((c.weights[1] - 5.0 / 7.0).abs() < 1e-12);
+        // {tx0, tx2} lost every label and is gone
+        let c = sub.class(2);
+        assert_eq!((c.labels, c.count), (&[0][..], 9));
+
+        assert!(ecs.subset_targets(&[]).is_empty());
+    }
+
+    #[test]
+    fn quant_records_streams_rows() {
+        let recs = vec![
+            quant_rec("txA", 100, 50.0, 600000.0, 6.5),
+            quant_rec("txB", 200, 150.0, 400000.0, 12.0),
+        ];
+        let p = write_quant("streamed_quant.sf", &recs);
+        let mut enc = GzEncoder::new(Vec::new(), Compression::default());
+        enc.write_all(&read(&p).unwrap()).unwrap();
+        let gz = write_tmp("streamed_quant.sf.gz", &enc.finish().unwrap());
+
+        let map = HashMap::<String, QuantEntry>::from_path(&p).unwrap();
+        let expected: f64 = map.values().map(|e| e.num_reads).sum();
+        for path in [&p, &gz] {
+            let total: f64 = quant_records(path)
+                .unwrap()
+                .map(|r| r.unwrap().num_reads)
+                .sum();
+            assert_eq!(total, expected);
+        }
+        let first = quant_records(&p).unwrap().next().unwrap().unwrap();
+        assert_eq!(first.name, "txA");
+    }
+
+    #[test]
+    fn quant_and_eq_classes_consistency() {
+        let mut quant = QuantTable::default();
+        for name in ["tx0", "tx1", "tx2"] {
+            quant.push(quant_rec(name, 100, 50.0, 0.0, 0.0));
+        }
+        let ecs = collection(3, &[(&[0, 2], &[0.5, 0.5], 1)]);
+        check_consistency(&quant, &ecs).unwrap();
+
+        let mut other = collection(3, &[(&[0, 2], &[0.5, 0.5], 1)]);
+        other.targets[1] = "txX".to_string();
+        match check_consistency(&quant, &other) {
+            Err(SeineError::TargetNameMismatch {
+                index,
+                expected,
+                found,
+            }) => assert_eq!(
+                (index, expected.as_str(), found.as_str()),
+                (1, "tx1", "txX")
+            ),
+            r => panic!("unexpected {:?}", r),
+        }
+
+        let short = collection(2, &[]);
+        assert!(matches!(
+            check_consistency(&quant, &short),
+            Err(SeineError::TargetCountMismatch {
+                expected: 3,
+                found: 2
+            })
+        ));
+    }
+
+    #[test]
+    fn target_graph_weights() {
+        let ecs = collection(
+            4,
+            &[
+                (&[0, 1, 2], &[0.2, 0.3, 0.5], 4),
+                (&[2, 1], &[0.5, 0.5], 3),
+                (&[3], &[1.0], 9),
+            ],
+        );
+        let g = ecs.target_graph(None);
+        assert_eq!(g.edge_list(), vec![(0, 1, 4.0), (0, 2, 4.0), (1, 2, 7.0)]);
+        assert_eq!(g.weight(2, 1), 7.0);
+        assert_eq!(g.weight(0, 3), 0.0);
+
+        let g = ecs.target_graph(Some(2));
+        assert_eq!(g.edge_list(), vec![(1, 2, 3.0)]);
+    }
+
+    #[test]
+    fn builder_custom_aux_dir() {
+        let dir = tmp_path("custom_aux");
+        write_tmp(
+            "custom_aux/aux/meta_info.json",
+            meta_info_json(0, r#""gzipped""#).as_bytes(),
+        );
+        let sf = SalmonFilesBuilder::new(&dir)
+            .aux_dir("aux")
+            .build()
+            .unwrap();
+        assert_eq!(sf.mi_file, dir.join("aux/meta_info.json"));
+        assert_eq!(sf.eq_file, dir.join("aux/eq_classes.txt.gz"));
+        assert_eq!(sf.bootstrap_file, dir.join("aux/bootstrap/bootstraps.gz"));
+        assert_eq!(sf.ambig_file, dir.join("aux/ambig_info.tsv"));
+        assert_eq!(sf.quant_file, dir.join("quant.sf"));
+
+        let sf = SalmonFilesBuilder::new(&dir)
+            .aux_dir("aux")
+            .eq_file("eqs.txt")
+            .quant_file("renamed.sf")
+            .build()
+            .unwrap();
+        assert_eq!(sf.eq_file, dir.join("eqs.txt"));
+        assert_eq!(sf.quant_file, dir.join("renamed.sf"));
+
+        let default = SalmonFilesBuilder::new(&dir).build().unwrap();
+        assert_eq!(default.mi_file, dir.join("aux_info/meta_info.json"));
+        assert_eq!(default.eq_file, dir.join("aux_info/eq_classes.txt"));
+    }
+
+    #[test]
+    fn sampling_type_from_meta_info() {
+        for (value, expected) in [
+            ("bootstrap", SampType::Bootstrap),
+            ("gibbs", SampType::Gibbs),
+            ("none", SampType::None),
+        ] {
+            let json = meta_info_json(10, "").replace("\"bootstrap\"", &format!("\"{}\"", value));
+            let p = write_tmp(&format!("samp_{}.json", value), json.as_bytes());
+            let mi = MetaInfo::from_path(&p).unwrap();
+            assert_eq!(mi.sampling_type(), expected);
+            assert_eq!(mi.has_inferential_replicates(), expected != SampType::None);
+        }
+        let qc = SampleQc::from_salmon_files(&SalmonFiles::new(salmon_dir("samp_qc", 0))).unwrap();
+        assert_eq!(qc.samp_type, Some(SampType::Bootstrap));
+    }
+
+    #[test]
+    fn view_mass_helpers() {
+        let ecs = collection(
+            4,
+            &[
+                (&[0, 2, 3], &[0.2, 0.3, 0.5], 15),
+                (&[1, 3], &[0.0, 0.0], 2),
+            ],
+        );
+        let c = ecs.class(0);
+        assert_eq!(c.total_mass(), 15.0);
+        let w = c.normalized_weights();
+        for (a, b) in w.iter().zip([0.2, 0.3, 0.5]) {
+            assert!((a - b).abs() < 1e-12);
+        }
+        assert!((c.mass_for(2).unwrap() - 4.5).abs() < 1e-12);
+        assert!((c.mass_for(3).unwrap() - 7.5).abs() < 1e-12);
+        assert_eq!(c.mass_for(1), None);
+        assert_eq!(ecs.class(1).normalized_weights(), vec![0.5, 0.5]);
+    }
+
+    #[test]
+    fn total_count_and_size_histogram() {
+        let ecs = collection(
+            3,
+            &[
+                (&[0], &[1.0], u32::MAX),
+                (&[0, 1], &[0.5, 0.5], 5),
+                (&[1, 2], &[0.5, 0.5], 2),
+            ],
+        );
+        assert_eq!(ecs.total_count(), u32::MAX as u64 + 7);
+        let hist: Vec<_> = ecs.count_histogram().into_iter().collect();
+        assert_eq!(hist, vec![(1, 1), (2, 2)]);
+    }
+
+    #[test]
+    fn eq_file_line_endings() {
+        let lf = "2\n2\ntxA\ntxB\n2 0 1 0.4 0.6 5\n1 1 1.0 12\n";
+        let expected =
+            EqClassCollection::from_path(&write_tmp("eq_lf.txt", lf.as_bytes())).unwrap();
+        let variants = [
+            ("eq_crlf.txt", lf.replace('\n', "\r\n")),
+            ("eq_no_final_newline.txt", lf.trim_end().to_string()),
+            (
+                "eq_crlf_no_final_newline.txt",
+                lf.trim_end().replace('\n', "\r\n"),
+            ),
+        ];
+        for (name, text) in &variants {
+            let ecs = EqClassCollection::from_path(&write_tmp(name, text.as_bytes())).unwrap();
+            assert_eq!(ecs, expected, "{}", name);
+        }
+        assert_eq!(expected.targets, vec!["txA", "txB"]);
+        assert_eq!(expected.class(1).count, 12);
+    }
+
+    #[test]
+    fn header_class_count_is_checked() {
+        let over = b"2\n3\ntxA\ntxB\n1 0 1.0 3\n1 1 1.0 4\n";
+        let under = b"2\n1\ntxA\ntxB\n1 0 1.0 3\n1 1 1.0 4\n\n";
+        for (text, declared, found) in [(&over[..], 3, 2), (&under[..], 1, 2)] {
+            let expected = EqParseError::ClassCount { declared, found };
+            assert_eq!(eq_parse_error(text), expected);
+            let err = EqClassCollection::from_slice(text).unwrap_err();
+            assert_eq!(err.to_string(), expected.to_string());
+        }
+        assert_eq!(
+            EqParseError::ClassCount {
+                declared: 3,
+                found: 2
+            }
+            .to_string(),
+            "header declares 3 equivalence classes but the file holds 2"
+        );
+
+        let ok =
+            EqClassCollection::from_reader(&b"2\n1\ntxA\ntxB\n1 0 1.0 3\n\n"[..], false).unwrap();
+        assert_eq!((ok.len(), ok.neq), (1, 1));
+        assert!(!ok.is_empty());
+    }
+
+    #[test]
+    fn class_count_agrees_across_parsers() {
+        // overstated, with a trailing blank line; understated; and a header
+        // that matches once trailing blank lines are ignored
+        let over = b"2\n3\ntxA\ntxB\n1 0 1.0 3\n1 1 1.0 4\n\n \n";
+        let under = b"2\n1\ntxA\ntxB\n1 0 1.0 3\n1 1 1.0 4\n";
+        for (name, text, declared, found) in [
+            ("eq_count_over.txt", &over[..], 3, 2),
+            ("eq_count_under.txt", &under[..], 1, 2),
+        ] {
+            let expected = EqParseError::ClassCount { declared, found }.to_string();
+            let p = write_tmp(name, text);
+            let serial = EqClassCollection::from_path(&p).unwrap_err();
+            let parallel = EqClassCollection::from_path_parallel(&p).unwrap_err();
+            let slice = EqClassCollection::from_slice(text).unwrap_err();
+            let each = for_each_eq_class(&p, |_| ()).unwrap_err();
+            let csr = eq_classes_to_csr_streaming(text, Vec::new()).unwrap_err();
+            let filter = filter_eq_classes_streaming(io::Cursor::new(text), Vec::new(), |_| true)
+                .unwrap_err();
+            for err in [serial, parallel, slice, each, csr, filter] {
+                assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+                assert_eq!(err.to_string(), expected, "{}", name);
+            }
+        }
+
+        let exact = b"2\n2\ntxA\ntxB\n1 0 1.0 3\n1 1 1.0 4\n\n";
+        let p = write_tmp("eq_count_exact.txt", exact);
+        assert_eq!(EqClassCollection::from_path(&p).unwrap().len(), 2);
+        assert_eq!(EqClassCollection::from_path_parallel(&p).unwrap().len(), 2);
+        assert_eq!(EqClassCollection::from_slice(exact).unwrap().len(), 2);
+        assert_eq!(for_each_eq_class(&p, |_| ()).unwrap().neq, 2);
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let ecs = collection(3, &[(&[2], &[1.0], 4), (&[0, 2], &[0.25, 0.75], 8)]);
+        let json = serde_json::to_string(&ecs).unwrap();
+        let back: EqClassCollection = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, ecs);
+
+        let list: EqClassList =
+            serde_json::from_str(&serde_json::to_string(&ecs.classes).unwrap()).unwrap();
+        assert_eq!(list, ecs.classes);
+
+        for bad in [
+            r#"{"offsets": [], "labels": [], "weights": [], "counts": []}"#,
+            r#"{"offsets": [0, 2, 1], "labels": [0, 1], "weights": [0.5, 0.5], "counts": [1, 1]}"#,
+            r#"{"offsets": [0, 3], "labels": [0, 1], "weights": [0.5, 0.5], "counts": [1]}"#,
+            r#"{"offsets": [0, 2], "labels": [0, 1], "weights": [0.5, 0.5], "counts": []}"#,
+        ] {
+            assert!(serde_json::from_str::<EqClassList>(bad).is_err(), "{}", bad);
+        }
+        let short = EqClassList {
+            offsets: vec![0, 2],
+            labels: vec![0, 1],
+            weights: vec![0.5, 0.5],
+            counts: Vec::new(),
         };
+        let err = short.check_layout().unwrap_err();
+        assert!(matches!(
+            err,
+            SeineError::LayoutMismatch { what: "counts", .. }
+        ));
+        let bad = r#"{"offsets": [0, 2], "labels": [0, 1], "weights": [0.5, 0.5], "counts": []}"#;
+        let serde_err = serde_json::from_str::<EqClassList>(bad).unwrap_err();
+        assert!(serde_err.to_string().contains(&err.to_string()));
+        // well-formed list, but label 5 does not exist
+        let bad = json.replace("\"labels\":[2,0,2]", "\"labels\":[2,0,5]");
+        assert_ne!(bad, json);
+        assert!(serde_json::from_str::<EqClassCollection>(&bad).is_err());
+    }
 
-        ecs.push(ec);
+    #[test]
+    fn quant_table_top_n() {
+        let mut table = QuantTable::default();
+        for (name, tpm, reads) in [
+            ("txE", 10.0, 1.0),
+            ("txB", 50.0, 4.0),
+            ("txD", 50.0, 9.0),
+            ("txA", 5.0, 2.0),
+            ("txC", 50.0, 3.0),
+        ] {
+            table.push(quant_rec(name, 100, 50.0, tpm, reads));
+        }
+        assert_eq!(
+            table.top_n(2, QuantValue::Tpm),
+            vec![("txB", 50.0), ("txC", 50.0)]
+        );
+        assert_eq!(
+            table.top_n(2, QuantValue::NumReads),
+            vec![("txD", 9.0), ("txB", 4.0)]
+        );
+        let all = table.top_n(10, QuantValue::Tpm);
+        let names: Vec<&str> = all.iter().map(|&(n, _)| n).collect();
+        assert_eq!(names, vec!["txB", "txC", "txD", "txE", "txA"]);
+        assert!(table.top_n(0, QuantValue::Tpm).is_empty());
+    }
 
-        assert_eq!(ecs.len(), 1);
+    #[test]
+    fn labels_must_fit_in_u32() {
+        let ecs = collection(3, &[(&[2, 0], &[0.5, 0.5], 1)]);
+        let labels: Vec<usize> = ecs.class(0).labels_usize().collect();
+        assert_eq!(labels, vec![2, 0]);
 
-        assert!(ecs.get(1).is_none());
+        let too_big = b"1\n1\ntxA\n1 4294967296 1.0 1\n";
+        assert!(EqClassCollection::from_reader(&too_big[..], false).is_err());
+        assert!(EqClassCollection::from_slice(too_big).is_err());
+    }
 
-        assert_eq!(ecs.iter().count(), ecs.len());
+    #[test]
+    fn read_collapse_and_delta_logs() {
+        let dir = salmon_dir("collapse_logs", 0);
+        write_tmp(
+            "collapse_logs/collapsed.log",
+            b"# group\tmembers\nG1\ttx1\ttx2\n\nG2 tx3,tx4,tx5\n",
+        );
+        write_tmp(
+            "collapse_logs/delta.log",
+            b"# ids delta\ntx1\ttx2\t0.125\n  # indented comment\nG2\t-1.5e-3\n",
+        );
+        let sf = SalmonFiles::new(&dir);
+        let log = sf.read_collapse_log().unwrap();
+        assert_eq!(log.records.len(), 2);
+        assert_eq!(log.records[0].group, "G1");
+        assert_eq!(log.records[0].members, vec!["tx1", "tx2"]);
+        assert_eq!(log.records[1].members, vec!["tx3", "tx4", "tx5"]);
 
-        let ec = ecs.get(0).unwrap();
-        assert_eq!(ec.labels, vec![0, 1, 2]);
-        assert_eq!(ec.weights, vec![0.2, 0.3, 0.5]);
-        assert_eq!(ec.count, 15);
+        let deltas = sf.read_delta_log().unwrap();
+        assert_eq!(
+            deltas.records,
+            vec![
+                DeltaRecord {
+                    ids: vec!["tx1".to_string(), "tx2".to_string()],
+                    delta: 0.125,
+                },
+                DeltaRecord {
+                    ids: vec!["G2".to_string()],
+                    delta: -1.5e-3,
+                },
+            ]
+        );
+
+        let bad = write_tmp("bad_delta.log", b"# header\ntx1 tx2 big\n");
+        let err = DeltaLog::from_path(&bad).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("line 2"));
+        assert!(matches!(
+            SalmonFiles::new(salmon_dir("no_collapse_logs", 0)).read_collapse_log(),
+            Err(SeineError::MissingFile(_))
+        ));
+    }
+
+    #[test]
+    fn builder_from_targets() {
+        let mut b = EqClassCollection::builder(vec!["txA".to_string(), "txB".to_string()]);
+        b.push(EqClass {
+            labels: vec![1],
+            weights: vec![1.0],
+            count: 3,
+        })
+        .unwrap();
+        b.push(EqClass {
+            labels: vec![0, 1],
+            weights: vec![0.4, 0.6],
+            count: 5,
+        })
+        .unwrap();
+        let out_of_range = EqClass {
+            labels: vec![0, 2],
+            weights: vec![0.5, 0.5],
+            count: 1,
+        };
+        assert!(matches!(
+            b.push(out_of_range),
+            Err(SeineError::InvalidEqClass { class: 2, .. })
+        ));
+
+        let ecs = b.build();
+        assert_eq!((ecs.ntarget, ecs.neq), (2, 2));
+        ecs.validate().unwrap();
+        let seen: Vec<(Vec<Label>, u32)> = ecs
+            .classes
+            .iter()
+            .map(|ec| (ec.labels.to_vec(), ec.count))
+            .collect();
+        assert_eq!(seen, vec![(vec![1], 3), (vec![0, 1], 5)]);
+    }
+
+    #[test]
+    fn shrink_class_buffers() {
+        let mut list = EqClassList::new();
+        for i in 0..100 {
+            list.push(EqClass {
+                labels: vec![i, i + 1],
+                weights: vec![0.5, 0.5],
+                count: 1,
+            });
+        }
+        list.labels.reserve(1000);
+        assert!(list.capacity_overhead() >= 1000 * std::mem::size_of::<Label>());
+        list.shrink_to_fit();
+        assert_eq!(list.offsets.capacity(), list.offsets.len());
+        assert_eq!(list.labels.capacity(), list.labels.len());
+        assert_eq!(list.weights.capacity(), list.weights.len());
+        assert_eq!(list.counts.capacity(), list.counts.len());
+        assert_eq!(list.capacity_overhead(), 0);
+        assert_eq!(list.len(), 100);
+
+        let p = write_tmp(
+            "eq_shrunk.txt",
+            b"2\n2\ntxA\ntxB\n2 0 1 0.5 0.5 3\n1 1 1.0 2\n",
+        );
+        assert_eq!(
+            EqClassCollection::from_path(&p)
+                .unwrap()
+                .classes
+                .capacity_overhead(),
+            0
+        );
+    }
+
+    #[test]
+    fn read_fld() {
+        let dir = salmon_dir("fld_read", 0);
+        let sf = SalmonFiles::new(&dir);
+        let err = sf.read_fragment_length_dist().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        match sf.try_read_fragment_length_dist() {
+            Err(SeineError::NotAvailable(p)) => assert_eq!(p, sf.fld_file),
+            other => panic!("expected NotAvailable, got {:?}", other),
+        }
+
+        // 1 fragment of length 1, 2 of length 2, 1 of length 4
+        let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+        for c in &[0i32, 1, 2, 0, 1] {
+            gz.write_all(&c.to_le_bytes()).unwrap();
+        }
+        write(dir.join("aux_info/fld.gz"), gz.finish().unwrap()).unwrap();
+        let probs = sf.read_fragment_length_dist().unwrap();
+        assert_eq!(probs, vec![0.0, 0.25, 0.5, 0.0, 0.25]);
+        let fld = sf.fragment_length_dist().unwrap();
+        assert_eq!(fld.mean(), 2.25);
+        assert_eq!(fld.mode(), 2);
+    }
+
+    /// A gzipped binary GC model with `rows` contexts, weights column-major.
+    fn gc_model(log_space: bool, rows: i64, weights: &[f64]) -> Vec<u8> {
+        let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+        gz.write_all(&(if log_space { 0i32 } else { 1 }).to_le_bytes())
+            .unwrap();
+        gz.write_all(&rows.to_le_bytes()).unwrap();
+        gz.write_all(&(weights.len() as i64 / rows).to_le_bytes())
+            .unwrap();
+        for w in weights {
+            gz.write_all(&w.to_le_bytes()).unwrap();
+        }
+        gz.finish().unwrap()
+    }
+
+    #[test]
+    fn read_gc_bias() {
+        let dir = salmon_dir("gc_bias", 0);
+        let models = SalmonFiles::new(&dir).bias_models();
+        let err = models.read_gc_bias().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert!(matches!(
+            models.try_read_gc_bias(),
+            Err(SeineError::NotAvailable(_))
+        ));
+
+        // two contexts x four bins; bins sum to 2, 2, 4, 0 out of 8
+        let obs = [1.0, 1.0, 0.5, 1.5, 2.0, 2.0, 0.0, 0.0];
+        write(dir.join("aux_info/obs_gc.gz"), gc_model(false, 2, &obs)).unwrap();
+        let exp: Vec<f64> = [1.0, 1.0, 1.0, 1.0].iter().map(|w: &f64| w.ln()).collect();
+        write(dir.join("aux_info/exp_gc.gz"), gc_model(true, 1, &exp)).unwrap();
+        let gc = models.read_gc_bias().unwrap();
+        assert_eq!(gc.num_bins(), 4);
+        assert_eq!(gc.observed, vec![0.25, 0.25, 0.5, 0.0]);
+        assert_eq!(gc.expected, vec![0.25; 4]);
+        assert_eq!(gc.ratio(), vec![1.0, 1.0, 2.0, 0.0]);
+
+        write(
+            dir.join("aux_info/exp_gc.gz"),
+            gc_model(false, 1, &[1.0, 0.0]),
+        )
+        .unwrap();
+        let err = models.read_gc_bias().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // headers whose matrix size overflows, or that name no rows
+        for &(rows, cols) in &[(i64::MAX / 2, 4), (1 << 32, 1 << 32), (0, i64::MAX)] {
+            let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+            gz.write_all(&1i32.to_le_bytes()).unwrap();
+            gz.write_all(&rows.to_le_bytes()).unwrap();
+            gz.write_all(&cols.to_le_bytes()).unwrap();
+            gz.write_all(&1.0f64.to_le_bytes()).unwrap();
+            write(dir.join("aux_info/obs_gc.gz"), gz.finish().unwrap()).unwrap();
+            let err = models.read_gc_bias().unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn named_eq_class_view() {
+        let ecs = collection(4, &[(&[1], &[1.0], 3), (&[3, 0, 2], &[0.2, 0.3, 0.5], 15)]);
+        let named = ecs.named_view(1).unwrap();
+        assert_eq!(
+            named.entries,
+            vec![("tx3", 0.2), ("tx0", 0.3), ("tx2", 0.5)]
+        );
+        assert_eq!(named.count, 15);
+        assert_eq!(named.to_string(), "count=15 [tx3:0.2, tx0:0.3, tx2:0.5]");
+        assert_eq!(ecs.named_view(0).unwrap().to_string(), "count=3 [tx1:1]");
+        assert!(ecs.named_view(2).is_none());
+    }
+
+    #[test]
+    fn ec_list_class_sizes() {
+        let ecs = collection(
+            5,
+            &[
+                (&[0], &[1.0], 4),
+                (&[1, 2, 3], &[0.2, 0.3, 0.5], 7),
+                (&[2, 4], &[0.5, 0.5], 1),
+            ],
+        );
+        let list = &ecs.classes;
+        let sizes: Vec<_> = (0..4).map(|i| list.class_size(i)).collect();
+        assert_eq!(sizes, vec![Some(1), Some(3), Some(2), None]);
+        let counts: Vec<_> = (0..4).map(|i| list.class_count(i)).collect();
+        assert_eq!(counts, vec![Some(4), Some(7), Some(1), None]);
+        assert_eq!(EqClassList::new().class_size(0), None);
+    }
+
+    #[test]
+    fn global_eq_class_dict() {
+        let mut dict = GlobalEqClassDict::new();
+        let a = collection(3, &[(&[0], &[1.0], 4), (&[2, 1], &[0.5, 0.5], 2)]);
+        let b = collection(
+            3,
+            &[
+                (&[0, 2], &[0.5, 0.5], 1),
+                (&[1, 2], &[0.4, 0.6], 5),
+                (&[0, 2], &[0.1, 0.9], 3),
+            ],
+        );
+        assert_eq!(dict.add_sample(&a), vec![0, 1]);
+        assert_eq!(dict.add_sample(&b), vec![2, 1, 2]);
+        assert_eq!(dict.len(), 3);
+        assert_eq!(dict.num_samples(), 2);
+        assert_eq!(dict.label_set(1), Some(&[1, 2][..]));
+        assert_eq!(dict.label_set(3), None);
+        // adding a sample again assigns no new ids
+        assert_eq!(dict.add_sample(&a), vec![0, 1]);
+        assert_eq!(dict.len(), 3);
+    }
+
+    #[test]
+    fn counts_per_effective_kilobase() {
+        let mut table = QuantTable::default();
+        for (name, efflen) in [("txA", 500.0), ("txB", 2000.0), ("txC", 0.0)] {
+            table.push(quant_rec(name, 2100, efflen, 0.0, 0.0));
+        }
+        let cpk = counts_per_effective_kb(&[10.0, 10.0, 7.0], &table).unwrap();
+        assert_eq!(cpk, vec![20.0, 5.0, 0.0]);
+        match counts_per_effective_kb(&[1.0], &table) {
+            Err(SeineError::TargetCountMismatch { expected, found }) => {
+                assert_eq!((expected, found), (3, 1))
+            }
+            other => panic!("expected a count mismatch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn cohort_quants_from_dirs() {
+        let recs = |reads: [f64; 3]| {
+            vec![
+                quant_rec("txA", 100, 50.0, reads[0] * 10.0, reads[0]),
+                quant_rec("txB", 200, 150.0, reads[1] * 10.0, reads[1]),
+                quant_rec("txC", 300, 250.0, reads[2] * 10.0, reads[2]),
+            ]
+        };
+        write_quant("cohort_a/quant.sf", &recs([1.0, 2.0, 3.0]));
+        write_quant("cohort_b/quant.sf", &recs([4.0, 5.0, 6.0]));
+        let dirs = vec![tmp_path("cohort_a"), tmp_path("cohort_b")];
+        let cohort = CohortQuants::from_dirs(&dirs).unwrap();
+        assert_eq!(cohort.sample_names(), &["cohort_a", "cohort_b"]);
+        assert_eq!(cohort.target_names(), &["txA", "txB", "txC"]);
+        assert_eq!(cohort.num_reads.row(1), &[2.0, 5.0]);
+        assert_eq!(cohort.tpm.get(2, 1), 60.0);
+
+        let mut other = recs([1.0, 1.0, 1.0]);
+        other[1].name = "txZ".to_string();
+        write_quant("cohort_c/quant.sf", &other);
+        let dirs = vec![tmp_path("cohort_a"), tmp_path("cohort_c")];
+        match CohortQuants::from_dirs(&dirs) {
+            Err(SeineError::InSample { dir, source }) => {
+                assert_eq!(dir, tmp_path("cohort_c"));
+                assert!(matches!(
+                    *source,
+                    SeineError::TargetNameMismatch { index: 1, .. }
+                ));
+            }
+            other => panic!("expected a sample mismatch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn eq_text_round_trip() {
+        let ecs = collection(
+            3,
+            &[
+                (&[2, 0], &[0.1, 0.9], 7),
+                (&[1], &[1.0], 2),
+                (&[0, 1, 2], &[1.0 / 3.0, 0.25, 0.4166], 11),
+            ],
+        );
+        for &gzip in &[false, true] {
+            let path = tmp_path(if gzip { "eq_rt.txt.gz" } else { "eq_rt.txt" });
+            ecs.write_text(&path, gzip).unwrap();
+            let back = EqClassCollection::from_path(&path).unwrap();
+            assert_eq!(back.targets, ecs.targets);
+            assert_eq!((back.ntarget, back.neq), (3, 3));
+            assert_eq!(back.classes, ecs.classes);
+        }
+        let mut out = Vec::new();
+        collection(2, &[(&[1, 0], &[0.5, 0.5], 4)])
+            .write_text_to(&mut out)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "2\n1\ntx0\ntx1\n2\t1\t0\t0.5\t0.5\t4\n"
+        );
+    }
+
+    #[test]
+    fn parallel_summarize_matches_serial() {
+        let (nboot, ntarget) = (7, 1001);
+        let mut x = 12345_u64;
+        let values: Vec<f64> = (0..nboot * ntarget)
+            .map(|_| {
+                x = x
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                (x >> 40) as f64 / 1e3
+            })
+            .collect();
+        let boot = Bootstraps::new(nboot, ntarget, values);
+        assert_eq!(boot.summarize_parallel(), boot.summarize());
+        let empty = Bootstraps::new(3, 0, Vec::new());
+        assert!(empty.summarize_parallel().is_empty());
+    }
+
+    #[test]
+    fn drop_decoy_targets() {
+        // two valid targets padded with two decoys
+        let mi: MetaInfo = serde_json::from_str(&meta_info_json(0, "")).unwrap();
+        let mut table = QuantTable::default();
+        for name in &["txA", "txB", "decoy1", "decoy2"] {
+            table.push(quant_rec(name, 100, 50.0, 1.0, 2.0));
+        }
+        assert_eq!(table.valid_target_range(&mi).unwrap(), 0..2);
+        let valid = table.drop_decoys(&mi).unwrap();
+        assert_eq!(valid.names, vec!["txA", "txB"]);
+        assert_eq!(valid.index_of("decoy1"), None);
+
+        let ecs = collection(
+            4,
+            &[
+                (&[0, 2], &[0.5, 0.5], 3),
+                (&[3, 2], &[0.5, 0.5], 1),
+                (&[1], &[1.0], 2),
+            ],
+        );
+        let valid = ecs.drop_decoys(&mi).unwrap();
+        assert_eq!(valid.targets, vec!["tx0", "tx1"]);
+        assert_eq!(valid.classes.len(), 2);
+        assert_eq!(valid.get(0).unwrap().weights, &[1.0]);
+
+        let short = collection(1, &[(&[0], &[1.0], 3)]);
+        match short.drop_decoys(&mi) {
+            Err(SeineError::TargetCountMismatch { expected, found }) => {
+                assert_eq!((expected, found), (2, 1))
+            }
+            other => panic!("expected a count mismatch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn shared_target_dict() {
+        let a = write_tmp(
+            "dict_a_eq.txt",
+            b"2\n2\ntxA\ntxB\n1 0 1 5\n2 0 1 0.5 0.5 3\n",
+        );
+        let b = write_tmp("dict_b_eq.txt", b"2\n1\ntxC\ntxA\n2 1 0 0.25 0.75 4\n");
+        let mut dict = TargetDict::from_names(&["txB", "txA"]);
+        let ea = EqClassCollection::from_path_with_dict(&a, &mut dict, false).unwrap();
+        assert_eq!(ea.targets, vec!["txB", "txA"]);
+        assert_eq!(ea.get(0).unwrap().labels, &[1]);
+        assert_eq!(ea.get(1).unwrap().labels, &[1, 0]);
+        assert!(ea.validate().is_ok());
+
+        match EqClassCollection::from_path_with_dict(&b, &mut dict, false) {
+            Err(SeineError::UnknownTarget(name)) => assert_eq!(name, "txC"),
+            other => panic!("expected an unknown target, got {:?}", other),
+        }
+        let eb = EqClassCollection::from_path_with_dict(&b, &mut dict, true).unwrap();
+        assert_eq!(dict.id("txC"), Some(2));
+        assert_eq!(eb.ntarget, 3);
+        // txA has id 1 in both samples
+        assert_eq!(eb.get(0).unwrap().labels, &[1, 2]);
+        assert_eq!(dict.ids_of(&["txC", "txB"]).unwrap(), vec![2, 0]);
+        assert!(dict.ids_of(&["txZ"]).is_err());
+    }
+
+    #[test]
+    fn corrupt_binary_cache_is_an_error() {
+        let ecs = collection(3, &[(&[0, 2], &[0.25, 0.75], 6), (&[1], &[1.0], 2)]);
+        let cache = tmp_path("eq_corrupt.cache");
+        ecs.save_binary(&cache).unwrap();
+        let good = read(&cache).unwrap();
+
+        // offsets [0, 2, 3] start after the header, names, ntarget, neq and
+        // the offsets length; make them decrease
+        let at = 8 + 4 + 8 + 3 * (8 + 3) + 8 + 8 + 8;
+        assert_eq!(good[at + 8..at + 16], 2u64.to_le_bytes());
+        let mut bytes = good.clone();
+        bytes[at + 8..at + 16].copy_from_slice(&5u64.to_le_bytes());
+        write(&cache, &bytes).unwrap();
+        let err = EqClassCollection::load_binary(&cache).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // a first name claiming to be enormous
+        let mut bytes = good;
+        bytes[20..28].copy_from_slice(&(u64::MAX / 2).to_le_bytes());
+        write(&cache, &bytes).unwrap();
+        let err = EqClassCollection::load_binary(&cache).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
     }
 }
@@ -0,0 +1,520 @@
+//! Numeric helpers and metrics for comparing quantifications.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::salmon::{QuantEntry, QuantRecord, QuantTable, QuantValue};
+
+/// Arithmetic mean, or NaN for an empty slice.
+pub fn mean(x: &[f64]) -> f64 {
+    if x.is_empty() {
+        return f64::NAN;
+    }
+    x.iter().sum::<f64>() / x.len() as f64
+}
+
+/// Pearson correlation of two equal-length slices. NaN if there are fewer
+/// than two points or either side has zero variance.
+pub fn pearson(x: &[f64], y: &[f64]) -> f64 {
+    assert_eq!(x.len(), y.len(), "pearson requires equal-length inputs");
+    if x.len() < 2 {
+        return f64::NAN;
+    }
+    let (mx, my) = (mean(x), mean(y));
+    let mut sxy = 0.0;
+    let mut sxx = 0.0;
+    let mut syy = 0.0;
+    for (a, b) in x.iter().zip(y) {
+        sxy += (a - mx) * (b - my);
+        sxx += (a - mx) * (a - mx);
+        syy += (b - my) * (b - my);
+    }
+    if sxx == 0.0 || syy == 0.0 {
+        return f64::NAN;
+    }
+    sxy / (sxx * syy).sqrt()
+}
+
+/// SplitMix64, a tiny seeded generator for reproducible resampling. Not
+/// suitable for anything cryptographic.
+pub(crate) struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    pub(crate) fn new(seed: u64) -> SplitMix64 {
+        SplitMix64 { state: seed }
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `[0, 1)`.
+    pub(crate) fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// A Binomial(`n`, `p`) draw, by summing `n` Bernoulli trials.
+    pub(crate) fn binomial(&mut self, n: u32, p: f64) -> u32 {
+        (0..n).filter(|_| self.next_f64() < p).count() as u32
+    }
+}
+
+/// The chosen values of the transcripts present in both maps, paired up in
+/// transcript-name order.
+fn shared_values(
+    a: &HashMap<String, QuantEntry>,
+    b: &HashMap<String, QuantEntry>,
+    value: QuantValue,
+) -> (Vec<f64>, Vec<f64>) {
+    let mut names: Vec<&String> = a.keys().filter(|k| b.contains_key(*k)).collect();
+    names.sort();
+    names
+        .into_iter()
+        .map(|n| (value.of(&a[n]), value.of(&b[n])))
+        .unzip()
+}
+
+/// Lin's concordance correlation coefficient between two runs over their
+/// shared transcripts. Unlike Pearson it also penalizes shifts in location
+/// and scale, so it measures agreement rather than just association. Use a
+/// `Log` [`QuantValue`] to compare on the `log2(x + 1)` scale. NaN if fewer
+/// than two transcripts are shared or both sides are constant and equal.
+pub fn ccc(
+    a: &HashMap<String, QuantEntry>,
+    b: &HashMap<String, QuantEntry>,
+    value: QuantValue,
+) -> f64 {
+    let (x, y) = shared_values(a, b, value);
+    if x.len() < 2 {
+        return f64::NAN;
+    }
+    let n = x.len() as f64;
+    let (mx, my) = (mean(&x), mean(&y));
+    let mut sxy = 0.0;
+    let mut sxx = 0.0;
+    let mut syy = 0.0;
+    for (a, b) in x.iter().zip(&y) {
+        sxy += (a - mx) * (b - my);
+        sxx += (a - mx) * (a - mx);
+        syy += (b - my) * (b - my);
+    }
+    let denom = sxx / n + syy / n + (mx - my) * (mx - my);
+    if denom == 0.0 {
+        return f64::NAN;
+    }
+    2.0 * (sxy / n) / denom
+}
+
+/// 1-based ranks of `x` in ascending order, with tied values sharing the
+/// average of the ranks they span.
+fn average_ranks(x: &[f64]) -> Vec<f64> {
+    let mut order: Vec<usize> = (0..x.len()).collect();
+    order.sort_by(|&i, &j| x[i].partial_cmp(&x[j]).unwrap_or(std::cmp::Ordering::Equal));
+    let mut ranks = vec![0.0; x.len()];
+    let mut start = 0;
+    while start < order.len() {
+        let mut end = start + 1;
+        while end < order.len() && x[order[end]] == x[order[start]] {
+            end += 1;
+        }
+        let rank = (start + end + 1) as f64 / 2.0;
+        for &i in &order[start..end] {
+            ranks[i] = rank;
+        }
+        start = end;
+    }
+    ranks
+}
+
+/// Spearman's footrule between two runs: the sum of absolute rank
+/// differences over the transcripts both runs report, divided by its
+/// maximum `floor(n^2 / 2)` so that 0 means identical rankings and 1 a full
+/// reversal. Tied values get the average of their ranks, so ties never
+/// count as disagreement among themselves. NaN with fewer than two shared
+/// transcripts.
+pub fn spearman_footrule(a: &[QuantRecord], b: &[QuantRecord], by: QuantValue) -> f64 {
+    let b_values: HashMap<&str, f64> = b
+        .iter()
+        .map(|r| (r.name.as_str(), by.of_record(r)))
+        .collect();
+    let (x, y): (Vec<f64>, Vec<f64>) = a
+        .iter()
+        .filter_map(|r| b_values.get(r.name.as_str()).map(|&v| (by.of_record(r), v)))
+        .unzip();
+    let n = x.len();
+    if n < 2 {
+        return f64::NAN;
+    }
+    let (rx, ry) = (average_ranks(&x), average_ranks(&y));
+    let total: f64 = rx.iter().zip(&ry).map(|(a, b)| (a - b).abs()).sum();
+    total / (n * n / 2) as f64
+}
+
+/// Bhattacharyya coefficient `sum_i sqrt(p_i q_i)` between the TPM
+/// proportions of two runs, each renormalized to sum to 1 over the
+/// transcripts both report. 1 means identical distributions, 0 disjoint
+/// support. NaN if either side has no TPM on the shared transcripts.
+pub fn bhattacharyya(a: &[QuantRecord], b: &[QuantRecord]) -> f64 {
+    match shared_proportions(a, b) {
+        Some((p, q)) => p.iter().zip(&q).map(|(x, y)| (x * y).sqrt()).sum(),
+        None => f64::NAN,
+    }
+}
+
+/// Hellinger distance `sqrt(0.5 * sum_i (sqrt(p_i) - sqrt(q_i))^2)` between
+/// the TPM proportions of two runs, normalized over the shared transcripts
+/// as in [`bhattacharyya`]. 0 for identical distributions, 1 for disjoint
+/// support; NaN if either side has no TPM on the shared transcripts.
+pub fn hellinger(a: &[QuantRecord], b: &[QuantRecord]) -> f64 {
+    match shared_proportions(a, b) {
+        Some((p, q)) => {
+            let sum: f64 = p
+                .iter()
+                .zip(&q)
+                .map(|(x, y)| (x.sqrt() - y.sqrt()).powi(2))
+                .sum();
+            (0.5 * sum).sqrt()
+        }
+        None => f64::NAN,
+    }
+}
+
+/// Paired TPM proportions of the transcripts `a` and `b` share, each side
+/// renormalized to sum to 1, or `None` if either side sums to 0.
+fn shared_proportions(a: &[QuantRecord], b: &[QuantRecord]) -> Option<(Vec<f64>, Vec<f64>)> {
+    let b_tpm: HashMap<&str, f64> = b.iter().map(|r| (r.name.as_str(), r.tpm)).collect();
+    let (mut p, mut q): (Vec<f64>, Vec<f64>) = a
+        .iter()
+        .filter_map(|r| b_tpm.get(r.name.as_str()).map(|&t| (r.tpm, t)))
+        .unzip();
+    let (sp, sq): (f64, f64) = (p.iter().sum(), q.iter().sum());
+    if sp <= 0.0 || sq <= 0.0 {
+        return None;
+    }
+    p.iter_mut().for_each(|x| *x /= sp);
+    q.iter_mut().for_each(|x| *x /= sq);
+    Some((p, q))
+}
+
+/// `by` for the transcripts both tables report, paired in `a`'s row order.
+fn paired_rows(a: &QuantTable, b: &QuantTable, by: QuantValue) -> (Vec<f64>, Vec<f64>) {
+    (0..a.len())
+        .filter_map(|i| {
+            b.index_of(&a.names[i])
+                .map(|j| (by.of_row(a, i), by.of_row(b, j)))
+        })
+        .unzip()
+}
+
+/// Pearson correlation of `by` between two tables over the transcripts both
+/// report; those in only one table are ignored. NaN if fewer than two
+/// transcripts are shared or either side is constant.
+pub fn table_pearson(a: &QuantTable, b: &QuantTable, by: QuantValue) -> f64 {
+    let (x, y) = paired_rows(a, b, by);
+    pearson(&x, &y)
+}
+
+/// Spearman rank correlation of `by` between two tables over the shared
+/// transcripts, with tied values given their average rank. NaN under the
+/// same conditions as [`table_pearson`].
+pub fn table_spearman(a: &QuantTable, b: &QuantTable, by: QuantValue) -> f64 {
+    let (x, y) = paired_rows(a, b, by);
+    pearson(&average_ranks(&x), &average_ranks(&y))
+}
+
+/// TPM proportions of every transcript in either table, a transcript missing
+/// from one side counting as 0 there, or `None` if either side sums to 0.
+fn union_proportions(a: &QuantTable, b: &QuantTable) -> Option<(Vec<f64>, Vec<f64>)> {
+    let mut p = a.tpm.clone();
+    let mut q: Vec<f64> = a
+        .names
+        .iter()
+        .map(|n| b.index_of(n).map_or(0.0, |j| b.tpm[j]))
+        .collect();
+    for (j, name) in b.names.iter().enumerate() {
+        if a.index_of(name).is_none() {
+            p.push(0.0);
+            q.push(b.tpm[j]);
+        }
+    }
+    let (sp, sq): (f64, f64) = (p.iter().sum(), q.iter().sum());
+    if sp <= 0.0 || sq <= 0.0 {
+        return None;
+    }
+    p.iter_mut().for_each(|x| *x /= sp);
+    q.iter_mut().for_each(|x| *x /= sq);
+    Some((p, q))
+}
+
+/// Jensen-Shannon distance (the square root of the base-2 JS divergence)
+/// between the TPM profiles of `a` and `b`, each normalized to sum to 1
+/// over the union of their transcripts, with a transcript one table lacks
+/// at 0 there. Ranges from 0 (identical) to 1 (disjoint). NaN if either
+/// table has no TPM mass.
+pub fn js_distance(a: &QuantTable, b: &QuantTable) -> f64 {
+    let (p, q) = match union_proportions(a, b) {
+        Some(pq) => pq,
+        None => return f64::NAN,
+    };
+    let kl_to_mid = |x: f64, m: f64| if x > 0.0 { x * (x / m).log2() } else { 0.0 };
+    let js: f64 = p
+        .iter()
+        .zip(&q)
+        .map(|(&x, &y)| {
+            let m = 0.5 * (x + y);
+            0.5 * (kl_to_mid(x, m) + kl_to_mid(y, m))
+        })
+        .sum();
+    // rounding can leave a tiny negative divergence for identical profiles
+    js.max(0.0).sqrt()
+}
+
+/// L1 distance between the TPM profiles of `a` and `b`, normalized as in
+/// [`js_distance`]. Ranges from 0 to 2; NaN if either table has no TPM mass.
+pub fn l1_distance(a: &QuantTable, b: &QuantTable) -> f64 {
+    match union_proportions(a, b) {
+        Some((p, q)) => p.iter().zip(&q).map(|(x, y)| (x - y).abs()).sum(),
+        None => f64::NAN,
+    }
+}
+
+/// The names of the top `n` records by `by`, ties broken by name.
+fn top_names(recs: &[QuantRecord], n: usize, by: QuantValue) -> HashSet<&str> {
+    let mut ranked: Vec<(&str, f64)> = recs
+        .iter()
+        .map(|r| (r.name.as_str(), by.of_record(r)))
+        .collect();
+    ranked.sort_by(|x, y| {
+        y.1.partial_cmp(&x.1)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| x.0.cmp(y.0))
+    });
+    ranked.into_iter().take(n).map(|(name, _)| name).collect()
+}
+
+/// How many transcripts the two runs' top-`n` sets by `by` have in common.
+/// Ties at the cut-off are broken by transcript name so the result is
+/// deterministic.
+pub fn top_n_overlap(a: &[QuantRecord], b: &[QuantRecord], n: usize, by: QuantValue) -> usize {
+    top_names(a, n, by)
+        .intersection(&top_names(b, n, by))
+        .count()
+}
+
+/// Pearson correlation between effective length and `ln(1 + TPM)` over
+/// transcripts with a positive effective length. A strongly negative value
+/// suggests short transcripts are getting inflated abundances.
+pub fn efflen_tpm_correlation(records: &[QuantRecord]) -> f64 {
+    let (len, tpm): (Vec<f64>, Vec<f64>) = records
+        .iter()
+        .filter(|r| r.efflen > 0.0)
+        .map(|r| (r.efflen, r.tpm.ln_1p()))
+        .unzip();
+    pearson(&len, &tpm)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pearson_known_values() {
+        assert!((pearson(&[1.0, 2.0, 3.0], &[2.0, 4.0, 6.0]) - 1.0).abs() < 1e-12);
+        assert!((pearson(&[1.0, 2.0, 3.0], &[3.0, 2.0, 1.0]) + 1.0).abs() < 1e-12);
+        assert!(pearson(&[1.0], &[1.0]).is_nan());
+        assert!(pearson(&[1.0, 1.0], &[1.0, 2.0]).is_nan());
+        assert_eq!(mean(&[1.0, 2.0, 6.0]), 3.0);
+    }
+
+    fn quant_map(values: &[(&str, f64)]) -> HashMap<String, QuantEntry> {
+        values
+            .iter()
+            .map(|&(name, tpm)| {
+                let e = QuantEntry {
+                    len: 100,
+                    efflen: 100.0,
+                    tpm,
+                    num_reads: tpm / 10.0,
+                };
+                (name.to_string(), e)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn concordance_correlation() {
+        let a = quant_map(&[("t1", 1.0), ("t2", 2.0), ("t3", 3.0), ("only_a", 9.0)]);
+        let b = quant_map(&[("t1", 2.0), ("t2", 3.0), ("t3", 4.0), ("only_b", 0.0)]);
+
+        // var = 2/3 each, cov = 2/3, mean shift 1: 2(2/3) / (4/3 + 1) = 4/7
+        assert!((ccc(&a, &b, QuantValue::Tpm) - 4.0 / 7.0).abs() < 1e-12);
+        assert!((ccc(&a, &a, QuantValue::NumReads) - 1.0).abs() < 1e-12);
+        assert!(ccc(&a, &quant_map(&[("t1", 1.0)]), QuantValue::Tpm).is_nan());
+    }
+
+    fn records(values: &[(&str, f64)]) -> Vec<QuantRecord> {
+        values
+            .iter()
+            .map(|&(name, tpm)| QuantRecord {
+                name: name.to_string(),
+                len: 100,
+                efflen: 100.0,
+                tpm,
+                num_reads: 0.0,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn footrule_distances() {
+        let a = records(&[
+            ("t1", 4.0),
+            ("t2", 3.0),
+            ("t3", 2.0),
+            ("t4", 1.0),
+            ("x", 9.0),
+        ]);
+        let swapped = records(&[("t2", 4.0), ("t1", 3.0), ("t3", 2.0), ("t4", 1.0)]);
+        let reversed = records(&[("t1", 1.0), ("t2", 2.0), ("t3", 3.0), ("t4", 4.0)]);
+
+        assert_eq!(spearman_footrule(&a, &a, QuantValue::Tpm), 0.0);
+        // one adjacent swap moves two ranks by 1, out of a maximum of 8
+        assert!((spearman_footrule(&a, &swapped, QuantValue::Tpm) - 0.25).abs() < 1e-12);
+        assert!((spearman_footrule(&a, &reversed, QuantValue::Tpm) - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn tied_values_share_ranks() {
+        assert_eq!(
+            average_ranks(&[5.0, 1.0, 5.0, 3.0]),
+            vec![3.5, 1.0, 3.5, 2.0]
+        );
+    }
+
+    #[test]
+    fn bhattacharyya_coefficient() {
+        let a = records(&[("t1", 500.0), ("t2", 500.0), ("only_a", 1000.0)]);
+        let b = records(&[("t1", 100.0), ("t2", 900.0)]);
+        // sqrt(0.5 * 0.1) + sqrt(0.5 * 0.9)
+        let expected = 0.05_f64.sqrt() + 0.45_f64.sqrt();
+        assert!((bhattacharyya(&a, &b) - expected).abs() < 1e-12);
+        assert!((bhattacharyya(&a, &a) - 1.0).abs() < 1e-12);
+        assert!(bhattacharyya(&a, &records(&[("t1", 0.0)])).is_nan());
+    }
+
+    #[test]
+    fn top_five_overlap() {
+        let a = records(&[
+            ("t1", 90.0),
+            ("t2", 80.0),
+            ("t3", 70.0),
+            ("t4", 60.0),
+            ("t5", 50.0),
+            ("t6", 40.0),
+            ("t7", 30.0),
+        ]);
+        let b = records(&[
+            ("t1", 10.0),
+            ("t2", 85.0),
+            ("t3", 75.0),
+            ("t4", 15.0),
+            ("t5", 55.0),
+            ("t6", 45.0),
+            ("t7", 35.0),
+        ]);
+        // top 5: {t1..t5} vs {t2, t3, t5, t6, t7}
+        assert_eq!(top_n_overlap(&a, &b, 5, QuantValue::Tpm), 3);
+        assert_eq!(top_n_overlap(&a, &a, 5, QuantValue::Tpm), 5);
+    }
+
+    #[test]
+    fn efflen_tpm_correlation_sign() {
+        let mut recs = records(&[
+            ("a", 3.0_f64.exp() - 1.0),
+            ("b", 2.0_f64.exp() - 1.0),
+            ("c", 1.0_f64.exp() - 1.0),
+            ("d", 1e6),
+        ]);
+        for (r, len) in recs.iter_mut().zip(&[100.0, 200.0, 300.0, 0.0]) {
+            r.efflen = *len;
+        }
+        assert!((efflen_tpm_correlation(&recs) + 1.0).abs() < 1e-12);
+
+        recs.swap(0, 2);
+        recs[0].efflen = 100.0;
+        recs[2].efflen = 300.0;
+        assert!((efflen_tpm_correlation(&recs) - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn hellinger_distance() {
+        let a = records(&[("t1", 1.0), ("t2", 3.0), ("only_a", 10.0)]);
+        let b = records(&[("t1", 3.0), ("t2", 1.0)]);
+        // p = (0.25, 0.75), q = (0.75, 0.25): sqrt(0.5 * 2 * (0.5 - sqrt(0.75))^2)
+        let expected = 0.75_f64.sqrt() - 0.5;
+        assert!((hellinger(&a, &b) - expected).abs() < 1e-12);
+        assert!(hellinger(&a, &a).abs() < 1e-12);
+        assert!(hellinger(&a, &records(&[("t1", 0.0)])).is_nan());
+    }
+
+    fn table(values: &[(&str, f64)]) -> QuantTable {
+        let mut t = QuantTable::default();
+        for r in records(values) {
+            t.push(r);
+        }
+        t
+    }
+
+    #[test]
+    fn table_correlations() {
+        let a = table(&[
+            ("t1", 1.0),
+            ("t2", 2.0),
+            ("t3", 3.0),
+            ("t4", 4.0),
+            ("only_a", 9.0),
+        ]);
+        let b = table(&[
+            ("t4", 16.0),
+            ("t3", 9.0),
+            ("t2", 4.0),
+            ("t1", 1.0),
+            ("only_b", 0.0),
+        ]);
+        // monotone but not linear: ranks agree exactly
+        assert!((table_spearman(&a, &b, QuantValue::Tpm) - 1.0).abs() < 1e-12);
+        let r = table_pearson(&a, &b, QuantValue::Tpm);
+        assert!((r - 25.0 / 645.0_f64.sqrt()).abs() < 1e-12);
+
+        let one = table(&[("t1", 5.0)]);
+        assert!(table_spearman(&a, &one, QuantValue::Tpm).is_nan());
+        assert!(table_pearson(&a, &one, QuantValue::NumReads).is_nan());
+    }
+
+    #[test]
+    fn profile_distances() {
+        let a = table(&[("t1", 3.0), ("t2", 1.0)]);
+        let b = table(&[("t2", 1.0), ("t3", 1.0)]);
+        // p = (3/4, 1/4, 0), q = (0, 1/2, 1/2), m = (3/8, 3/8, 1/4)
+        let kl_p = 0.75 * 2f64.log2() + 0.25 * (2.0f64 / 3.0).log2();
+        let kl_q = 0.5 * (4.0f64 / 3.0).log2() + 0.5 * 2f64.log2();
+        let expected = (0.5 * (kl_p + kl_q)).sqrt();
+        assert!((js_distance(&a, &b) - expected).abs() < 1e-12);
+        assert!((js_distance(&b, &a) - expected).abs() < 1e-12);
+        assert!((l1_distance(&a, &b) - 1.5).abs() < 1e-12);
+
+        assert!(js_distance(&a, &a).abs() < 1e-6);
+        assert_eq!(l1_distance(&a, &a), 0.0);
+        let disjoint = table(&[("t9", 5.0)]);
+        assert!((js_distance(&a, &disjoint) - 1.0).abs() < 1e-12);
+
+        let zero = table(&[("t1", 0.0)]);
+        assert!(js_distance(&a, &zero).is_nan());
+        assert!(l1_distance(&zero, &a).is_nan());
+    }
+}